@@ -6,6 +6,120 @@ pub(crate) enum ProgramError {
     MissingFile(String),
     #[error("Failed to read: {0}")]
     ReadFailed(String),
+    #[error("Failed to write: {0}")]
+    WriteFailed(String),
+    #[error("Failed to parse config: {0}")]
+    ConfigParseFailed(String),
     #[error("Failed to build config")]
     ConfigBuildFailed,
+    #[error("Invalid config: {0}")]
+    InvalidConfig(String),
+    #[error("Profile not found: {0}")]
+    ProfileNotFound(String),
+    #[error("Invalid invite: {0}")]
+    InvalidInvite(String),
+    #[error("QR code error: {0}")]
+    QrFailed(String),
+    #[error("Secret error: {0}")]
+    SecretFailed(String),
+    #[error("Secrets are locked, a passphrase is required")]
+    SecretsLocked,
+    #[error("Config file could not be loaded, fix or reset it first: {0}")]
+    ConfigUnavailable(String),
+    #[error("Child process not found: {0}")]
+    ChildNotFound(String),
+    #[error("Tool is not supervised: {0}")]
+    NotSupervised(String),
+    #[error("Supervised tool is already running: {0}")]
+    SupervisedRunning(String),
+    #[error("Child process is not running in a terminal: {0}")]
+    NotTerminal(String),
+    #[error("Download failed: {0}")]
+    DownloadFailed(String),
+    #[error("Checksum mismatch: {0}")]
+    ChecksumMismatch(String),
+    #[error("Path is outside the tools directory: {0}")]
+    OutsideToolsDir(String),
+    #[error("Invalid tool name or version: {0}")]
+    InvalidToolName(String),
+    #[error("Unsafe archive entry: {0}")]
+    UnsafeArchiveEntry(String),
+    #[error("Failed to extract: {0}")]
+    ExtractFailed(String),
+    #[error("Version is not installed: {0}")]
+    VersionNotInstalled(String),
+    #[error("Tool is in use: {0}")]
+    ToolInUse(String),
+    #[error("Invalid signature: {0}")]
+    SignatureInvalid(String),
+    #[error("Invalid catalog: {0}")]
+    InvalidCatalog(String),
+    #[error("Tool not found in catalog: {0}")]
+    CatalogToolNotFound(String),
+    #[error("Update endpoint is not configured")]
+    UpdateNotConfigured,
+    #[error("Invalid release manifest: {0}")]
+    InvalidReleaseManifest(String),
+    #[error("No update available: {0}")]
+    NoUpdate(String),
+    #[error("Failed to install update: {0}")]
+    UpdateFailed(String),
+}
+
+/// command_spawn拒绝启动的原因，序列化后以kind区分
+#[derive(Error, Debug, serde::Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub(crate) enum SpawnError {
+    #[error("Unknown tool: {tool}")]
+    UnknownTool { tool: String },
+    #[error("Tools directory unavailable: {message}")]
+    ToolsDirUnavailable { message: String },
+    #[error("Executable of {tool} not found: {path}")]
+    ExecutableMissing { tool: String, path: String },
+    #[error("Path is outside the tools directory: {path}")]
+    OutsideToolsDir { path: String },
+    #[error("{tool} expects {min} to {max} arguments, got {count}")]
+    ArgumentCount {
+        tool: String,
+        min: usize,
+        max: usize,
+        count: usize,
+    },
+    #[error("Invalid argument #{index} \"{value}\": {reason}")]
+    InvalidArgument {
+        index: usize,
+        value: String,
+        reason: String,
+    },
+    #[error("Cannot resolve {{{placeholder}}} in argument #{index}: {reason}")]
+    Template {
+        index: usize,
+        placeholder: String,
+        reason: String,
+    },
+    #[error("Environment variable not allowed for {tool}: {name}")]
+    EnvNotAllowed { tool: String, name: String },
+    #[error("Stdin is not allowed for {tool}")]
+    StdinNotAllowed { tool: String },
+    #[error("{tool} is already running")]
+    AlreadyRunning { tool: String },
+    #[error("Failed to spawn: {message}")]
+    SpawnFailed { message: String },
+}
+
+/// 返回给前端的启动错误，附带可读信息
+#[derive(Debug, serde::Serialize)]
+pub(crate) struct SpawnRejection {
+    #[serde(flatten)]
+    error: SpawnError,
+    message: String,
+}
+
+impl From<SpawnError> for SpawnRejection {
+    fn from(error: SpawnError) -> Self {
+        SpawnRejection {
+            message: error.to_string(),
+            error,
+        }
+    }
 }
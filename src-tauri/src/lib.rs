@@ -1,22 +1,58 @@
-use crate::errors::ProgramError;
 use crate::tools::{
-    command::{child_kill, command_spawn, ChildrenManager},
-    config_builder::{get_config, set_config, Config},
+    catalog::{
+        install_tool, list_tools, load_cached_catalog, refresh_catalog, reload_catalog,
+        remove_tool, set_catalog_source, ToolCatalog,
+    },
+    command::{
+        child_attach, child_kill, child_resize, child_status, child_write_stdin, command_spawn,
+        list_children, ChildrenManager,
+    },
+    config_builder::{get_config, save_config, set_config, Config},
+    config_transfer::{export_config, import_config, preview_import_config},
+    downloader::DownloadManager,
+    installer::{list_installed_tools, set_active_version, uninstall_tool},
+    invite::{
+        create_invite, join_invite, open_invite_url, preview_invite, take_pending_invite,
+        PendingInvite,
+    },
+    migration::{
+        config_load_error, load_config, prepare_config_file, reset_broken_config,
+        ConfigLoadError, SUPERVISED_KEY,
+    },
+    nat_diagnostics::{export_diagnostics, get_nat_report, NatReportCache},
+    port_mapping::{stop_port_mapping, PortMapper},
+    profile::{delete_profile, list_profiles, save_profiles, use_profile},
+    qr::{generate_invite_qr, import_invite_qr},
+    resource_monitor::start_resource_monitor,
+    secret::{decrypt_config, reset_secrets, secret_status, unlock_secrets},
+    stun::{get_stun_probes, StunCache},
+    supervisor::{
+        list_supervised, start_supervised, stop_all, stop_supervised, supervise_tool,
+        unsupervise_tool, SupervisedTool, Supervisor,
+    },
+    tool_registry::ToolRegistry,
+    updater::{
+        check_update, confirm_startup, download_update, install_update, run_watchdog,
+        set_update_settings, start_update_check, update_status, Updater,
+    },
     users::get_user_list,
-    vnt_handler::{get_running_status, get_virtual_ip, start_vnt, stop_vnt},
+    vnt_handler::{
+        get_active_server, get_running_status, get_virtual_ip, probe_server_list, start_vnt,
+        stop_vnt,
+    },
     ExternalFilePosition, Status,
 };
 use log::error;
-use std::collections::HashMap;
-use std::fs::File;
 use std::path::Path;
 use std::sync::atomic::AtomicBool;
-use std::sync::{Arc, Mutex};
+use std::sync::Mutex;
+use std::time::Duration;
 use tauri::{
     menu::{Menu, MenuItem},
     tray::{MouseButton, MouseButtonState, TrayIconBuilder, TrayIconEvent},
     Manager,
 };
+use tauri_plugin_deep_link::DeepLinkExt;
 use tauri_plugin_store::StoreExt;
 use crate::tools::users::fresh_user_list;
 
@@ -24,9 +60,19 @@ mod errors;
 mod tools;
 
 pub fn run() {
+    // 更新后的看护进程不启动界面
+    if run_watchdog() {
+        return;
+    }
     tauri::Builder::default()
-        .plugin(tauri_plugin_fs::init())
-        .plugin(tauri_plugin_upload::init())
+        // 单例运行，重复启动时由deep-link插件转发邀请链接
+        .plugin(tauri_plugin_single_instance::init(|app, _argv, _cwd| {
+            if let Some(window) = app.get_webview_window("main") {
+                let _ = window.show();
+                let _ = window.set_focus();
+            }
+        }))
+        .plugin(tauri_plugin_deep_link::init())
         .plugin(tauri_plugin_store::Builder::new().build())
         .plugin(tauri_plugin_opener::init())
         .setup(|app| {
@@ -67,49 +113,116 @@ pub fn run() {
                 })
                 .show_menu_on_left_click(false)
                 .build(app)?;
-            // 读取本地配置文件
-            if !Path::new(&ExternalFilePosition::Config.to_string()).exists() {
-                // 新建配置文件
-                if let Err(e) = File::create(ExternalFilePosition::Config.to_string()) {
-                    error!("Failed to create config file: {}", e);
-                    return Err(ProgramError::MissingFile(
-                        ExternalFilePosition::Config.to_string(),
-                    )
-                    .into());
+            // 读取本地配置文件，必要时迁移到当前版本
+            // 无法读取时不改动原文件，使用内存中的默认配置并禁止保存和连接，由前端提示用户修复或重置
+            let config_path = ExternalFilePosition::Config.to_string();
+            let loaded = prepare_config_file(Path::new(&config_path))
+                .and_then(|_| load_config(app.handle()));
+            let (config, profiles, load_error) = match loaded {
+                Ok((config, profiles)) => (config, profiles, None),
+                Err(e) => {
+                    error!("Failed to load config file: {}", e);
+                    (Config::default(), vec![], Some(e.to_string()))
                 }
-            }
-            let Ok(store) = app.store(ExternalFilePosition::Config.to_string()) else {
-                return Err(
-                    ProgramError::MissingFile(ExternalFilePosition::Config.to_string()).into(),
-                );
             };
-            let config = match store.get("vnt_config") {
-                Some(config) => {
-                    let config =
-                        serde_json::from_value::<Config>(config).unwrap_or(Config::default());
-                    config
+            let config_readable = load_error.is_none();
+            app.manage(ConfigLoadError(Mutex::new(load_error)));
+            // 受监管工具在vnt启动或前端请求时才会运行
+            if config_readable {
+                if let Some(supervised) = app
+                    .store(&config_path)
+                    .ok()
+                    .and_then(|store| store.get(SUPERVISED_KEY))
+                {
+                    match serde_json::from_value::<Vec<SupervisedTool>>(supervised) {
+                        Ok(supervised) => app.state::<Supervisor>().load(supervised),
+                        Err(e) => error!("Failed to parse supervised tools: {}", e),
+                    }
                 }
-                None => {
-                    store.set(
-                        "vnt_config",
-                        serde_json::to_value(Config::default()).unwrap(),
-                    );
-                    Config::default()
+            }
+            // 解密密钥，旧版本的明文密钥加密后重新保存
+            // 无法解密时保留密文并进入锁定状态，由前端提示输入口令或重置
+            let mut config = config;
+            let mut profiles = profiles;
+            let mut secrets_locked = false;
+            let mut plain = match decrypt_config(&mut config) {
+                Ok(plain) => plain,
+                Err(e) => {
+                    error!("Failed to decrypt config: {}", e);
+                    secrets_locked = true;
+                    false
                 }
             };
+            for profile in profiles.iter_mut() {
+                match decrypt_config(&mut profile.config) {
+                    Ok(x) => plain |= x,
+                    Err(e) => {
+                        error!("Failed to decrypt profile {}: {}", profile.name, e);
+                        secrets_locked = true;
+                    }
+                }
+            }
+            if plain && !secrets_locked && config_readable {
+                if let Err(e) = save_config(app.handle(), &config)
+                    .and_then(|_| save_profiles(app.handle(), &profiles))
+                {
+                    error!("Failed to encrypt plain text secrets: {}", e);
+                }
+            }
             app.manage(Mutex::new(Status {
                 running: AtomicBool::new(false),
                 config,
+                profiles,
                 vnt: None,
+                generation: 0,
+                failover: None,
                 users: vec![],
                 virtual_ip: String::from("0.0.0.0"),
+                secrets_locked,
             }));
+            // 子进程资源采样
+            start_resource_monitor(app.handle().clone());
+            // 先使用缓存的工具目录，再在后台从配置的来源更新
+            load_cached_catalog(app.handle());
+            let handle = app.handle().clone();
+            std::thread::spawn(move || {
+                if let Err(e) = refresh_catalog(&handle) {
+                    error!("Failed to refresh tool catalog: {}", e);
+                }
+            });
+            // 注册lightp2p://协议，处理邀请链接
+            app.manage(PendingInvite::default());
+            if let Err(e) = app.deep_link().register_all() {
+                error!("Failed to register deep link: {}", e);
+            }
+            let handle = app.handle().clone();
+            app.deep_link().on_open_url(move |event| {
+                for url in event.urls() {
+                    open_invite_url(&handle, url.as_str());
+                }
+            });
+            if let Ok(Some(urls)) = app.deep_link().get_current() {
+                for url in urls {
+                    open_invite_url(app.handle(), url.as_str());
+                }
+            }
+            // 启动完成，确认更新成功并在后台检查新版本
+            confirm_startup(app.handle());
+            start_update_check(app.handle().clone());
             Ok(())
         })
-        .manage(ChildrenManager {
-            children: Arc::new(Mutex::new(HashMap::new())),
-        })
+        .manage(StunCache::default())
+        .manage(NatReportCache::default())
+        .manage(PortMapper::default())
+        .manage(ToolRegistry::default())
+        .manage(Supervisor::default())
+        .manage(DownloadManager::default())
+        .manage(ToolCatalog::default())
+        .manage(Updater::default())
+        .manage(ChildrenManager::default())
         .invoke_handler(tauri::generate_handler![
+            config_load_error,
+            reset_broken_config,
             start_vnt,
             stop_vnt,
             get_user_list,
@@ -118,8 +231,51 @@ pub fn run() {
             get_running_status,
             get_virtual_ip,
             child_kill,
+            child_attach,
+            child_status,
+            child_write_stdin,
+            child_resize,
+            list_children,
+            supervise_tool,
+            unsupervise_tool,
+            start_supervised,
+            stop_supervised,
+            list_supervised,
             command_spawn,
-            fresh_user_list
+            set_active_version,
+            uninstall_tool,
+            list_installed_tools,
+            list_tools,
+            install_tool,
+            remove_tool,
+            set_catalog_source,
+            reload_catalog,
+            set_update_settings,
+            update_status,
+            check_update,
+            download_update,
+            install_update,
+            fresh_user_list,
+            list_profiles,
+            use_profile,
+            delete_profile,
+            create_invite,
+            preview_invite,
+            take_pending_invite,
+            join_invite,
+            generate_invite_qr,
+            import_invite_qr,
+            secret_status,
+            unlock_secrets,
+            reset_secrets,
+            export_config,
+            preview_import_config,
+            import_config,
+            probe_server_list,
+            get_active_server,
+            get_stun_probes,
+            get_nat_report,
+            export_diagnostics
         ])
         .build(tauri::generate_context!())
         .expect("error while running tauri application")
@@ -130,38 +286,20 @@ pub fn run() {
                     match x.state::<Mutex<Status>>().lock() {
                         Ok(status) => {
                             // 存储配置文件
-                            if let Ok(store) = x.store(ExternalFilePosition::Config.to_string()) {
-                                if let Ok(config) = serde_json::to_value(status.config.clone()) {
-                                    store.set("vnt_config", config);
-                                    if let Err(e) = store.save() {
-                                        error!("Failed to save config: {}", e);
-                                    }
-                                } else {
-                                    error!("Failed to serialize config");
-                                }
-                            } else {
-                                error!(
-                                    "{}",
-                                    ProgramError::MissingFile(
-                                        ExternalFilePosition::Config.to_string()
-                                    )
-                                );
-                            };
+                            if let Err(e) = save_config(x, &status.config) {
+                                error!("{}", e);
+                            }
                         }
                         Err(e) => {
                             error!("Failed to read status: {}", e);
                         }
                     }
-                    // 停止所有子进程
-                    for (_, child) in x
-                        .state::<ChildrenManager>()
-                        .children
-                        .lock()
-                        .unwrap()
-                        .iter_mut()
-                    {
-                        let _ = child.kill();
-                    }
+                    // 删除路由器端口映射
+                    stop_port_mapping(x);
+                    // 停止所有子进程，先停止监管以免被重启
+                    stop_all(x);
+                    x.state::<ChildrenManager>()
+                        .shutdown_all(Duration::from_secs(2));
                 }
                 _ => {}
             }
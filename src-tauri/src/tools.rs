@@ -1,16 +1,59 @@
+pub(crate) mod arg_template;
+pub(crate) mod catalog;
 pub(crate) mod command;
 pub(crate) mod config_builder;
+pub(crate) mod config_transfer;
+pub(crate) mod downloader;
+pub(crate) mod failover;
+pub(crate) mod installer;
+pub(crate) mod invite;
+pub(crate) mod migration;
+pub(crate) mod nat_diagnostics;
+pub(crate) mod output_log;
+pub(crate) mod port_mapping;
+pub(crate) mod process;
+pub(crate) mod profile;
+pub(crate) mod pty;
+pub(crate) mod qr;
+pub(crate) mod resource_monitor;
+pub(crate) mod secret;
+pub(crate) mod signing;
+pub(crate) mod stun;
+pub(crate) mod supervisor;
+#[cfg(test)]
+pub(crate) mod test_util;
+pub(crate) mod tool_registry;
+pub(crate) mod updater;
 pub(crate) mod users;
 pub(crate) mod vnt_handler;
 
 use crate::tools::config_builder::Config;
 use std::fmt::{Display, Formatter};
-use std::path::PathBuf;
+use std::io::Write;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::AtomicBool;
 
 /// 外部文件位置
 pub(crate) enum ExternalFilePosition {
     Config,
+    /// 迁移前的配置备份，参数为原版本号
+    ConfigBackup(u64),
+    /// 无法解析的配置备份，参数为备份时间
+    ConfigBroken(u64),
+    /// 无系统凭据管理器时使用的密钥文件
+    SecretKey,
+    /// 邀请签名密钥，使用配置密钥加密
+    InviteKey,
+    /// 子进程启动审计日志
+    SpawnAudit,
+    /// 工具输出日志，参数为工具名
+    ToolLog(String),
+    /// 校验通过的工具目录清单缓存
+    Catalog,
+    /// 更新标记，记录替换程序后的状态
+    UpdateMarker,
+    /// 下载的新版本程序，参数为版本号
+    Update(String),
 }
 
 impl Display for ExternalFilePosition {
@@ -22,6 +65,44 @@ impl Display for ExternalFilePosition {
             ExternalFilePosition::Config => {
                 write!(f, "{}\\config.json", prefix)
             }
+            ExternalFilePosition::ConfigBackup(version) => {
+                write!(f, "{}\\config.v{}.bak.json", prefix, version)
+            }
+            ExternalFilePosition::ConfigBroken(time) => {
+                write!(f, "{}\\config.broken.{}.json", prefix, time)
+            }
+            ExternalFilePosition::SecretKey => {
+                write!(f, "{}\\secret.key", prefix)
+            }
+            ExternalFilePosition::InviteKey => {
+                write!(f, "{}\\invite.key", prefix)
+            }
+            ExternalFilePosition::SpawnAudit => {
+                write!(f, "{}\\spawn_audit.log", prefix)
+            }
+            ExternalFilePosition::ToolLog(tool) => {
+                // 工具名可能来自外部目录，只保留安全字符
+                let name: String = tool
+                    .chars()
+                    .map(|c| {
+                        if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                            c
+                        } else {
+                            '_'
+                        }
+                    })
+                    .collect();
+                write!(f, "{}\\logs\\{}.log", prefix, name)
+            }
+            ExternalFilePosition::Catalog => {
+                write!(f, "{}\\catalog.json", prefix)
+            }
+            ExternalFilePosition::UpdateMarker => {
+                write!(f, "{}\\update.json", prefix)
+            }
+            ExternalFilePosition::Update(version) => {
+                write!(f, "{}\\updates\\{}", prefix, version)
+            }
         }
     }
 }
@@ -30,9 +111,15 @@ impl Display for ExternalFilePosition {
 pub(crate) struct Status {
     pub(crate) running: AtomicBool,
     pub(crate) config: Config,
+    pub(crate) profiles: Vec<profile::Profile>,
     pub(crate) vnt: Option<vnt::core::Vnt>,
+    /// 每次创建vnt时递增
+    pub(crate) generation: u64,
+    pub(crate) failover: Option<failover::Failover>,
     pub(crate) users: Vec<users::User>,
     pub(crate) virtual_ip: String,
+    /// 配置中的密钥无法解密，解锁或重置前不能连接
+    pub(crate) secrets_locked: bool,
 }
 
 /// 判断两个Vec是否相等
@@ -40,3 +127,16 @@ pub(crate) fn do_vecs_match<T: PartialEq>(a: &Vec<T>, b: &Vec<T>) -> bool {
     let matching = a.iter().zip(b.iter()).filter(|&(a, b)| a == b).count();
     matching == a.len() && matching == b.len()
 }
+
+/// 先写入临时文件再重命名，避免写入中途崩溃导致文件损坏
+pub(crate) fn write_file_atomic(path: &Path, content: &[u8]) -> std::io::Result<()> {
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    let temp = PathBuf::from(temp);
+    {
+        let mut file = std::fs::File::create(&temp)?;
+        file.write_all(content)?;
+        file.sync_all()?;
+    }
+    std::fs::rename(&temp, path)
+}
@@ -0,0 +1,294 @@
+//! 工具参数模板
+//!
+//! 参数中的`{变量}`在启动时按当前网络状态替换，`{{`和`}}`表示字面量花括号。
+//! 支持的变量：
+//! - `{virtual_ip}` 本机虚拟IP
+//! - `{server}` 当前使用的服务器地址
+//! - `{online_peer_ips}` 全部在线成员的虚拟IP，单独作为一个参数时展开为多个参数，否则以逗号连接
+//! - `{peer:<name>.ip}` 指定名称成员的虚拟IP
+
+use crate::errors::SpawnError;
+use crate::tools::Status;
+
+/// 启动时的网络状态快照
+pub(crate) struct TemplateContext {
+    virtual_ip: Option<String>,
+    server: Option<String>,
+    /// (名称, 虚拟IP, 是否在线)
+    peers: Vec<(String, String, bool)>,
+}
+
+impl TemplateContext {
+    pub(crate) fn from_status(status: &Status) -> Self {
+        let running = status.vnt.is_some();
+        let virtual_ip = Some(status.virtual_ip.clone())
+            .filter(|ip| running && ip != "0.0.0.0" && !ip.is_empty());
+        let server = match &status.failover {
+            Some(failover) => failover.active().cloned(),
+            None => Some(status.config.server_address_str.clone()),
+        }
+        .filter(|x| !x.is_empty());
+        let peers = status
+            .users
+            .iter()
+            .map(|x| (x.name.clone(), x.ip.clone(), x.status))
+            .collect();
+        TemplateContext {
+            virtual_ip,
+            server,
+            peers,
+        }
+    }
+
+    fn resolve(&self, placeholder: &str) -> Result<Vec<String>, String> {
+        match placeholder {
+            "virtual_ip" => self
+                .virtual_ip
+                .clone()
+                .map(|x| vec![x])
+                .ok_or_else(|| "vnt is not connected".to_string()),
+            "server" => self
+                .server
+                .clone()
+                .map(|x| vec![x])
+                .ok_or_else(|| "no server is configured".to_string()),
+            "online_peer_ips" => {
+                let ips: Vec<_> = self
+                    .peers
+                    .iter()
+                    .filter(|(_, _, online)| *online)
+                    .map(|(_, ip, _)| ip.clone())
+                    .collect();
+                if ips.is_empty() {
+                    return Err("no peer is online".to_string());
+                }
+                Ok(ips)
+            }
+            _ => match placeholder.strip_prefix("peer:") {
+                Some(peer) => self.resolve_peer(peer).map(|x| vec![x]),
+                None => Err("unknown placeholder".to_string()),
+            },
+        }
+    }
+
+    /// 解析`<name>.ip`，名称中可以包含`.`，以最后一个`.`分隔属性
+    fn resolve_peer(&self, peer: &str) -> Result<String, String> {
+        let Some((name, attribute)) = peer.rsplit_once('.') else {
+            return Err("expected peer:<name>.ip".to_string());
+        };
+        if attribute != "ip" {
+            return Err(format!("unknown peer attribute \"{}\"", attribute));
+        }
+        let mut matches = self.peers.iter().filter(|(x, _, _)| x == name);
+        match (matches.next(), matches.next()) {
+            (Some((_, ip, _)), None) => Ok(ip.clone()),
+            (None, _) => Err(format!("peer \"{}\" not found", name)),
+            (Some(_), Some(_)) => Err(format!("more than one peer is named \"{}\"", name)),
+        }
+    }
+}
+
+enum Segment {
+    Text(String),
+    Placeholder(String),
+}
+
+/// 拆分参数中的文本和占位符
+fn parse(arg: &str) -> Result<Vec<Segment>, String> {
+    let mut segments = vec![];
+    let mut text = String::new();
+    let mut chars = arg.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                text.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                text.push('}');
+            }
+            '{' => {
+                let mut name = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some('{') | None => {
+                            return Err("unclosed \"{\", use \"{{\" for a literal brace".to_string())
+                        }
+                        Some(c) => name.push(c),
+                    }
+                }
+                if !text.is_empty() {
+                    segments.push(Segment::Text(std::mem::take(&mut text)));
+                }
+                segments.push(Segment::Placeholder(name.trim().to_string()));
+            }
+            '}' => return Err("unmatched \"}\", use \"}}\" for a literal brace".to_string()),
+            c => text.push(c),
+        }
+    }
+    if !text.is_empty() || segments.is_empty() {
+        segments.push(Segment::Text(text));
+    }
+    Ok(segments)
+}
+
+/// 替换全部参数中的占位符
+pub(crate) fn expand_args(
+    args: &[String],
+    context: &TemplateContext,
+) -> Result<Vec<String>, SpawnError> {
+    let mut expanded = Vec::with_capacity(args.len());
+    for (index, arg) in args.iter().enumerate() {
+        let error = |placeholder: &str, reason: String| SpawnError::Template {
+            index,
+            placeholder: placeholder.to_string(),
+            reason,
+        };
+        let segments = parse(arg).map_err(|reason| error(arg, reason))?;
+        // 单独的占位符可以展开为多个参数
+        if let [Segment::Placeholder(name)] = segments.as_slice() {
+            expanded.extend(
+                context
+                    .resolve(name)
+                    .map_err(|reason| error(name, reason))?,
+            );
+            continue;
+        }
+        let mut value = String::new();
+        for segment in segments {
+            match segment {
+                Segment::Text(text) => value.push_str(&text),
+                Segment::Placeholder(name) => {
+                    let values = context
+                        .resolve(&name)
+                        .map_err(|reason| error(&name, reason))?;
+                    value.push_str(&values.join(","));
+                }
+            }
+        }
+        expanded.push(value);
+    }
+    Ok(expanded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context() -> TemplateContext {
+        TemplateContext {
+            virtual_ip: Some("10.26.0.2".to_string()),
+            server: Some("vnt.example.com:29872".to_string()),
+            peers: vec![
+                ("a".to_string(), "10.26.0.3".to_string(), true),
+                ("b.lan".to_string(), "10.26.0.4".to_string(), true),
+                ("c".to_string(), "10.26.0.5".to_string(), false),
+            ],
+        }
+    }
+
+    fn expand(args: &[&str], context: &TemplateContext) -> Result<Vec<String>, SpawnError> {
+        let args: Vec<_> = args.iter().map(|x| x.to_string()).collect();
+        expand_args(&args, context)
+    }
+
+    #[test]
+    fn expands_placeholders() {
+        assert_eq!(
+            expand(&["-I", "{virtual_ip}", "--server={server}"], &context()).unwrap(),
+            vec!["-I", "10.26.0.2", "--server=vnt.example.com:29872"]
+        );
+        // 名称中的.不影响属性解析
+        assert_eq!(
+            expand(&["{peer:b.lan.ip}", "{ peer:a.ip }"], &context()).unwrap(),
+            vec!["10.26.0.4", "10.26.0.3"]
+        );
+    }
+
+    #[test]
+    fn escapes_braces() {
+        assert_eq!(
+            expand(
+                &["{{virtual_ip}}", "{{{virtual_ip}}}", "a}}b{{"],
+                &context()
+            )
+            .unwrap(),
+            vec!["{virtual_ip}", "{10.26.0.2}", "a}b{"]
+        );
+        assert_eq!(expand(&[""], &context()).unwrap(), vec![""]);
+    }
+
+    #[test]
+    fn rejects_malformed_templates() {
+        for arg in ["{virtual_ip", "{", "a{b{c}", "virtual_ip}", "}"] {
+            assert!(
+                matches!(
+                    expand(&["ok", arg], &context()),
+                    Err(SpawnError::Template { index: 1, .. })
+                ),
+                "{}",
+                arg
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_placeholders() {
+        for (arg, placeholder) in [
+            ("{home}", "home"),
+            ("x={peer:a.mac}", "peer:a.mac"),
+            ("{peer:a}", "peer:a"),
+        ] {
+            assert!(
+                matches!(
+                    expand(&[arg], &context()),
+                    Err(SpawnError::Template { placeholder: x, .. }) if x == placeholder
+                ),
+                "{}",
+                arg
+            );
+        }
+    }
+
+    #[test]
+    fn expands_online_peers() {
+        // 单独作为参数时展开为多个参数，离线成员不包含在内
+        assert_eq!(
+            expand(&["-c", "{online_peer_ips}", "-v"], &context()).unwrap(),
+            vec!["-c", "10.26.0.3", "10.26.0.4", "-v"]
+        );
+        // 与其他文本组合时以逗号连接
+        assert_eq!(
+            expand(&["--hosts={online_peer_ips}"], &context()).unwrap(),
+            vec!["--hosts=10.26.0.3,10.26.0.4"]
+        );
+        let mut offline = context();
+        offline.peers.retain(|(_, _, online)| !online);
+        assert!(matches!(
+            expand(&["{online_peer_ips}"], &offline),
+            Err(SpawnError::Template { .. })
+        ));
+    }
+
+    #[test]
+    fn rejects_missing_peer() {
+        let mut context = context();
+        assert!(matches!(
+            expand(&["{peer:d.ip}"], &context),
+            Err(SpawnError::Template { reason, .. }) if reason.contains("not found")
+        ));
+        // 同名成员无法确定使用哪个
+        context
+            .peers
+            .push(("a".to_string(), "10.26.0.6".to_string(), true));
+        assert!(matches!(
+            expand(&["{peer:a.ip}"], &context),
+            Err(SpawnError::Template { reason, .. }) if reason.contains("more than one")
+        ));
+        // 未连接时没有本机虚拟IP
+        context.virtual_ip = None;
+        assert!(expand(&["{virtual_ip}"], &context).is_err());
+    }
+}
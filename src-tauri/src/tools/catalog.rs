@@ -0,0 +1,382 @@
+//! 工具目录
+//!
+//! 可用工具由签名的JSON清单描述，可从配置的URL获取或从本地文件加载。
+//! 校验通过的清单缓存到本地，离线时使用缓存，清单中的工具注册到启动白名单。
+
+use crate::errors::ProgramError;
+use crate::tools::command::ChildrenManager;
+use crate::tools::config_builder::persist_store_value;
+use crate::tools::downloader::DownloadEvent;
+use crate::tools::installer::{self, check_name, InstallSource, InstalledTool};
+use crate::tools::migration::CATALOG_SOURCE_KEY;
+use crate::tools::signing::open_envelope;
+use crate::tools::tool_registry::{ArgSchema, ToolRegistry, ToolSpec};
+use crate::tools::{write_file_atomic, ExternalFilePosition};
+use log::{error, info};
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+use std::sync::RwLock;
+use std::time::Duration;
+use tauri::ipc::Channel;
+use tauri::{AppHandle, Emitter, Manager, State};
+use tauri_plugin_store::StoreExt;
+
+/// 支持的清单格式版本
+const CATALOG_SCHEMA: u32 = 1;
+/// 清单大小上限
+const MAX_CATALOG_SIZE: u64 = 4 * 1024 * 1024;
+const FETCH_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// 清单来源
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum CatalogSource {
+    Url(String),
+    File(PathBuf),
+}
+
+/// 前端展示和启动方式的标记
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ToolTag {
+    /// 需要下载
+    Download,
+    /// 需要运行命令
+    Command,
+    /// 以开关启动，保持单例
+    Switch,
+    /// 以按钮启动，可以多次启动
+    Button,
+    /// 参数由前端动态获取
+    ArgsGet,
+}
+
+/// 某个平台的安装包
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub(crate) struct PlatformArtifact {
+    /// 与`std::env::consts::OS`一致，如windows、linux、macos
+    pub(crate) os: String,
+    /// 与`std::env::consts::ARCH`一致，如x86_64、aarch64
+    pub(crate) arch: String,
+    #[serde(flatten)]
+    pub(crate) source: InstallSource,
+}
+
+/// 工具运行时需要的权限
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub(crate) struct ToolPermissions {
+    /// 允许设置的环境变量
+    pub(crate) env: Vec<String>,
+    /// 是否允许接管标准输入
+    pub(crate) stdin: bool,
+    /// 同一时间只允许运行一个实例
+    pub(crate) singleton: bool,
+}
+
+/// 清单中的工具
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub(crate) struct CatalogTool {
+    /// 工具名，同时作为启动时的注册名和安装目录名
+    pub(crate) name: String,
+    #[serde(default)]
+    pub(crate) display_name: Option<String>,
+    #[serde(default)]
+    pub(crate) description: String,
+    #[serde(default)]
+    pub(crate) link: Option<String>,
+    /// 图标文字或emoji
+    #[serde(default)]
+    pub(crate) icon: Option<String>,
+    pub(crate) version: String,
+    #[serde(default)]
+    pub(crate) tags: Vec<ToolTag>,
+    #[serde(default)]
+    pub(crate) platforms: Vec<PlatformArtifact>,
+    /// 可执行文件相对于版本目录的路径，不含平台后缀，为空时工具不可启动
+    #[serde(default)]
+    pub(crate) entry: Option<String>,
+    /// 参数约束
+    #[serde(default)]
+    pub(crate) args: ArgSchema,
+    /// 默认参数，支持参数模板中的占位符
+    #[serde(default)]
+    pub(crate) arg_template: Vec<String>,
+    #[serde(default)]
+    pub(crate) permissions: ToolPermissions,
+    /// 在浏览器中打开的地址，用于无需下载的工具
+    #[serde(default)]
+    pub(crate) browser: Option<String>,
+}
+
+impl CatalogTool {
+    /// 当前平台的安装包
+    fn artifact(&self) -> Option<&PlatformArtifact> {
+        self.platforms
+            .iter()
+            .find(|x| x.os == std::env::consts::OS && x.arch == std::env::consts::ARCH)
+    }
+
+    fn spec(&self) -> Option<ToolSpec> {
+        Some(ToolSpec {
+            name: self.name.clone(),
+            executable: self.entry.clone()?,
+            args: self.args.clone(),
+            env: self.permissions.env.clone(),
+            stdin: self.permissions.stdin,
+            singleton: self.permissions.singleton,
+        })
+    }
+
+    fn validate(&self) -> Result<(), ProgramError> {
+        let invalid =
+            |reason: &str| ProgramError::InvalidCatalog(format!("{}: {}", self.name, reason));
+        check_name(&self.name).map_err(|_| invalid("invalid name"))?;
+        check_name(&self.version).map_err(|_| invalid("invalid version"))?;
+        if let Some(entry) = &self.entry {
+            let relative = Path::new(entry)
+                .components()
+                .all(|x| matches!(x, Component::Normal(_)));
+            if entry.is_empty() || !relative {
+                return Err(invalid("entry must be a relative path"));
+            }
+        }
+        for platform in &self.platforms {
+            let sha256 = &platform.source.sha256;
+            let hashed = sha256.len() == 64 && sha256.chars().all(|c| c.is_ascii_hexdigit());
+            if !hashed {
+                return Err(invalid("every artifact needs a sha256"));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 工具清单
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub(crate) struct Catalog {
+    pub(crate) schema: u32,
+    #[serde(default)]
+    pub(crate) published_at: Option<String>,
+    pub(crate) tools: Vec<CatalogTool>,
+}
+
+impl Catalog {
+    /// 校验签名并解析
+    fn parse(content: &[u8]) -> Result<Self, ProgramError> {
+        let payload = open_envelope(content)?;
+        let catalog = serde_json::from_slice::<Catalog>(&payload)
+            .map_err(|e| ProgramError::InvalidCatalog(e.to_string()))?;
+        if catalog.schema != CATALOG_SCHEMA {
+            return Err(ProgramError::InvalidCatalog(format!(
+                "unsupported schema {}",
+                catalog.schema
+            )));
+        }
+        for tool in &catalog.tools {
+            tool.validate()?;
+        }
+        Ok(catalog)
+    }
+
+    fn get(&self, name: &str) -> Option<&CatalogTool> {
+        self.tools.iter().find(|x| x.name == name)
+    }
+}
+
+/// 当前使用的工具清单
+#[derive(Default)]
+pub(crate) struct ToolCatalog(RwLock<Option<Catalog>>);
+
+/// list_tools返回的条目
+#[derive(serde::Serialize, Clone, Debug)]
+pub(crate) struct ToolListing {
+    #[serde(flatten)]
+    tool: CatalogTool,
+    /// 当前平台是否有安装包，无需下载的工具始终可用
+    available: bool,
+    installed: Option<InstalledTool>,
+    /// 已安装但当前版本与清单不同
+    update_available: bool,
+}
+
+fn read_source(source: &CatalogSource) -> Result<Vec<u8>, ProgramError> {
+    let mut content = vec![];
+    match source {
+        CatalogSource::Url(url) => {
+            let response = ureq::AgentBuilder::new()
+                .timeout(FETCH_TIMEOUT)
+                .build()
+                .get(url)
+                .call()
+                .map_err(|e| ProgramError::DownloadFailed(format!("{}: {}", url, e)))?;
+            response
+                .into_reader()
+                .take(MAX_CATALOG_SIZE + 1)
+                .read_to_end(&mut content)
+                .map_err(|e| ProgramError::DownloadFailed(e.to_string()))?;
+        }
+        CatalogSource::File(path) => {
+            std::fs::File::open(path)
+                .and_then(|file| file.take(MAX_CATALOG_SIZE + 1).read_to_end(&mut content))
+                .map_err(|e| ProgramError::ReadFailed(e.to_string()))?;
+        }
+    }
+    if content.len() as u64 > MAX_CATALOG_SIZE {
+        return Err(ProgramError::InvalidCatalog(
+            "catalog is too large".to_string(),
+        ));
+    }
+    Ok(content)
+}
+
+/// 启用清单并注册其中的工具
+fn apply(app: &AppHandle, catalog: Catalog) {
+    let specs = catalog.tools.iter().filter_map(CatalogTool::spec).collect();
+    app.state::<ToolRegistry>().replace_catalog(specs);
+    info!("Tool catalog loaded with {} tools", catalog.tools.len());
+    *app.state::<ToolCatalog>().0.write().unwrap() = Some(catalog);
+    if let Err(e) = app.emit("lers://catalog/updated", ()) {
+        error!("Failed to emit catalog update: {}", e);
+    }
+}
+
+/// 加载上次校验通过的缓存
+pub(crate) fn load_cached_catalog(app: &AppHandle) {
+    let path = ExternalFilePosition::Catalog.to_string();
+    let Ok(content) = std::fs::read(&path) else {
+        return;
+    };
+    match Catalog::parse(&content) {
+        Ok(catalog) => apply(app, catalog),
+        Err(e) => error!("Failed to load cached tool catalog: {}", e),
+    }
+}
+
+fn configured_source(app: &AppHandle) -> Option<CatalogSource> {
+    let store = app.store(ExternalFilePosition::Config.to_string()).ok()?;
+    serde_json::from_value::<Option<CatalogSource>>(store.get(CATALOG_SOURCE_KEY)?)
+        .ok()
+        .flatten()
+}
+
+/// 从配置的来源重新加载，校验通过后替换缓存
+pub(crate) fn refresh_catalog(app: &AppHandle) -> Result<bool, ProgramError> {
+    let Some(source) = configured_source(app) else {
+        return Ok(false);
+    };
+    let content = read_source(&source)?;
+    let catalog = Catalog::parse(&content)?;
+    if let Err(e) = write_file_atomic(
+        Path::new(&ExternalFilePosition::Catalog.to_string()),
+        &content,
+    ) {
+        error!("Failed to cache tool catalog: {}", e);
+    }
+    apply(app, catalog);
+    Ok(true)
+}
+
+fn find_tool(app: &AppHandle, name: &str) -> Result<CatalogTool, ProgramError> {
+    app.state::<ToolCatalog>()
+        .0
+        .read()
+        .unwrap()
+        .as_ref()
+        .and_then(|x| x.get(name).cloned())
+        .ok_or_else(|| ProgramError::CatalogToolNotFound(name.to_string()))
+}
+
+/// 设置清单来源并立即加载，为空时停止从外部更新
+#[tauri::command]
+pub(crate) async fn set_catalog_source(
+    app: AppHandle,
+    source: Option<CatalogSource>,
+) -> Result<bool, String> {
+    let value = serde_json::to_value(&source).map_err(|e| e.to_string())?;
+    persist_store_value(&app, CATALOG_SOURCE_KEY, value).map_err(|e| e.to_string())?;
+    tauri::async_runtime::spawn_blocking(move || refresh_catalog(&app))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())
+}
+
+/// 从配置的来源重新加载清单，未配置来源时返回false
+#[tauri::command]
+pub(crate) async fn reload_catalog(app: AppHandle) -> Result<bool, String> {
+    tauri::async_runtime::spawn_blocking(move || refresh_catalog(&app))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())
+}
+
+/// 列出清单中的工具及安装情况
+#[tauri::command]
+pub(crate) fn list_tools(app: AppHandle, catalog: State<ToolCatalog>) -> Vec<ToolListing> {
+    let tools = match catalog.0.read().unwrap().as_ref() {
+        Some(catalog) => catalog.tools.clone(),
+        None => return vec![],
+    };
+    tools
+        .into_iter()
+        .map(|tool| {
+            let installed = installer::installed(&app, &tool.name);
+            let update_available = installed
+                .as_ref()
+                .is_some_and(|x| x.active.as_deref() != Some(tool.version.as_str()));
+            ToolListing {
+                available: tool.platforms.is_empty() || tool.artifact().is_some(),
+                installed,
+                update_available,
+                tool,
+            }
+        })
+        .collect()
+}
+
+/// 安装清单中工具的当前版本并切换到该版本
+#[tauri::command]
+pub(crate) async fn install_tool(
+    app: AppHandle,
+    name: String,
+    on_event: Channel<DownloadEvent>,
+) -> Result<InstalledTool, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let tool = find_tool(&app, &name)?;
+        let artifact = tool.artifact().ok_or_else(|| {
+            ProgramError::CatalogToolNotFound(format!(
+                "{} for {} {}",
+                name,
+                std::env::consts::OS,
+                std::env::consts::ARCH
+            ))
+        })?;
+        let on_event = |event: DownloadEvent| {
+            let _ = on_event.send(event);
+        };
+        installer::install(
+            &app,
+            &tool.name,
+            &tool.version,
+            &artifact.source,
+            true,
+            &on_event,
+        )
+    })
+    .await
+    .map_err(|e| e.to_string())?
+    .map_err(|e| e.to_string())
+}
+
+/// 卸载清单中工具的全部版本
+#[tauri::command]
+pub(crate) fn remove_tool(
+    app: AppHandle,
+    name: String,
+    manager: State<ChildrenManager>,
+) -> Result<(), String> {
+    let tool = find_tool(&app, &name).map_err(|e| e.to_string())?;
+    installer::uninstall(&app, &manager, &tool.name, None)
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
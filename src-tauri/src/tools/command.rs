@@ -1,10 +1,27 @@
-use std::collections::HashMap;
-use std::io::{BufRead, BufReader};
-use std::os::windows::process::CommandExt;
-use std::process::{Child, Command};
-use std::sync::{Arc, Mutex};
+use crate::errors::{ProgramError, SpawnError, SpawnRejection};
+use crate::tools::arg_template::{expand_args, TemplateContext};
+use crate::tools::output_log::OutputLog;
+use crate::tools::process::{self, ProcessTree};
+use crate::tools::pty::{self, TerminalSize};
+use crate::tools::resource_monitor::{MonitorTarget, ResourceLimits, ResourceSample};
+use crate::tools::tool_registry::{tools_dir, AuditRecord, ResolvedSpawn, ToolRegistry};
+use crate::tools::Status;
+use portable_pty::MasterPty;
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use tauri::ipc::Channel;
-use tauri::State;
+use tauri::{AppHandle, Manager, State};
+
+/// 请求正常退出后等待的时间，超时则强制结束整棵进程树
+const GRACEFUL_TIMEOUT: Duration = Duration::from_secs(5);
+/// 根进程退出后等待输出读取完毕的时间
+const OUTPUT_DRAIN_TIMEOUT: Duration = Duration::from_millis(500);
+/// 保留的已退出子进程记录数
+const MAX_EXITED_RECORDS: usize = 32;
 
 #[derive(Clone, serde::Serialize)]
 #[serde(rename_all = "camelCase", tag = "event", content = "payload")]
@@ -13,6 +30,9 @@ pub(crate) enum CommandEvent {
     Stdout(String),
     #[serde(rename_all = "camelCase")]
     Error(String),
+    /// 伪终端模式下的原始输出，保留ANSI控制序列
+    #[serde(rename_all = "camelCase")]
+    Data(Vec<u8>),
     #[serde(rename_all = "camelCase")]
     Terminated {
         code: Option<i32>,
@@ -20,94 +40,878 @@ pub(crate) enum CommandEvent {
     },
 }
 
-pub(crate) struct ChildrenManager {
-    pub(crate) children: Arc<Mutex<HashMap<u32, Child>>>,
+impl CommandEvent {
+    fn terminated(status: Option<ExitStatus>) -> Self {
+        #[cfg(unix)]
+        let signal = {
+            use std::os::unix::process::ExitStatusExt;
+            status.and_then(|status| status.signal())
+        };
+        #[cfg(not(unix))]
+        let signal = None;
+        CommandEvent::Terminated {
+            code: status.and_then(|status| status.code()),
+            signal,
+        }
+    }
 }
 
-#[tauri::command]
-pub(crate) fn command_spawn(
+/// 根进程是否已退出，由等待线程写入
+#[derive(Default)]
+struct ExitState {
+    exited: Mutex<bool>,
+    condvar: Condvar,
+}
+
+impl ExitState {
+    fn set(&self) {
+        *self.exited.lock().unwrap() = true;
+        self.condvar.notify_all();
+    }
+
+    /// 等待根进程退出，返回是否已退出
+    fn wait_until(&self, deadline: Instant) -> bool {
+        let mut guard = self.exited.lock().unwrap();
+        while !*guard {
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            guard = self.condvar.wait_timeout(guard, deadline - now).unwrap().0;
+        }
+        true
+    }
+}
+
+/// 子进程状态
+#[derive(serde::Serialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case", tag = "state")]
+pub(crate) enum ChildState {
+    Running,
+    /// 已请求停止，等待退出
+    Stopping,
+    Exited {
+        code: Option<i32>,
+        signal: Option<i32>,
+    },
+}
+
+/// 子进程信息
+#[derive(serde::Serialize, Clone, Debug)]
+pub(crate) struct ChildInfo {
+    /// 句柄，由command_spawn返回
+    id: String,
+    pid: u32,
+    tool: String,
+    /// 可执行文件的绝对路径
     command: String,
     args: Vec<String>,
-    hide: bool,
-    on_event: Channel<CommandEvent>,
-    manager: State<ChildrenManager>,
-) -> Result<u32, ()> {
-    let mut binding = Command::new(command);
-    let command = binding.args(args);
-    command.stderr(std::process::Stdio::piped());
-    // 隐藏状态下截取标准输出，否则仅截取错误输出
-    if hide {
-        command.creation_flags(0x08000000);
-        command.stdout(std::process::Stdio::piped());
-    }
-    let mut child = match command.spawn() {
-        Ok(child) => child,
-        Err(e) => {
-            on_event.send(CommandEvent::Error(e.to_string())).unwrap();
-            on_event
-                .send(CommandEvent::Terminated {
-                    code: None,
-                    signal: None,
-                })
-                .unwrap();
-            return Err(());
+    /// 是否运行在伪终端中
+    terminal: bool,
+    /// 启动时间，unix时间戳(毫秒)
+    started_at: u64,
+    #[serde(flatten)]
+    state: ChildState,
+    /// 最近一次资源采样
+    resources: Option<ResourceSample>,
+}
+
+/// 启动子进程的可选参数
+#[derive(serde::Serialize, serde::Deserialize, Clone, Default, Debug)]
+#[serde(default)]
+pub(crate) struct SpawnOptions {
+    /// 工作目录，相对于工具目录，默认为可执行文件所在目录
+    pub(crate) cwd: Option<PathBuf>,
+    /// 追加的环境变量，仅限工具注册时允许的变量
+    pub(crate) env: HashMap<String, String>,
+    /// 是否接管标准输入，开启后可通过child_write_stdin写入
+    pub(crate) stdin: bool,
+    /// 内存中保留的输出行数，伪终端模式下为输出块数，默认1000
+    pub(crate) buffer_lines: Option<usize>,
+    /// 是否同时写入按工具名轮换的日志文件
+    pub(crate) log_file: bool,
+    /// 进程树的资源限制
+    pub(crate) limits: Option<ResourceLimits>,
+    /// 设置后在该尺寸的伪终端中运行，输出以Data事件推送，child_write_stdin写入终端
+    pub(crate) pty: Option<TerminalSize>,
+}
+
+/// 子进程的输入，管道或伪终端
+type Input = Arc<Mutex<Box<dyn Write + Send>>>;
+/// 伪终端的主端，用于调整尺寸
+type Terminal = Arc<Mutex<Box<dyn MasterPty + Send>>>;
+
+pub(crate) struct ManagedChild {
+    info: ChildInfo,
+    stdin: Option<Input>,
+    terminal: Option<Terminal>,
+    output: Arc<OutputLog>,
+    limits: Option<ResourceLimits>,
+    tree: Arc<ProcessTree>,
+    exit: Arc<ExitState>,
+}
+
+impl ManagedChild {
+    fn handles(&self) -> (Arc<ProcessTree>, Arc<ExitState>) {
+        (self.tree.clone(), self.exit.clone())
+    }
+
+    fn is_alive(&self) -> bool {
+        !matches!(self.info.state, ChildState::Exited { .. })
+    }
+}
+
+/// 按句柄管理子进程，句柄不随PID复用而冲突
+#[derive(Default)]
+pub(crate) struct ChildrenManager {
+    pub(crate) children: Arc<Mutex<HashMap<String, ManagedChild>>>,
+    /// 正在启动的单例工具，启动期间不持有进程表锁
+    starting: Mutex<HashSet<String>>,
+}
+
+/// 单例工具的启动占位，释放时移除
+struct Starting<'a> {
+    starting: &'a Mutex<HashSet<String>>,
+    tool: String,
+}
+
+impl Drop for Starting<'_> {
+    fn drop(&mut self) {
+        self.starting.lock().unwrap().remove(&self.tool);
+    }
+}
+
+impl ChildrenManager {
+    /// 单例工具未运行也未在启动时占位，已有实例时返回None
+    fn reserve(&self, tool: &str) -> Option<Starting<'_>> {
+        let children = self.children.lock().unwrap();
+        let mut starting = self.starting.lock().unwrap();
+        if starting.contains(tool)
+            || children
+                .values()
+                .any(|x| x.is_alive() && x.info.tool == tool)
+        {
+            return None;
         }
-    };
-    let pid = child.id();
-    let stderr = child.stderr.take().unwrap();
-    // 创建一个线程，用于读取子进程的标准输出
-    if hide {
-        let stdout = child.stdout.take().unwrap();
-        let on_event = on_event.clone();
-        std::thread::spawn(move || {
-            let mut reader = BufReader::new(stdout).lines();
-            while let Some(Ok(line)) = reader.next() {
-                // 处理每一行输出
-                on_event.send(CommandEvent::Stdout(line)).unwrap();
+        starting.insert(tool.to_string());
+        Some(Starting {
+            starting: &self.starting,
+            tool: tool.to_string(),
+        })
+    }
+
+    /// 运行中、需要采样的子进程
+    pub(crate) fn monitor_targets(&self) -> Vec<MonitorTarget> {
+        self.children
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|x| x.is_alive())
+            .map(|x| MonitorTarget {
+                id: x.info.id.clone(),
+                tool: x.info.tool.clone(),
+                pid: x.info.pid,
+                started_at: x.info.started_at,
+                limits: x.limits.clone(),
+            })
+            .collect()
+    }
+
+    /// 是否有运行中的子进程的可执行文件位于该目录内
+    pub(crate) fn is_running_from(&self, dir: &Path) -> bool {
+        self.children
+            .lock()
+            .unwrap()
+            .values()
+            .any(|x| x.is_alive() && Path::new(&x.info.command).starts_with(dir))
+    }
+
+    pub(crate) fn info(&self, id: &str) -> Result<ChildInfo, ProgramError> {
+        self.children
+            .lock()
+            .unwrap()
+            .get(id)
+            .map(|x| x.info.clone())
+            .ok_or_else(|| ProgramError::ChildNotFound(id.to_string()))
+    }
+
+    /// 运行中及最近退出的子进程，按启动时间排序
+    pub(crate) fn list(&self) -> Vec<ChildInfo> {
+        let mut children: Vec<_> = self
+            .children
+            .lock()
+            .unwrap()
+            .values()
+            .map(|x| x.info.clone())
+            .collect();
+        children.sort_by_key(|x| x.started_at);
+        children
+    }
+
+    pub(crate) fn set_resources(&self, id: &str, sample: ResourceSample) {
+        if let Some(child) = self.children.lock().unwrap().get_mut(id) {
+            if child.is_alive() {
+                child.info.resources = Some(sample);
             }
-        });
+        }
     }
-    manager.children.lock().unwrap().insert(pid, child);
-    let children = manager.children.clone();
-    // 创建一个线程，用于读取子进程的错误输出
-    std::thread::spawn(move || {
-        let mut reader = BufReader::new(stderr).lines();
 
-        while let Some(Ok(line)) = reader.next() {
-            // 处理每一行输出
-            on_event.send(CommandEvent::Error(line)).unwrap();
+    /// 在后台停止子进程，等待正常退出可能耗时数秒，不阻塞调用方
+    pub(crate) fn stop(&self, id: &str) -> Result<(), ProgramError> {
+        let (tree, exit) = {
+            let mut children = self.children.lock().unwrap();
+            let child = children
+                .get_mut(id)
+                .ok_or_else(|| ProgramError::ChildNotFound(id.to_string()))?;
+            if !child.is_alive() {
+                return Ok(());
+            }
+            child.info.state = ChildState::Stopping;
+            child.handles()
+        };
+        std::thread::spawn(move || stop_child(&tree, &exit));
+        Ok(())
+    }
+
+    /// 停止全部子进程，先请求正常退出，超时后强制结束
+    pub(crate) fn shutdown_all(&self, timeout: Duration) {
+        let children: Vec<_> = self
+            .children
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|x| x.is_alive())
+            .map(ManagedChild::handles)
+            .collect();
+        for (tree, _) in &children {
+            tree.terminate();
+        }
+        let deadline = Instant::now() + timeout;
+        for (tree, exit) in &children {
+            exit.wait_until(deadline);
+            tree.kill();
+        }
+    }
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|x| x.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// 只保留最近退出的若干条记录
+fn prune_exited(children: &mut HashMap<String, ManagedChild>) {
+    let mut exited: Vec<_> = children
+        .values()
+        .filter(|x| !x.is_alive())
+        .map(|x| (x.info.started_at, x.info.id.clone()))
+        .collect();
+    if exited.len() <= MAX_EXITED_RECORDS {
+        return;
+    }
+    exited.sort();
+    for (_, id) in exited.iter().take(exited.len() - MAX_EXITED_RECORDS) {
+        children.remove(id);
+    }
+}
+
+/// 先请求正常退出，超时或根进程退出后清理进程树中的剩余进程
+fn stop_child(tree: &ProcessTree, exit: &ExitState) {
+    tree.terminate();
+    if !exit.wait_until(Instant::now() + GRACEFUL_TIMEOUT) {
+        log::warn!(
+            "Process {} did not exit within {:?}, killing process tree",
+            tree.pid(),
+            GRACEFUL_TIMEOUT
+        );
+    }
+    tree.kill();
+}
+
+/// 子进程事件的接收方
+pub(crate) type EventSink = Arc<dyn Fn(CommandEvent) + Send + Sync>;
+
+/// 启动请求
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub(crate) struct SpawnRequest {
+    /// 注册的工具名
+    pub(crate) tool: String,
+    #[serde(default)]
+    pub(crate) args: Vec<String>,
+    #[serde(default)]
+    pub(crate) hide: bool,
+    #[serde(default)]
+    pub(crate) options: SpawnOptions,
+}
+
+/// 已启动的进程及其输入输出
+struct Spawned {
+    pid: u32,
+    tree: ProcessTree,
+    stdin: Option<Input>,
+    terminal: Option<Terminal>,
+    /// 各输出流的读取过程，在独立线程中运行
+    readers: Vec<Box<dyn FnOnce(EventSink) + Send>>,
+    /// 等待根进程退出
+    wait: Box<dyn FnOnce() -> CommandEvent + Send>,
+}
+
+/// 逐行转发输出流
+fn forward_lines(stream: impl Read, sink: EventSink, event: fn(String) -> CommandEvent) {
+    let mut reader = BufReader::new(stream).lines();
+    while let Some(Ok(line)) = reader.next() {
+        // 处理每一行输出
+        sink(event(line));
+    }
+}
+
+/// 通过管道截取输出
+fn spawn_piped(
+    resolved: &ResolvedSpawn,
+    args: &[String],
+    options: &SpawnOptions,
+    hide: bool,
+) -> std::io::Result<Spawned> {
+    let mut binding = Command::new(&resolved.executable);
+    let process = binding
+        .args(args)
+        .envs(&options.env)
+        .current_dir(&resolved.cwd);
+    process::configure(process, hide);
+    // 始终截取标准输出和错误输出
+    process.stdout(Stdio::piped()).stderr(Stdio::piped());
+    if options.stdin {
+        process.stdin(Stdio::piped());
+    }
+    let mut child = process.spawn()?;
+    let tree = ProcessTree::attach(&child);
+    let stdin = child
+        .stdin
+        .take()
+        .map(|x| Arc::new(Mutex::new(Box::new(x) as Box<dyn Write + Send>)));
+    let mut readers: Vec<Box<dyn FnOnce(EventSink) + Send>> = Vec::new();
+    if let Some(stdout) = child.stdout.take() {
+        readers.push(Box::new(move |sink| {
+            forward_lines(stdout, sink, CommandEvent::Stdout)
+        }));
+    }
+    if let Some(stderr) = child.stderr.take() {
+        readers.push(Box::new(move |sink| {
+            forward_lines(stderr, sink, CommandEvent::Error)
+        }));
+    }
+    Ok(Spawned {
+        pid: child.id(),
+        tree,
+        stdin,
+        terminal: None,
+        readers,
+        wait: Box::new(move || CommandEvent::terminated(child.wait().ok())),
+    })
+}
+
+/// 在伪终端中运行，标准输出和错误输出合并为原始字节流
+fn spawn_terminal(
+    resolved: &ResolvedSpawn,
+    args: &[String],
+    options: &SpawnOptions,
+    size: TerminalSize,
+) -> anyhow::Result<Spawned> {
+    let process = pty::spawn(
+        &resolved.executable,
+        args,
+        &options.env,
+        &resolved.cwd,
+        size,
+    )?;
+    let reader = process.reader;
+    let child = process.child;
+    Ok(Spawned {
+        pid: process.pid,
+        tree: ProcessTree::attach_pid(process.pid),
+        stdin: Some(Arc::new(Mutex::new(process.writer))),
+        terminal: Some(Arc::new(Mutex::new(process.master))),
+        readers: vec![Box::new(move |sink| pty::forward_output(reader, sink))],
+        wait: Box::new(move || pty::wait(child)),
+    })
+}
+
+/// 按白名单校验并启动工具，返回子进程句柄
+pub(crate) fn spawn_tool(
+    app: &AppHandle,
+    mut request: SpawnRequest,
+    sink: EventSink,
+) -> Result<String, SpawnError> {
+    let registry = app.state::<ToolRegistry>();
+    // 按当前网络状态替换参数中的占位符
+    let context = app
+        .state::<Mutex<Status>>()
+        .lock()
+        .map(|status| TemplateContext::from_status(&status))
+        .map_err(|e| SpawnError::SpawnFailed {
+            message: e.to_string(),
+        });
+    let prepared = context
+        .and_then(|context| expand_args(&request.args, &context))
+        .and_then(|args| Ok((args, tools_dir(app)?)));
+    match prepared {
+        Ok((args, dir)) => {
+            request.args = args;
+            let manager = app.state::<ChildrenManager>();
+            spawn_child(&registry, &manager, &dir, request, sink)
+        }
+        Err(e) => {
+            registry.audit(
+                AuditRecord::new(&request.tool, &request.args, &request.options).rejected(&e),
+            );
+            Err(e)
         }
+    }
+}
 
-        match children.lock().unwrap().remove(&pid) {
-            None => {
-                on_event
-                    .send(CommandEvent::Terminated {
-                        code: None,
-                        signal: None,
-                    })
-                    .unwrap();
+/// 启动参数已展开的工具并加入进程表
+fn spawn_child(
+    registry: &ToolRegistry,
+    manager: &ChildrenManager,
+    tools_dir: &Path,
+    request: SpawnRequest,
+    sink: EventSink,
+) -> Result<String, SpawnError> {
+    let SpawnRequest {
+        tool,
+        args,
+        hide,
+        options,
+    } = request;
+    let record = AuditRecord::new(&tool, &args, &options);
+    let resolved = match registry.resolve(tools_dir, &tool, &args, &options) {
+        Ok(resolved) => resolved,
+        Err(e) => {
+            registry.audit(record.rejected(&e));
+            return Err(e);
+        }
+    };
+    let record = record.resolved(&resolved);
+    // 单例工具先占位再启动，占位到插入进程表后才释放
+    let starting = if resolved.singleton {
+        let Some(starting) = manager.reserve(&tool) else {
+            let e = SpawnError::AlreadyRunning { tool };
+            registry.audit(record.rejected(&e));
+            return Err(e);
+        };
+        Some(starting)
+    } else {
+        None
+    };
+    let spawned = match options.pty {
+        Some(size) => spawn_terminal(&resolved, &args, &options, size).map_err(|e| e.to_string()),
+        None => spawn_piped(&resolved, &args, &options, hide).map_err(|e| e.to_string()),
+    };
+    let spawned = match spawned {
+        Ok(spawned) => spawned,
+        Err(message) => {
+            let e = SpawnError::SpawnFailed { message };
+            registry.audit(record.failed(&e));
+            return Err(e);
+        }
+    };
+    let id = uuid::Uuid::new_v4().to_string();
+    registry.audit(record.spawned(&id, spawned.pid));
+    let output = Arc::new(OutputLog::new(
+        &tool,
+        options.buffer_lines,
+        options.log_file,
+    ));
+    let sink = output.wrap(sink);
+    let exit = Arc::new(ExitState::default());
+    // 每个输出流由一个线程读取
+    let readers: Vec<_> = spawned
+        .readers
+        .into_iter()
+        .map(|read| {
+            let sink = sink.clone();
+            std::thread::spawn(move || read(sink))
+        })
+        .collect();
+    let mut children = manager.children.lock().unwrap();
+    prune_exited(&mut children);
+    children.insert(
+        id.clone(),
+        ManagedChild {
+            info: ChildInfo {
+                id: id.clone(),
+                pid: spawned.pid,
+                tool,
+                command: resolved.executable.to_string_lossy().to_string(),
+                args,
+                terminal: spawned.terminal.is_some(),
+                started_at: now_millis(),
+                state: ChildState::Running,
+                resources: None,
+            },
+            stdin: spawned.stdin,
+            terminal: spawned.terminal,
+            output,
+            limits: options.limits.clone(),
+            tree: Arc::new(spawned.tree),
+            exit: exit.clone(),
+        },
+    );
+    drop(children);
+    drop(starting);
+    let children = manager.children.clone();
+    let handle = id.clone();
+    let wait = spawned.wait;
+    // 创建一个线程，等待子进程退出并上报退出码或信号
+    std::thread::spawn(move || {
+        let event = wait();
+        exit.set();
+        // 释放输入和伪终端，Windows下伪终端关闭后才能读到输出结束
+        let released = children
+            .lock()
+            .unwrap()
+            .get_mut(&handle)
+            .map(|child| (child.stdin.take(), child.terminal.take()));
+        drop(released);
+        // 读完剩余输出后再上报退出，后代进程可能仍持有输出管道，因此只等待片刻
+        let deadline = Instant::now() + OUTPUT_DRAIN_TIMEOUT;
+        for reader in readers {
+            while !reader.is_finished() && Instant::now() < deadline {
+                std::thread::sleep(Duration::from_millis(20));
             }
-            Some(mut child) => {
-                let _ = child.kill();
-                let code = child.wait().ok();
-                on_event
-                    .send(CommandEvent::Terminated {
-                        code: code.and_then(|status| status.code()),
-                        signal: None,
-                    })
-                    .unwrap();
+            if reader.is_finished() {
+                let _ = reader.join();
             }
         }
+        if let CommandEvent::Terminated { code, signal } = &event {
+            if let Some(child) = children.lock().unwrap().get_mut(&handle) {
+                child.info.state = ChildState::Exited {
+                    code: *code,
+                    signal: *signal,
+                };
+            }
+        }
+        sink(event);
     });
-    Ok(pid)
+    Ok(id)
+}
+
+/// 启动已注册的工具
+#[tauri::command]
+pub(crate) fn command_spawn(
+    app: AppHandle,
+    tool: String,
+    args: Vec<String>,
+    hide: bool,
+    options: Option<SpawnOptions>,
+    on_event: Channel<CommandEvent>,
+) -> Result<String, SpawnRejection> {
+    let sink: EventSink = Arc::new(move |event| {
+        let _ = on_event.send(event);
+    });
+    let request = SpawnRequest {
+        tool,
+        args,
+        hide,
+        options: options.unwrap_or_default(),
+    };
+    spawn_tool(&app, request, sink.clone()).map_err(|e| {
+        // 拒绝启动时同样通过事件通道通知前端，保持与启动失败时一致
+        sink(CommandEvent::Error(e.to_string()));
+        sink(CommandEvent::terminated(None));
+        e.into()
+    })
+}
+
+/// 停止子进程及其派生的全部进程
+#[tauri::command]
+pub(crate) fn child_kill(id: String, manager: State<ChildrenManager>) -> Result<(), String> {
+    manager.stop(&id).map_err(|e| e.to_string())
+}
+
+/// 向子进程的标准输入写入数据
+#[tauri::command]
+pub(crate) fn child_write_stdin(
+    id: String,
+    data: String,
+    manager: State<ChildrenManager>,
+) -> Result<(), String> {
+    let stdin = manager
+        .children
+        .lock()
+        .unwrap()
+        .get(&id)
+        .ok_or_else(|| ProgramError::ChildNotFound(id.clone()).to_string())?
+        .stdin
+        .clone()
+        .ok_or_else(|| {
+            ProgramError::WriteFailed(format!("stdin of {} is not piped", id)).to_string()
+        })?;
+    // 写入可能阻塞，不持有进程表的锁
+    let mut stdin = stdin.lock().unwrap();
+    stdin
+        .write_all(data.as_bytes())
+        .and_then(|_| stdin.flush())
+        .map_err(|e| ProgramError::WriteFailed(e.to_string()).to_string())
+}
+
+/// 调整伪终端的尺寸
+#[tauri::command]
+pub(crate) fn child_resize(
+    id: String,
+    cols: u16,
+    rows: u16,
+    manager: State<ChildrenManager>,
+) -> Result<(), String> {
+    let terminal = manager
+        .children
+        .lock()
+        .unwrap()
+        .get(&id)
+        .ok_or_else(|| ProgramError::ChildNotFound(id.clone()).to_string())?
+        .terminal
+        .clone()
+        .ok_or_else(|| ProgramError::NotTerminal(id).to_string())?;
+    let size = TerminalSize { cols, rows };
+    let terminal = terminal.lock().unwrap();
+    terminal
+        .resize(size.into())
+        .map_err(|e| ProgramError::WriteFailed(e.to_string()).to_string())
+}
+
+/// 回放子进程缓冲的输出，之后继续推送实时输出，用于页面刷新后重新观察工具
+#[tauri::command]
+pub(crate) fn child_attach(
+    id: String,
+    on_event: Channel<CommandEvent>,
+    manager: State<ChildrenManager>,
+) -> Result<(), String> {
+    let output = manager
+        .children
+        .lock()
+        .unwrap()
+        .get(&id)
+        .map(|x| x.output.clone())
+        .ok_or_else(|| ProgramError::ChildNotFound(id).to_string())?;
+    output.attach(on_event);
+    Ok(())
 }
 
 #[tauri::command]
-pub(crate) fn child_kill(id: u32, manager: State<ChildrenManager>) {
-    match manager.children.lock().unwrap().remove(&id) {
-        None => {}
-        Some(mut child) => {
-            let _ = child.kill();
+pub(crate) fn child_status(
+    id: String,
+    manager: State<ChildrenManager>,
+) -> Result<ChildInfo, String> {
+    manager.info(&id).map_err(|e| e.to_string())
+}
+
+/// 列出运行中及最近退出的子进程，按启动时间排序
+#[tauri::command]
+pub(crate) fn list_children(manager: State<ChildrenManager>) -> Vec<ChildInfo> {
+    manager.list()
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::tools::test_util::{temp_dir, write_script};
+    use crate::tools::tool_registry::ToolSpec;
+    use std::sync::mpsc;
+
+    /// 临时工具目录中的注册表和进程表
+    struct Fixture {
+        dir: PathBuf,
+        registry: ToolRegistry,
+        manager: ChildrenManager,
+    }
+
+    impl Fixture {
+        fn new() -> Self {
+            let dir = temp_dir();
+            let registry = ToolRegistry::default();
+            registry.set_audit_log(dir.join("spawn_audit.log"));
+            Fixture {
+                dir,
+                registry,
+                manager: ChildrenManager::default(),
+            }
+        }
+
+        /// 以脚本注册一个不带参数的工具
+        fn tool(&self, name: &str, script: &str, singleton: bool) {
+            write_script(&self.dir.join(name), script);
+            self.registry.register(ToolSpec {
+                name: name.to_string(),
+                executable: name.to_string(),
+                args: Default::default(),
+                env: vec![],
+                stdin: false,
+                singleton,
+            });
+        }
+
+        fn spawn(&self, tool: &str) -> Result<(String, mpsc::Receiver<CommandEvent>), SpawnError> {
+            let (tx, rx) = mpsc::channel();
+            let sink: EventSink = Arc::new(move |event| {
+                let _ = tx.send(event);
+            });
+            let request = SpawnRequest {
+                tool: tool.to_string(),
+                args: vec![],
+                hide: true,
+                options: SpawnOptions::default(),
+            };
+            let id = spawn_child(&self.registry, &self.manager, &self.dir, request, sink)?;
+            Ok((id, rx))
+        }
+
+        fn state(&self, id: &str) -> ChildState {
+            self.manager.info(id).unwrap().state
+        }
+
+        fn audit_log(&self) -> String {
+            std::fs::read_to_string(self.dir.join("spawn_audit.log")).unwrap_or_default()
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            self.manager.shutdown_all(Duration::from_secs(1));
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    fn next_stdout(rx: &mpsc::Receiver<CommandEvent>) -> String {
+        match rx.recv_timeout(Duration::from_secs(10)).unwrap() {
+            CommandEvent::Stdout(line) => line,
+            _ => panic!("expected stdout"),
+        }
+    }
+
+    fn next_terminated(rx: &mpsc::Receiver<CommandEvent>) -> (Option<i32>, Option<i32>) {
+        loop {
+            match rx.recv_timeout(Duration::from_secs(10)).unwrap() {
+                CommandEvent::Terminated { code, signal } => return (code, signal),
+                _ => continue,
+            }
         }
     }
+
+    #[test]
+    fn reports_exit_code() {
+        let fixture = Fixture::new();
+        fixture.tool("exit", "echo hello; exit 3", false);
+        let (id, rx) = fixture.spawn("exit").unwrap();
+        assert_eq!(next_stdout(&rx), "hello");
+        assert_eq!(next_terminated(&rx), (Some(3), None));
+        // 上报退出前已更新状态
+        assert_eq!(
+            fixture.state(&id),
+            ChildState::Exited {
+                code: Some(3),
+                signal: None
+            }
+        );
+        assert!(fixture.audit_log().contains(&format!("\"id\":\"{}\"", id)));
+    }
+
+    #[test]
+    fn stop_reports_terminated() {
+        let fixture = Fixture::new();
+        fixture.tool("sleep", "echo ready; sleep 30", false);
+        let (id, rx) = fixture.spawn("sleep").unwrap();
+        assert_eq!(next_stdout(&rx), "ready");
+        assert_eq!(fixture.state(&id), ChildState::Running);
+        let start = Instant::now();
+        fixture.manager.stop(&id).unwrap();
+        assert_eq!(fixture.state(&id), ChildState::Stopping);
+        assert_eq!(next_terminated(&rx), (None, Some(libc::SIGTERM)));
+        assert!(start.elapsed() < GRACEFUL_TIMEOUT);
+        assert_eq!(
+            fixture.state(&id),
+            ChildState::Exited {
+                code: None,
+                signal: Some(libc::SIGTERM)
+            }
+        );
+        // 已退出的子进程再次停止无效果
+        fixture.manager.stop(&id).unwrap();
+    }
+
+    #[test]
+    fn lists_children_in_start_order() {
+        let fixture = Fixture::new();
+        fixture.tool("sleep", "echo ready; sleep 30", false);
+        let (first, first_rx) = fixture.spawn("sleep").unwrap();
+        next_stdout(&first_rx);
+        std::thread::sleep(Duration::from_millis(5));
+        let (second, second_rx) = fixture.spawn("sleep").unwrap();
+        next_stdout(&second_rx);
+        let children = fixture.manager.list();
+        assert_eq!(
+            children.iter().map(|x| x.id.as_str()).collect::<Vec<_>>(),
+            vec![first.as_str(), second.as_str()]
+        );
+        assert!(children.iter().all(|x| x.tool == "sleep"));
+        assert_eq!(fixture.manager.monitor_targets().len(), 2);
+        assert!(fixture.manager.is_running_from(&fixture.dir));
+        fixture.manager.stop(&first).unwrap();
+        next_terminated(&first_rx);
+        assert_eq!(fixture.manager.monitor_targets().len(), 1);
+        // 已退出的记录仍可查询
+        assert_eq!(fixture.manager.list().len(), 2);
+    }
+
+    #[test]
+    fn rejects_second_singleton() {
+        let fixture = Fixture::new();
+        fixture.tool("single", "echo ready; sleep 30", true);
+        let (id, rx) = fixture.spawn("single").unwrap();
+        next_stdout(&rx);
+        assert!(matches!(
+            fixture.spawn("single"),
+            Err(SpawnError::AlreadyRunning { .. })
+        ));
+        // 退出后可以再次启动
+        fixture.manager.stop(&id).unwrap();
+        next_terminated(&rx);
+        fixture.spawn("single").unwrap();
+        assert_eq!(fixture.audit_log().matches("\"spawned\"").count(), 2);
+    }
+
+    #[test]
+    fn rejects_unregistered_tool() {
+        let fixture = Fixture::new();
+        assert!(matches!(
+            fixture.spawn("sh"),
+            Err(SpawnError::UnknownTool { .. })
+        ));
+        assert!(fixture.manager.list().is_empty());
+        assert!(fixture.audit_log().contains("\"rejected\""));
+    }
+
+    #[test]
+    fn singleton_reservation() {
+        let manager = ChildrenManager::default();
+        let starting = manager.reserve("tool").unwrap();
+        assert!(manager.reserve("tool").is_none());
+        assert!(manager.reserve("other").is_some());
+        drop(starting);
+        assert!(manager.reserve("tool").is_some());
+    }
+
+    #[test]
+    fn stop_unknown_child() {
+        let manager = ChildrenManager::default();
+        assert!(matches!(
+            manager.stop("missing"),
+            Err(ProgramError::ChildNotFound(_))
+        ));
+        assert!(matches!(
+            manager.info("missing"),
+            Err(ProgramError::ChildNotFound(_))
+        ));
+    }
 }
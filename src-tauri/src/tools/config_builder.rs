@@ -1,17 +1,248 @@
-use crate::tools::Status;
+use crate::errors::ProgramError;
+use crate::tools::migration::{ConfigLoadError, CONFIG_KEY};
+use crate::tools::secret::{encrypt_config, redact, restore_secrets};
+use crate::tools::stun::PUB_STUN;
+use crate::tools::{write_file_atomic, ExternalFilePosition, Status};
 use log::error;
+use std::collections::HashSet;
+use std::path::Path;
 use std::sync::Mutex;
-use tauri::State;
+use tauri::{AppHandle, Emitter, Manager, State};
+use tauri_plugin_store::StoreExt;
 
-const PUB_STUN: [&'static str; 3] = ["stun.miwifi.com", "stun.chat.bilibili.com", "stun.hitv.com"];
-
-#[derive(serde::Serialize, serde::Deserialize, Clone)]
+#[derive(serde::Serialize, serde::Deserialize, Clone, PartialEq)]
 pub(crate) struct Config {
-    token: String,
-    device_id: String,
-    name: String,
-    server_address_str: String,
-    stun_server: Option<Vec<String>>,
+    pub(crate) token: String,
+    pub(crate) device_id: String,
+    pub(crate) name: String,
+    pub(crate) server_address_str: String,
+    pub(crate) stun_server: Option<Vec<String>>,
+    /// 组网密码，为空时不加密
+    #[serde(default)]
+    pub(crate) password: Option<String>,
+    /// 服务器列表，为空时只使用server_address_str
+    #[serde(default)]
+    pub(crate) servers: Vec<ServerEntry>,
+    #[serde(default)]
+    pub(crate) advanced: AdvancedConfig,
+}
+
+/// 服务器列表中的一项
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+pub(crate) struct ServerEntry {
+    pub(crate) address: String,
+    /// 优先级，数值越小越优先
+    #[serde(default)]
+    pub(crate) priority: u32,
+}
+
+/// 打洞模式
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum PunchMode {
+    Ipv4,
+    Ipv6,
+    Ipv4Tcp,
+    Ipv4Udp,
+    Ipv6Tcp,
+    Ipv6Udp,
+    #[default]
+    All,
+}
+
+impl From<PunchMode> for vnt::channel::punch::PunchModel {
+    fn from(value: PunchMode) -> Self {
+        match value {
+            PunchMode::Ipv4 => Self::IPv4,
+            PunchMode::Ipv6 => Self::IPv6,
+            PunchMode::Ipv4Tcp => Self::IPv4Tcp,
+            PunchMode::Ipv4Udp => Self::IPv4Udp,
+            PunchMode::Ipv6Tcp => Self::IPv6Tcp,
+            PunchMode::Ipv6Udp => Self::IPv6Udp,
+            PunchMode::All => Self::All,
+        }
+    }
+}
+
+/// ipv6底层连接策略
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Ipv6Mode {
+    /// 只通过ipv6打洞，只有ipv4的对端走中继
+    Only,
+    /// 同时通过ipv4和ipv6打洞
+    #[default]
+    Allow,
+    /// 只使用ipv4
+    Disable,
+}
+
+/// 通道模式
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ChannelMode {
+    /// 仅使用服务器中继
+    Relay,
+    /// 仅使用p2p
+    P2p,
+    #[default]
+    All,
+}
+
+impl From<ChannelMode> for vnt::channel::UseChannelType {
+    fn from(value: ChannelMode) -> Self {
+        match value {
+            ChannelMode::Relay => Self::Relay,
+            ChannelMode::P2p => Self::P2p,
+            ChannelMode::All => Self::All,
+        }
+    }
+}
+
+/// 加密方式，仅在设置了组网密码时生效
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum CipherMode {
+    None,
+    #[default]
+    Xor,
+    AesGcm,
+    Chacha20Poly1305,
+}
+
+impl From<CipherMode> for vnt::cipher::CipherModel {
+    fn from(value: CipherMode) -> Self {
+        match value {
+            CipherMode::None => Self::None,
+            CipherMode::Xor => Self::Xor,
+            CipherMode::AesGcm => Self::AesGcm,
+            CipherMode::Chacha20Poly1305 => Self::Chacha20Poly1305,
+        }
+    }
+}
+
+/// 压缩方式
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum CompressMode {
+    None,
+    #[default]
+    Lz4,
+}
+
+impl From<CompressMode> for vnt::compression::Compressor {
+    fn from(value: CompressMode) -> Self {
+        match value {
+            CompressMode::None => Self::None,
+            CompressMode::Lz4 => Self::Lz4,
+        }
+    }
+}
+
+/// 高级配置，默认值与原先写死的参数一致
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Default)]
+#[serde(default)]
+pub(crate) struct AdvancedConfig {
+    /// 虚拟网卡mtu，为空时由vnt决定
+    pub(crate) mtu: Option<u32>,
+    /// 虚拟网卡名称
+    pub(crate) device_name: Option<String>,
+    /// 本地监听端口，为空时随机
+    pub(crate) ports: Option<Vec<u16>>,
+    pub(crate) punch_mode: PunchMode,
+    pub(crate) ipv6_mode: Ipv6Mode,
+    /// 按延迟选择通道，对端有公网ipv6时通常会选中ipv6
+    pub(crate) first_latency: bool,
+    pub(crate) channel_mode: ChannelMode,
+    pub(crate) compress_mode: CompressMode,
+    pub(crate) cipher_mode: CipherMode,
+    /// 通过UPnP-IGD/NAT-PMP/PCP在路由器上映射监听端口，需要固定端口
+    pub(crate) port_mapping: bool,
+}
+
+impl AdvancedConfig {
+    /// 结合ipv6策略得到实际的打洞模式
+    pub(crate) fn effective_punch_mode(&self) -> PunchMode {
+        match (self.ipv6_mode, self.punch_mode) {
+            (Ipv6Mode::Disable, PunchMode::All) => PunchMode::Ipv4,
+            (Ipv6Mode::Only, PunchMode::All) => PunchMode::Ipv6,
+            (_, mode) => mode,
+        }
+    }
+
+    const MTU_RANGE: std::ops::RangeInclusive<u32> = 576..=9000;
+    const DEVICE_NAME_MAX_LEN: usize = 32;
+
+    /// 校验高级配置
+    pub(crate) fn validate(&self) -> Result<(), ProgramError> {
+        if let Some(mtu) = self.mtu {
+            if !Self::MTU_RANGE.contains(&mtu) {
+                return Err(ProgramError::InvalidConfig(format!(
+                    "mtu must be between {} and {}",
+                    Self::MTU_RANGE.start(),
+                    Self::MTU_RANGE.end()
+                )));
+            }
+        }
+        if let Some(name) = &self.device_name {
+            if name.is_empty() || name.len() > Self::DEVICE_NAME_MAX_LEN {
+                return Err(ProgramError::InvalidConfig(format!(
+                    "device name must be 1 to {} characters",
+                    Self::DEVICE_NAME_MAX_LEN
+                )));
+            }
+            if !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            {
+                return Err(ProgramError::InvalidConfig(
+                    "device name may only contain letters, digits, '-' and '_'".to_string(),
+                ));
+            }
+        }
+        if let Some(ports) = &self.ports {
+            if ports.is_empty() {
+                return Err(ProgramError::InvalidConfig(
+                    "ports must not be empty".to_string(),
+                ));
+            }
+            // 0表示随机端口，可以重复
+            let mut seen = HashSet::new();
+            if let Some(port) = ports.iter().find(|&&x| x != 0 && !seen.insert(x)) {
+                return Err(ProgramError::InvalidConfig(format!(
+                    "duplicate port: {}",
+                    port
+                )));
+            }
+        }
+        if self.ipv6_mode == Ipv6Mode::Disable
+            && matches!(
+                self.punch_mode,
+                PunchMode::Ipv6 | PunchMode::Ipv6Tcp | PunchMode::Ipv6Udp
+            )
+        {
+            return Err(ProgramError::InvalidConfig(
+                "ipv6 punch mode conflicts with ipv6 disabled".to_string(),
+            ));
+        }
+        if self.ipv6_mode == Ipv6Mode::Only
+            && matches!(
+                self.punch_mode,
+                PunchMode::Ipv4 | PunchMode::Ipv4Tcp | PunchMode::Ipv4Udp
+            )
+        {
+            return Err(ProgramError::InvalidConfig(
+                "ipv4 punch mode conflicts with ipv6 only".to_string(),
+            ));
+        }
+        // 端口映射需要固定端口
+        if self.port_mapping && !self.ports.iter().flatten().any(|&x| x != 0) {
+            return Err(ProgramError::InvalidConfig(
+                "port mapping requires fixed ports".to_string(),
+            ));
+        }
+        Ok(())
+    }
 }
 
 impl Default for Config {
@@ -22,21 +253,144 @@ impl Default for Config {
             name: whoami::devicename(),
             server_address_str: String::new(),
             stun_server: None,
+            password: None,
+            servers: vec![],
+            advanced: AdvancedConfig::default(),
         }
     }
 }
 
+/// 配置变更事件
+#[derive(serde::Serialize, Clone)]
+pub(crate) struct ConfigChanged {
+    config: Config,
+    /// 是否需要重连才能生效
+    reconnect_required: bool,
+}
+
+/// 校验 host 或 host:port 格式
+fn validate_host_port(value: &str, require_port: bool) -> Result<(), String> {
+    let (host, port) = match value.rsplit_once(':') {
+        Some((host, port)) => (host, Some(port)),
+        None => (value, None),
+    };
+    // ipv6地址需要使用[]包裹
+    let host = host
+        .strip_prefix('[')
+        .and_then(|x| x.strip_suffix(']'))
+        .unwrap_or(host);
+    if host.is_empty() || host.chars().any(|c| c.is_whitespace() || c == '/') {
+        return Err(format!("invalid host: {}", value));
+    }
+    match port {
+        Some(port) => match port.parse::<u16>() {
+            Ok(port) if port != 0 => Ok(()),
+            _ => Err(format!("invalid port: {}", value)),
+        },
+        None if require_port => Err(format!("missing port: {}", value)),
+        None => Ok(()),
+    }
+}
+
 impl Config {
+    const NAME_MAX_LEN: usize = 64;
+    const SERVER_SCHEMES: [&'static str; 4] = ["udp://", "tcp://", "ws://", "wss://"];
+
+    /// 校验服务器地址，允许携带协议前缀或使用txt记录
+    fn validate_server(server: &str) -> Result<(), ProgramError> {
+        let server = server.trim();
+        if let Some(domain) = server.strip_prefix("txt:") {
+            return validate_host_port(domain, false).map_err(ProgramError::InvalidConfig);
+        }
+        let server = Self::SERVER_SCHEMES
+            .iter()
+            .find_map(|scheme| server.strip_prefix(scheme))
+            .unwrap_or(server);
+        validate_host_port(server, true).map_err(ProgramError::InvalidConfig)
+    }
+
+    /// 候选stun服务器，未配置时使用公共服务器
+    pub(crate) fn stun_candidates(&self) -> Vec<String> {
+        match &self.stun_server {
+            Some(stun_server) => stun_server.clone(),
+            None => PUB_STUN.iter().map(|x| x.to_string()).collect(),
+        }
+    }
+
+    /// 按优先级排列的候选服务器
+    pub(crate) fn server_candidates(&self) -> Vec<ServerEntry> {
+        if self.servers.is_empty() {
+            return vec![ServerEntry {
+                address: self.server_address_str.clone(),
+                priority: 0,
+            }];
+        }
+        let mut servers = self.servers.clone();
+        // 稳定排序，同优先级保持配置中的顺序
+        servers.sort_by_key(|x| x.priority);
+        servers
+    }
+
+    /// 校验服务器，启动vnt前调用
+    ///
+    /// 保存配置时不校验服务器，前端每次输入都会保存，此时地址可能为空或未输完
+    pub(crate) fn validate_servers(&self) -> Result<(), ProgramError> {
+        if self.servers.is_empty() && self.server_address_str.trim().is_empty() {
+            return Err(ProgramError::InvalidConfig(
+                "server must not be empty".to_string(),
+            ));
+        }
+        // 配置了服务器列表时server_address_str可以为空
+        if self.servers.is_empty() || !self.server_address_str.trim().is_empty() {
+            Self::validate_server(&self.server_address_str)?;
+        }
+        for server in &self.servers {
+            Self::validate_server(&server.address)?;
+        }
+        Ok(())
+    }
+
+    /// 校验配置，不包括服务器
+    pub(crate) fn validate(&self) -> Result<(), ProgramError> {
+        if self.token.trim().is_empty() {
+            return Err(ProgramError::InvalidConfig(
+                "token must not be empty".to_string(),
+            ));
+        }
+        self.validate_without_token()
+    }
+
+    /// 校验除token外的配置，脱敏导入的档案没有token，需要使用前重新填写
+    pub(crate) fn validate_without_token(&self) -> Result<(), ProgramError> {
+        let name_len = self.name.chars().count();
+        if name_len == 0 || name_len > Self::NAME_MAX_LEN {
+            return Err(ProgramError::InvalidConfig(format!(
+                "name must be 1 to {} characters",
+                Self::NAME_MAX_LEN
+            )));
+        }
+        if self.password.as_ref().is_some_and(|x| x.is_empty()) {
+            return Err(ProgramError::InvalidConfig(
+                "password must not be empty".to_string(),
+            ));
+        }
+        if let Some(stun_server) = &self.stun_server {
+            for stun in stun_server {
+                validate_host_port(stun, false).map_err(ProgramError::InvalidConfig)?;
+            }
+        }
+        self.advanced.validate()
+    }
+
     /// 获取vnt配置
     pub(crate) fn get_vnt_config(&self) -> anyhow::Result<vnt::core::Config> {
-        let mut stun_server = vec![];
-        if self.stun_server.is_none() {
-            for x in PUB_STUN {
-                stun_server.push(x.to_string());
-            }
-        } else {
-            stun_server = self.stun_server.clone().unwrap();
+        // 在创建vnt之前校验配置
+        if let Err(e) = self.advanced.validate() {
+            error!("Invalid config: {}", e);
+            return Err(e.into());
         }
+        let advanced = &self.advanced;
+        let stun_server = self.stun_candidates();
         match vnt::core::Config::new(
             false,
             self.token.clone(),
@@ -47,26 +401,22 @@ impl Config {
             stun_server,
             vec![],
             vec![],
-            None,
-            None,
+            self.password.clone(),
+            advanced.mtu,
             None,
             false,
             false,
-            // 指定使用xor加密
-            vnt::cipher::CipherModel::Xor,
+            advanced.cipher_mode.into(),
             false,
-            // 指定使用全部打洞模式
-            vnt::channel::punch::PunchModel::All,
-            None,
-            false,
-            None,
-            // 指定使用p2p和代理
-            vnt::channel::UseChannelType::All,
+            advanced.effective_punch_mode().into(),
+            advanced.ports.clone(),
+            advanced.first_latency,
+            advanced.device_name.clone(),
+            advanced.channel_mode.into(),
             None,
             0,
             vec![],
-            // 指定使用lz4压缩
-            vnt::compression::Compressor::Lz4,
+            advanced.compress_mode.into(),
             false,
             false,
             None,
@@ -80,11 +430,20 @@ impl Config {
     }
 }
 
-/// 获取配置
+/// 获取配置，除非显式要求否则不返回明文密钥
 #[tauri::command]
-pub(crate) fn get_config(status: State<'_, Mutex<Status>>) -> Result<Config, String> {
+pub(crate) fn get_config(
+    reveal: Option<bool>,
+    status: State<'_, Mutex<Status>>,
+) -> Result<Config, String> {
     match status.lock() {
-        Ok(status) => Ok(status.config.clone()),
+        Ok(status) => {
+            let mut config = status.config.clone();
+            if !reveal.unwrap_or(false) {
+                redact(&mut config);
+            }
+            Ok(config)
+        }
         Err(e) => {
             error!("Failed to read status: {}", e);
             Err(e.to_string())
@@ -92,13 +451,101 @@ pub(crate) fn get_config(status: State<'_, Mutex<Status>>) -> Result<Config, Str
     }
 }
 
-/// 设置配置
+/// 立即持久化存储中的某一项，写入临时文件后再替换，避免崩溃导致配置丢失
+pub(crate) fn persist_store_value(
+    app: &AppHandle,
+    key: &str,
+    value: serde_json::Value,
+) -> Result<(), ProgramError> {
+    persist_store_values(app, vec![(key, value)])
+}
+
+/// 一次写入存储中的多项，文件写入成功后才更新内存中的存储，失败时不会只写入一部分
+pub(crate) fn persist_store_values(
+    app: &AppHandle,
+    values: Vec<(&str, serde_json::Value)>,
+) -> Result<(), ProgramError> {
+    app.state::<ConfigLoadError>().check()?;
+    let path = ExternalFilePosition::Config.to_string();
+    let store = app
+        .store(&path)
+        .map_err(|_| ProgramError::MissingFile(path.clone()))?;
+    let mut root = store
+        .entries()
+        .into_iter()
+        .collect::<serde_json::Map<_, _>>();
+    for (key, value) in &values {
+        root.insert(key.to_string(), value.clone());
+    }
+    let content = serde_json::to_vec_pretty(&root)
+        .map_err(|e| ProgramError::ConfigParseFailed(e.to_string()))?;
+    write_file_atomic(Path::new(&path), &content).map_err(|e| {
+        error!("Failed to save config: {}", e);
+        ProgramError::WriteFailed(path)
+    })?;
+    for (key, value) in values {
+        store.set(key, value);
+    }
+    Ok(())
+}
+
+/// 落盘用的配置，密钥已加密
+pub(crate) fn config_value(config: &Config) -> Result<serde_json::Value, ProgramError> {
+    serde_json::to_value(encrypt_config(config)?)
+        .map_err(|e| ProgramError::ConfigParseFailed(e.to_string()))
+}
+
+/// 立即持久化配置，密钥加密后落盘
+pub(crate) fn save_config(app: &AppHandle, config: &Config) -> Result<(), ProgramError> {
+    persist_store_value(app, CONFIG_KEY, config_value(config)?)
+}
+
+/// 校验后立即保存并应用配置，返回是否需要重连才能生效
+pub(crate) fn apply_config(
+    app: &AppHandle,
+    status: &mut Status,
+    config: Config,
+) -> Result<bool, ProgramError> {
+    config.validate()?;
+    if status.config == config {
+        return Ok(false);
+    }
+    save_config(app, &config)?;
+    Ok(replace_config(app, status, config))
+}
+
+/// 替换内存中已保存的配置并通知前端，返回是否需要重连才能生效
+pub(crate) fn replace_config(app: &AppHandle, status: &mut Status, config: Config) -> bool {
+    // 运行中修改配置需要重连
+    let reconnect_required = status.vnt.is_some();
+    status.config = config.clone();
+    let mut config = config;
+    redact(&mut config);
+    if let Err(e) = app.emit(
+        "lers://config/changed",
+        ConfigChanged {
+            config,
+            reconnect_required,
+        },
+    ) {
+        error!("Failed to emit config: {}", e);
+    }
+    reconnect_required
+}
+
+/// 设置配置，返回是否需要重连才能生效
 #[tauri::command]
-pub(crate) fn set_config(config: Config, status: State<'_, Mutex<Status>>) -> Result<(), String> {
+pub(crate) fn set_config(
+    app: AppHandle,
+    config: Config,
+    status: State<'_, Mutex<Status>>,
+) -> Result<bool, String> {
+    let mut config = config;
     match status.lock() {
         Ok(mut status) => {
-            status.config = config;
-            Ok(())
+            // 前端未修改的脱敏字段沿用原值
+            restore_secrets(&mut config, Some(&status.config));
+            apply_config(&app, &mut status, config).map_err(|e| e.to_string())
         }
         Err(e) => {
             error!("Failed to write status: {}", e);
@@ -106,3 +553,48 @@ pub(crate) fn set_config(config: Config, status: State<'_, Mutex<Status>>) -> Re
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn draft_server_is_saved_but_not_started() {
+        let mut config = Config {
+            name: "device".to_string(),
+            ..Default::default()
+        };
+        for server in ["", "vnt.exa"] {
+            config.server_address_str = server.to_string();
+            assert!(config.validate().is_ok(), "{}", server);
+            assert!(matches!(
+                config.validate_servers(),
+                Err(ProgramError::InvalidConfig(_))
+            ));
+        }
+        config.server_address_str = "vnt.example.com:29872".to_string();
+        config.validate_servers().unwrap();
+        // 服务器列表中的地址同样在启动前校验
+        config.servers = vec![ServerEntry {
+            address: "tcp://vnt.example.com".to_string(),
+            priority: 0,
+        }];
+        assert!(config.validate_servers().is_err());
+    }
+
+    #[test]
+    fn ipv6_mode_limits_punch_mode() {
+        let mut advanced = AdvancedConfig::default();
+        assert_eq!(advanced.effective_punch_mode(), PunchMode::All);
+        advanced.ipv6_mode = Ipv6Mode::Only;
+        assert_eq!(advanced.effective_punch_mode(), PunchMode::Ipv6);
+        advanced.punch_mode = PunchMode::Ipv4Udp;
+        assert!(advanced.validate().is_err());
+        advanced.ipv6_mode = Ipv6Mode::Disable;
+        advanced.validate().unwrap();
+        advanced.punch_mode = PunchMode::All;
+        assert_eq!(advanced.effective_punch_mode(), PunchMode::Ipv4);
+        advanced.punch_mode = PunchMode::Ipv6Tcp;
+        assert!(advanced.validate().is_err());
+    }
+}
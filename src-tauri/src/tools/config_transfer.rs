@@ -0,0 +1,588 @@
+use crate::errors::{ProgramError, SpawnError};
+use crate::tools::catalog::{refresh_catalog, CatalogSource};
+use crate::tools::config_builder::{config_value, persist_store_values, replace_config, Config};
+use crate::tools::migration::{
+    migrate, CATALOG_SOURCE_KEY, CONFIG_KEY, CURRENT_VERSION, PROFILES_KEY, SUPERVISED_KEY,
+    UPDATE_KEY, VERSION_KEY,
+};
+use crate::tools::profile::{profiles_value, upsert_profile, Profile};
+use crate::tools::secret::{is_redacted, redact, restore_secrets};
+use crate::tools::supervisor::{SupervisedTool, Supervisor};
+use crate::tools::tool_registry::ToolRegistry;
+use crate::tools::updater::{check_endpoint, UpdateSettings, Updater};
+use crate::tools::{ExternalFilePosition, Status};
+use log::{error, info};
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+use std::path::Path;
+use std::sync::Mutex;
+use tauri::{AppHandle, Manager, State};
+use tauri_plugin_store::StoreExt;
+
+/// 导入导出格式
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ConfigFormat {
+    Json,
+    Toml,
+}
+
+impl ConfigFormat {
+    /// 根据文件扩展名判断格式，默认json
+    fn from_path(path: &str) -> Self {
+        match Path::new(path).extension().and_then(|x| x.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("toml") => Self::Toml,
+            _ => Self::Json,
+        }
+    }
+}
+
+/// 同名档案的处理方式
+#[derive(serde::Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ConflictStrategy {
+    /// 跳过同名档案
+    #[default]
+    Skip,
+    /// 覆盖同名档案
+    Overwrite,
+    /// 重命名后导入
+    Rename,
+}
+
+/// 导出的全部配置
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
+pub(crate) struct ConfigBundle {
+    pub(crate) config_version: u64,
+    pub(crate) config: Config,
+    #[serde(default)]
+    pub(crate) profiles: Vec<Profile>,
+    /// 其他应用偏好设置
+    #[serde(default)]
+    pub(crate) preferences: Map<String, Value>,
+}
+
+/// 随配置导入导出的偏好设置
+const PREFERENCE_KEYS: [&str; 3] = [SUPERVISED_KEY, CATALOG_SOURCE_KEY, UPDATE_KEY];
+
+/// 校验通过的偏好设置，导入文件中没有的项保持不变
+struct Preferences {
+    supervised: Option<Vec<SupervisedTool>>,
+    catalog_source: Option<Option<CatalogSource>>,
+    update: Option<UpdateSettings>,
+}
+
+/// 合并并校验后待写入的导入内容
+struct StagedImport {
+    config: Config,
+    profiles: Vec<Profile>,
+    preferences: Preferences,
+}
+
+/// 导入时单个档案的预览
+#[derive(serde::Serialize, Clone)]
+pub(crate) struct ProfilePreview {
+    name: String,
+    server: String,
+    /// 本地已存在同名档案
+    conflict: bool,
+    /// 密钥已脱敏，导入后需要重新填写
+    redacted: bool,
+}
+
+/// 导入预览
+#[derive(serde::Serialize, Clone)]
+pub(crate) struct ImportPreview {
+    format: ConfigFormat,
+    server: String,
+    config_redacted: bool,
+    profiles: Vec<ProfilePreview>,
+    preferences: Vec<String>,
+}
+
+impl ConfigBundle {
+    fn serialize(&self, format: ConfigFormat) -> Result<String, ProgramError> {
+        match format {
+            ConfigFormat::Json => serde_json::to_string_pretty(self)
+                .map_err(|e| ProgramError::ConfigParseFailed(e.to_string())),
+            ConfigFormat::Toml => toml::to_string_pretty(self)
+                .map_err(|e| ProgramError::ConfigParseFailed(e.to_string())),
+        }
+    }
+
+    fn deserialize(content: &str, format: ConfigFormat) -> Result<Self, ProgramError> {
+        let value = match format {
+            ConfigFormat::Json => serde_json::from_str::<Value>(content)
+                .map_err(|e| ProgramError::ConfigParseFailed(e.to_string()))?,
+            ConfigFormat::Toml => toml::from_str::<Value>(content)
+                .map_err(|e| ProgramError::ConfigParseFailed(e.to_string()))?,
+        };
+        Self::migrate(value)
+    }
+
+    /// 按存储的结构迁移旧版本导出的配置，只保留导出文件中已有的偏好设置
+    fn migrate(value: Value) -> Result<Self, ProgramError> {
+        let Value::Object(mut bundle) = value else {
+            return Err(ProgramError::ConfigParseFailed(
+                "config bundle must be an object".to_string(),
+            ));
+        };
+        let mut root = match bundle.remove("preferences") {
+            None => Map::new(),
+            Some(Value::Object(preferences)) => preferences,
+            Some(_) => {
+                return Err(ProgramError::ConfigParseFailed(
+                    "preferences must be an object".to_string(),
+                ))
+            }
+        };
+        root.retain(|key, _| PREFERENCE_KEYS.contains(&key.as_str()));
+        let provided: Vec<String> = root.keys().cloned().collect();
+        let config = bundle
+            .remove("config")
+            .ok_or_else(|| ProgramError::ConfigParseFailed("missing config".to_string()))?;
+        root.insert(CONFIG_KEY.to_string(), config);
+        if let Some(profiles) = bundle.remove("profiles") {
+            root.insert(PROFILES_KEY.to_string(), profiles);
+        }
+        if let Some(version) = bundle.remove("config_version") {
+            root.insert(VERSION_KEY.to_string(), version);
+        }
+        migrate(&mut root)?;
+        let config = serde_json::from_value::<Config>(root.remove(CONFIG_KEY).unwrap_or_default())
+            .map_err(|e| ProgramError::ConfigParseFailed(e.to_string()))?;
+        let profiles = serde_json::from_value::<Vec<Profile>>(
+            root.remove(PROFILES_KEY).unwrap_or(Value::Array(vec![])),
+        )
+        .map_err(|e| ProgramError::ConfigParseFailed(e.to_string()))?;
+        // 迁移补全的默认值不应覆盖本地设置
+        root.retain(|key, _| provided.contains(key));
+        Ok(Self {
+            config_version: CURRENT_VERSION,
+            config,
+            profiles,
+            preferences: root,
+        })
+    }
+
+    fn read(path: &str) -> Result<(Self, ConfigFormat), ProgramError> {
+        let format = ConfigFormat::from_path(path);
+        let content =
+            std::fs::read_to_string(path).map_err(|e| ProgramError::ReadFailed(e.to_string()))?;
+        Ok((Self::deserialize(&content, format)?, format))
+    }
+
+    /// 与本机配置合并并校验，不做任何修改
+    ///
+    /// 脱敏字段沿用本机同名档案的密钥，新增的档案没有token，校验时跳过，使用前需要重新填写
+    fn stage(
+        self,
+        local: &Config,
+        local_profiles: &[Profile],
+        conflict: ConflictStrategy,
+    ) -> Result<StagedImport, ProgramError> {
+        let mut profiles = local_profiles.to_vec();
+        for mut profile in self.profiles {
+            let exist = profiles.iter().find(|x| x.name == profile.name).cloned();
+            match (&exist, conflict) {
+                (Some(_), ConflictStrategy::Skip) => continue,
+                (Some(_), ConflictStrategy::Rename) => {
+                    let mut index = 2;
+                    while profiles
+                        .iter()
+                        .any(|x| x.name == format!("{} ({})", profile.name, index))
+                    {
+                        index += 1;
+                    }
+                    profile.name = format!("{} ({})", profile.name, index);
+                    restore_secrets(&mut profile.config, None);
+                }
+                _ => restore_secrets(&mut profile.config, exist.as_ref().map(|x| &x.config)),
+            }
+            // 设备id属于本机
+            profile.config.device_id = local.device_id.clone();
+            profile
+                .config
+                .validate_without_token()
+                .map_err(|e| ProgramError::InvalidConfig(format!("{}: {}", profile.name, e)))?;
+            upsert_profile(&mut profiles, profile);
+        }
+        let mut config = self.config;
+        restore_secrets(&mut config, Some(local));
+        config.device_id = local.device_id.clone();
+        config.validate()?;
+        Ok(StagedImport {
+            config,
+            profiles,
+            preferences: Preferences::parse(&self.preferences)?,
+        })
+    }
+}
+
+/// 读取存储中的偏好设置
+fn read_preferences(app: &AppHandle) -> Result<Map<String, Value>, ProgramError> {
+    let path = ExternalFilePosition::Config.to_string();
+    let store = app
+        .store(&path)
+        .map_err(|_| ProgramError::MissingFile(path))?;
+    Ok(collect_preferences(store.entries()))
+}
+
+/// 挑出可导出的偏好设置，toml不支持null因此逐层去掉空值，导入时空值和缺省等价
+fn collect_preferences(entries: Vec<(String, Value)>) -> Map<String, Value> {
+    entries
+        .into_iter()
+        .filter(|(key, value)| PREFERENCE_KEYS.contains(&key.as_str()) && !value.is_null())
+        .map(|(key, mut value)| {
+            strip_nulls(&mut value);
+            (key, value)
+        })
+        .collect()
+}
+
+fn strip_nulls(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            map.retain(|_, x| !x.is_null());
+            map.values_mut().for_each(strip_nulls);
+        }
+        Value::Array(list) => list.iter_mut().for_each(strip_nulls),
+        _ => {}
+    }
+}
+
+fn preference<T: DeserializeOwned>(
+    preferences: &Map<String, Value>,
+    key: &str,
+) -> Result<Option<T>, ProgramError> {
+    preferences
+        .get(key)
+        .map(|x| serde_json::from_value(x.clone()))
+        .transpose()
+        .map_err(|e| ProgramError::ConfigParseFailed(format!("{}: {}", key, e)))
+}
+
+impl Preferences {
+    /// 解析偏好设置，不做任何修改
+    fn parse(preferences: &Map<String, Value>) -> Result<Self, ProgramError> {
+        let update = preference::<UpdateSettings>(preferences, UPDATE_KEY)?;
+        if let Some(endpoint) = update.as_ref().and_then(|x| x.endpoint.as_ref()) {
+            check_endpoint(endpoint)?;
+        }
+        Ok(Self {
+            supervised: preference(preferences, SUPERVISED_KEY)?,
+            catalog_source: preference(preferences, CATALOG_SOURCE_KEY)?,
+            update,
+        })
+    }
+
+    /// 检查监管定义能否替换：工具必须已注册，且当前没有运行中的监管线程
+    fn check(&self, app: &AppHandle) -> Result<(), ProgramError> {
+        let Some(supervised) = &self.supervised else {
+            return Ok(());
+        };
+        let registry = app.state::<ToolRegistry>();
+        if let Some(x) = supervised
+            .iter()
+            .find(|x| registry.get(&x.request.tool).is_none())
+        {
+            return Err(ProgramError::InvalidConfig(
+                SpawnError::UnknownTool {
+                    tool: x.request.tool.clone(),
+                }
+                .to_string(),
+            ));
+        }
+        app.state::<Supervisor>().ensure_stopped()
+    }
+
+    /// 待写入存储的各项
+    fn store_values(&self) -> Result<Vec<(&'static str, Value)>, ProgramError> {
+        let mut values = vec![];
+        if let Some(supervised) = &self.supervised {
+            values.push((SUPERVISED_KEY, to_value(supervised)?));
+        }
+        if let Some(source) = &self.catalog_source {
+            values.push((CATALOG_SOURCE_KEY, to_value(source)?));
+        }
+        if let Some(update) = &self.update {
+            values.push((UPDATE_KEY, to_value(update)?));
+        }
+        Ok(values)
+    }
+
+    /// 写入存储后重新加载到内存
+    fn reload(self, app: &AppHandle) {
+        if let Some(supervised) = self.supervised {
+            if let Err(e) = app.state::<Supervisor>().replace(supervised) {
+                error!("Failed to reload supervised tools: {}", e);
+            }
+        }
+        if self.catalog_source.is_some() {
+            let app = app.clone();
+            tauri::async_runtime::spawn_blocking(move || {
+                if let Err(e) = refresh_catalog(&app) {
+                    error!("Failed to refresh tool catalog: {}", e);
+                }
+            });
+        }
+        if self.update.is_some() {
+            app.state::<Updater>().reset();
+        }
+    }
+}
+
+fn to_value<T: serde::Serialize>(value: &T) -> Result<Value, ProgramError> {
+    serde_json::to_value(value).map_err(|e| ProgramError::ConfigParseFailed(e.to_string()))
+}
+
+/// 导出全部配置，默认脱敏
+#[tauri::command]
+pub(crate) fn export_config(
+    app: AppHandle,
+    path: String,
+    format: Option<ConfigFormat>,
+    include_secrets: bool,
+    status: State<'_, Mutex<Status>>,
+) -> Result<(), String> {
+    let mut bundle = match status
+        .lock()
+        .map_err(|e| ProgramError::ReadFailed(e.to_string()))
+    {
+        Ok(status) => ConfigBundle {
+            config_version: CURRENT_VERSION,
+            config: status.config.clone(),
+            profiles: status.profiles.clone(),
+            preferences: read_preferences(&app).map_err(|e| e.to_string())?,
+        },
+        Err(e) => return Err(e.to_string()),
+    };
+    if !include_secrets {
+        redact(&mut bundle.config);
+        bundle
+            .profiles
+            .iter_mut()
+            .for_each(|x| redact(&mut x.config));
+    }
+    let format = format.unwrap_or_else(|| ConfigFormat::from_path(&path));
+    let content = bundle.serialize(format).map_err(|e| e.to_string())?;
+    std::fs::write(&path, content).map_err(|e| {
+        error!("Failed to export config: {}", e);
+        ProgramError::WriteFailed(path.clone()).to_string()
+    })?;
+    info!("config exported to {}", path);
+    Ok(())
+}
+
+/// 预览导入内容和冲突，不做任何修改
+#[tauri::command]
+pub(crate) fn preview_import_config(
+    path: String,
+    status: State<'_, Mutex<Status>>,
+) -> Result<ImportPreview, String> {
+    let (bundle, format) = ConfigBundle::read(&path).map_err(|e| e.to_string())?;
+    match status
+        .lock()
+        .map_err(|e| ProgramError::ReadFailed(e.to_string()))
+    {
+        Ok(status) => Ok(ImportPreview {
+            format,
+            server: bundle.config.server_address_str.clone(),
+            config_redacted: is_redacted(&bundle.config),
+            profiles: bundle
+                .profiles
+                .iter()
+                .map(|x| ProfilePreview {
+                    name: x.name.clone(),
+                    server: x.config.server_address_str.clone(),
+                    conflict: status.profiles.iter().any(|y| y.name == x.name),
+                    redacted: is_redacted(&x.config),
+                })
+                .collect(),
+            preferences: bundle.preferences.keys().cloned().collect(),
+        }),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// 导入配置，脱敏字段沿用本地已有的密钥，返回是否需要重连才能生效
+#[tauri::command]
+pub(crate) fn import_config(
+    app: AppHandle,
+    path: String,
+    conflict: Option<ConflictStrategy>,
+    status: State<'_, Mutex<Status>>,
+) -> Result<bool, String> {
+    let (bundle, _) = ConfigBundle::read(&path).map_err(|e| e.to_string())?;
+    match status.lock() {
+        Ok(mut status) => {
+            let staged = bundle
+                .stage(
+                    &status.config,
+                    &status.profiles,
+                    conflict.unwrap_or_default(),
+                )
+                .map_err(|e| e.to_string())?;
+            staged.preferences.check(&app).map_err(|e| e.to_string())?;
+            // 全部校验通过后一次写入
+            let mut values = staged
+                .preferences
+                .store_values()
+                .map_err(|e| e.to_string())?;
+            values.push((
+                PROFILES_KEY,
+                profiles_value(&staged.profiles).map_err(|e| e.to_string())?,
+            ));
+            values.push((
+                CONFIG_KEY,
+                config_value(&staged.config).map_err(|e| e.to_string())?,
+            ));
+            persist_store_values(&app, values).map_err(|e| e.to_string())?;
+            staged.preferences.reload(&app);
+            status.profiles = staged.profiles;
+            let reconnect_required =
+                status.config != staged.config && replace_config(&app, &mut status, staged.config);
+            info!("config imported from {}", path);
+            Ok(reconnect_required)
+        }
+        Err(e) => {
+            error!("Failed to write status: {}", e);
+            Err(e.to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v0_config(config: &Config) -> Value {
+        let mut config = serde_json::to_value(config).unwrap();
+        config.as_object_mut().unwrap().remove("advanced");
+        config
+    }
+
+    #[test]
+    fn migrates_old_bundle() {
+        let config = Config::default();
+        let content = serde_json::json!({ "config": v0_config(&config) }).to_string();
+        let bundle = ConfigBundle::deserialize(&content, ConfigFormat::Json).unwrap();
+        assert_eq!(bundle.config_version, CURRENT_VERSION);
+        assert!(bundle.config == config);
+        assert!(bundle.profiles.is_empty());
+        // 迁移补全的默认值不会作为偏好设置导入
+        assert!(bundle.preferences.is_empty());
+    }
+
+    #[test]
+    fn keeps_only_known_preferences() {
+        let content = serde_json::json!({
+            "config_version": CURRENT_VERSION,
+            "config": Config::default(),
+            "preferences": {
+                UPDATE_KEY: UpdateSettings::default(),
+                VERSION_KEY: 0,
+                CONFIG_KEY: v0_config(&Config::default()),
+                "unknown": true,
+            },
+        })
+        .to_string();
+        let bundle = ConfigBundle::deserialize(&content, ConfigFormat::Json).unwrap();
+        let keys: Vec<_> = bundle.preferences.keys().cloned().collect();
+        assert_eq!(keys, vec![UPDATE_KEY.to_string()]);
+    }
+
+    #[test]
+    fn rejects_newer_bundle() {
+        let content = serde_json::json!({
+            "config_version": CURRENT_VERSION + 1,
+            "config": Config::default(),
+        })
+        .to_string();
+        assert!(ConfigBundle::deserialize(&content, ConfigFormat::Json).is_err());
+    }
+
+    #[test]
+    fn round_trips_toml() {
+        let bundle = ConfigBundle {
+            config_version: CURRENT_VERSION,
+            config: Config::default(),
+            profiles: vec![],
+            preferences: Map::new(),
+        };
+        let content = bundle.serialize(ConfigFormat::Toml).unwrap();
+        let parsed = ConfigBundle::deserialize(&content, ConfigFormat::Toml).unwrap();
+        assert!(parsed.config == bundle.config);
+    }
+
+    #[test]
+    fn exports_nested_none_as_toml() {
+        let settings = UpdateSettings {
+            endpoint: None,
+            ..Default::default()
+        };
+        let entries = vec![
+            (
+                UPDATE_KEY.to_string(),
+                serde_json::to_value(settings).unwrap(),
+            ),
+            (CATALOG_SOURCE_KEY.to_string(), Value::Null),
+            (
+                CONFIG_KEY.to_string(),
+                serde_json::to_value(Config::default()).unwrap(),
+            ),
+        ];
+        let bundle = ConfigBundle {
+            config_version: CURRENT_VERSION,
+            config: Config::default(),
+            profiles: vec![],
+            preferences: collect_preferences(entries),
+        };
+        let content = bundle.serialize(ConfigFormat::Toml).unwrap();
+        let parsed = ConfigBundle::deserialize(&content, ConfigFormat::Toml).unwrap();
+        let keys: Vec<_> = parsed.preferences.keys().cloned().collect();
+        assert_eq!(keys, vec![UPDATE_KEY.to_string()]);
+        let preferences = Preferences::parse(&parsed.preferences).unwrap();
+        assert!(preferences.update.unwrap().endpoint.is_none());
+    }
+
+    #[test]
+    fn imports_redacted_bundle_into_empty_store() {
+        let mut config = Config {
+            server_address_str: "vnt.example.com:29872".to_string(),
+            token: "token".to_string(),
+            password: Some("password".to_string()),
+            ..Default::default()
+        };
+        let mut profiles = vec![Profile {
+            name: "home".to_string(),
+            config: config.clone(),
+        }];
+        // 与默认的导出一样脱敏
+        redact(&mut config);
+        profiles.iter_mut().for_each(|x| redact(&mut x.config));
+        let bundle = ConfigBundle {
+            config_version: CURRENT_VERSION,
+            config,
+            profiles,
+            preferences: Map::new(),
+        };
+        let content = bundle.serialize(ConfigFormat::Json).unwrap();
+        let bundle = ConfigBundle::deserialize(&content, ConfigFormat::Json).unwrap();
+        let local = Config::default();
+        let staged = bundle
+            .stage(&local, &[], ConflictStrategy::default())
+            .unwrap();
+        assert_eq!(staged.profiles.len(), 1);
+        let profile = &staged.profiles[0].config;
+        assert_eq!(profile.server_address_str, "vnt.example.com:29872");
+        assert_eq!(profile.device_id, local.device_id);
+        // 密钥需要重新填写，填写前不能使用
+        assert!(profile.token.is_empty());
+        assert!(profile.password.is_none());
+        assert!(profile.validate().is_err());
+        // 主配置沿用本机的密钥
+        assert_eq!(staged.config.token, local.token);
+        assert_eq!(staged.config.device_id, local.device_id);
+    }
+}
@@ -0,0 +1,358 @@
+//! 工具文件下载
+//!
+//! 先写入同目录下的`.part`临时文件，校验SHA-256后原子重命名为目标文件，
+//! 因此目标文件存在即表示下载完整。中断后再次下载时通过HTTP Range续传。
+//! 只供签名的工具目录和更新使用，前端不能指定下载地址。
+
+use crate::errors::ProgramError;
+use log::warn;
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// 临时文件后缀
+const PART_SUFFIX: &str = ".part";
+/// 进度上报间隔
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(200);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// 读取超时，超时后保留临时文件以便续传
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// 清单中的单个文件
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub(crate) struct DownloadArtifact {
+    pub(crate) url: String,
+    /// 相对于工具目录的保存位置
+    pub(crate) path: String,
+    /// 文件内容的SHA-256，十六进制
+    pub(crate) sha256: String,
+    /// 文件大小，服务器未返回长度时用于显示进度
+    #[serde(default)]
+    pub(crate) size: Option<u64>,
+}
+
+#[derive(Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase", tag = "event", content = "payload")]
+pub(crate) enum DownloadEvent {
+    /// 开始传输，续传时downloaded为已有的字节数
+    #[serde(rename_all = "camelCase")]
+    Started {
+        path: String,
+        downloaded: u64,
+        total: Option<u64>,
+    },
+    #[serde(rename_all = "camelCase")]
+    Progress {
+        path: String,
+        downloaded: u64,
+        total: Option<u64>,
+        bytes_per_second: u64,
+    },
+    #[serde(rename_all = "camelCase")]
+    Verifying { path: String },
+    /// 文件已就绪，包括目标文件已存在且校验通过的情况
+    #[serde(rename_all = "camelCase")]
+    Finished { path: String },
+}
+
+/// 正在下载的目标文件，同一文件不允许并发下载
+#[derive(Default)]
+pub(crate) struct DownloadManager {
+    active: Mutex<HashSet<PathBuf>>,
+}
+
+/// 下载结束后释放目标文件
+pub(crate) struct ActiveGuard<'a> {
+    manager: &'a DownloadManager,
+    targets: Vec<PathBuf>,
+}
+
+impl Drop for ActiveGuard<'_> {
+    fn drop(&mut self) {
+        let mut active = self.manager.active.lock().unwrap();
+        for target in &self.targets {
+            active.remove(target);
+        }
+    }
+}
+
+impl DownloadManager {
+    pub(crate) fn acquire(&self, targets: Vec<PathBuf>) -> Result<ActiveGuard<'_>, ProgramError> {
+        let mut active = self.active.lock().unwrap();
+        if let Some(target) = targets.iter().find(|x| active.contains(*x)) {
+            return Err(ProgramError::DownloadFailed(format!(
+                "{} is already being downloaded",
+                target.display()
+            )));
+        }
+        active.extend(targets.iter().cloned());
+        Ok(ActiveGuard {
+            manager: self,
+            targets,
+        })
+    }
+}
+
+fn part_path(target: &Path) -> PathBuf {
+    let mut path = target.as_os_str().to_owned();
+    path.push(PART_SUFFIX);
+    PathBuf::from(path)
+}
+
+pub(crate) fn sha256_file(path: &Path) -> std::io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 64 * 1024];
+    loop {
+        let n = file.read(&mut buffer)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buffer[..n]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+fn io_error(path: &Path, e: std::io::Error) -> ProgramError {
+    ProgramError::DownloadFailed(format!("{}: {}", path.display(), e))
+}
+
+/// 下载到临时文件，已有临时文件时尝试续传，返回是否为续传
+fn fetch(
+    artifact: &DownloadArtifact,
+    part: &Path,
+    on_event: &dyn Fn(DownloadEvent),
+) -> Result<bool, ProgramError> {
+    let offset = fs::metadata(part).map(|x| x.len()).unwrap_or(0);
+    let agent = ureq::AgentBuilder::new()
+        .timeout_connect(CONNECT_TIMEOUT)
+        .timeout_read(READ_TIMEOUT)
+        .build();
+    let mut request = agent.get(&artifact.url);
+    if offset > 0 {
+        request = request.set("Range", &format!("bytes={}-", offset));
+    }
+    let response = match request.call() {
+        Ok(response) => response,
+        // 临时文件已包含全部内容
+        Err(ureq::Error::Status(416, _)) if offset > 0 => return Ok(true),
+        Err(e) => {
+            return Err(ProgramError::DownloadFailed(format!(
+                "{}: {}",
+                artifact.url, e
+            )))
+        }
+    };
+    // 服务器不支持Range时返回200和完整内容，从头写入
+    let resumed = offset > 0 && response.status() == 206;
+    let start = if resumed { offset } else { 0 };
+    let mut file = if resumed {
+        OpenOptions::new().append(true).open(part)
+    } else {
+        File::create(part)
+    }
+    .map_err(|e| io_error(part, e))?;
+    let length = response
+        .header("Content-Length")
+        .and_then(|x| x.parse::<u64>().ok());
+    let total = length.map(|x| x + start).or(artifact.size);
+    on_event(DownloadEvent::Started {
+        path: artifact.path.clone(),
+        downloaded: start,
+        total,
+    });
+    let mut reader = response.into_reader();
+    let mut buffer = vec![0u8; 64 * 1024];
+    let mut downloaded = start;
+    let begin = Instant::now();
+    let mut reported = begin;
+    let progress = |downloaded: u64| {
+        let elapsed = begin.elapsed().as_secs_f64().max(0.001);
+        DownloadEvent::Progress {
+            path: artifact.path.clone(),
+            downloaded,
+            total,
+            bytes_per_second: ((downloaded - start) as f64 / elapsed) as u64,
+        }
+    };
+    loop {
+        let n = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(io_error(part, e)),
+        };
+        file.write_all(&buffer[..n])
+            .map_err(|e| io_error(part, e))?;
+        downloaded += n as u64;
+        if let Some(size) = artifact.size {
+            if downloaded > size {
+                drop(file);
+                let _ = fs::remove_file(part);
+                return Err(ProgramError::DownloadFailed(format!(
+                    "{} is larger than {} bytes",
+                    artifact.url, size
+                )));
+            }
+        }
+        if reported.elapsed() >= PROGRESS_INTERVAL {
+            reported = Instant::now();
+            on_event(progress(downloaded));
+        }
+    }
+    file.sync_all().map_err(|e| io_error(part, e))?;
+    on_event(progress(downloaded));
+    if let Some(length) = length {
+        if downloaded - start < length {
+            return Err(ProgramError::DownloadFailed(format!(
+                "{}: connection closed after {} of {} bytes",
+                artifact.url,
+                downloaded - start,
+                length
+            )));
+        }
+    }
+    Ok(resumed)
+}
+
+/// 下载单个文件到`target`，目标文件已存在且校验通过时跳过
+pub(crate) fn download(
+    artifact: &DownloadArtifact,
+    target: &Path,
+    on_event: &dyn Fn(DownloadEvent),
+) -> Result<(), ProgramError> {
+    let expected = artifact.sha256.trim().to_ascii_lowercase();
+    let finished = || {
+        on_event(DownloadEvent::Finished {
+            path: artifact.path.clone(),
+        })
+    };
+    if target.is_file() && sha256_file(target).map_err(|e| io_error(target, e))? == expected {
+        finished();
+        return Ok(());
+    }
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent).map_err(|e| io_error(parent, e))?;
+    }
+    let part = part_path(target);
+    loop {
+        let resumed = fetch(artifact, &part, on_event)?;
+        on_event(DownloadEvent::Verifying {
+            path: artifact.path.clone(),
+        });
+        let actual = sha256_file(&part).map_err(|e| io_error(&part, e))?;
+        if actual == expected {
+            break;
+        }
+        let _ = fs::remove_file(&part);
+        if !resumed {
+            return Err(ProgramError::ChecksumMismatch(format!(
+                "{}: expected {}, got {}",
+                artifact.path, expected, actual
+            )));
+        }
+        // 续传前的临时文件可能来自其他版本，从头重新下载
+        warn!(
+            "Resumed download of {} failed verification, restarting",
+            artifact.path
+        );
+    }
+    fs::rename(&part, target).map_err(|e| io_error(target, e))?;
+    finished();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::test_util::{self, response, temp_dir};
+
+    const BODY: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+
+    /// 按Range头返回响应的本地服务器
+    fn serve(count: usize, handler: impl Fn(Option<u64>) -> Vec<u8> + Send + 'static) -> String {
+        format!(
+            "{}/file",
+            test_util::serve(count, move |request| handler(request.range))
+        )
+    }
+
+    fn sha256(data: &[u8]) -> String {
+        format!("{:x}", Sha256::digest(data))
+    }
+
+    fn artifact(url: String, sha256: String) -> DownloadArtifact {
+        DownloadArtifact {
+            url,
+            path: "file".to_string(),
+            sha256,
+            size: None,
+        }
+    }
+
+    #[test]
+    fn resumes_with_range() {
+        let url = serve(1, |range| {
+            let offset = range.expect("range header") as usize;
+            response("206 Partial Content", &BODY[offset..], BODY.len() - offset)
+        });
+        let dir = temp_dir();
+        let target = dir.join("file");
+        fs::write(part_path(&target), &BODY[..10]).unwrap();
+        download(&artifact(url, sha256(BODY)), &target, &|_| {}).unwrap();
+        assert_eq!(fs::read(&target).unwrap(), BODY);
+        assert!(!part_path(&target).exists());
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn restarts_without_range_support() {
+        let url = serve(1, |_| response("200 OK", BODY, BODY.len()));
+        let dir = temp_dir();
+        let target = dir.join("file");
+        fs::write(part_path(&target), b"stale").unwrap();
+        download(&artifact(url, sha256(BODY)), &target, &|_| {}).unwrap();
+        assert_eq!(fs::read(&target).unwrap(), BODY);
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn range_not_satisfiable_uses_complete_part() {
+        let url = serve(1, |_| response("416 Range Not Satisfiable", b"", 0));
+        let dir = temp_dir();
+        let target = dir.join("file");
+        fs::write(part_path(&target), BODY).unwrap();
+        download(&artifact(url, sha256(BODY)), &target, &|_| {}).unwrap();
+        assert_eq!(fs::read(&target).unwrap(), BODY);
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn rejects_checksum_mismatch() {
+        let url = serve(1, |_| response("200 OK", BODY, BODY.len()));
+        let dir = temp_dir();
+        let target = dir.join("file");
+        let result = download(&artifact(url, sha256(b"other")), &target, &|_| {});
+        assert!(matches!(result, Err(ProgramError::ChecksumMismatch(_))));
+        assert!(!target.exists());
+        assert!(!part_path(&target).exists());
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn keeps_part_after_truncation() {
+        let url = serve(1, |_| response("200 OK", &BODY[..10], BODY.len()));
+        let dir = temp_dir();
+        let target = dir.join("file");
+        let result = download(&artifact(url, sha256(BODY)), &target, &|_| {});
+        assert!(matches!(result, Err(ProgramError::DownloadFailed(_))));
+        assert!(!target.exists());
+        // 保留已下载的部分以便续传
+        assert_eq!(fs::read(part_path(&target)).unwrap(), &BODY[..10]);
+        let _ = fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,170 @@
+use crate::tools::config_builder::ServerEntry;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant};
+
+/// 单个服务器探测超时
+const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+/// 连接断开超过该时间后切换服务器
+const DISCONNECT_THRESHOLD: Duration = Duration::from_secs(30);
+
+/// 服务器探测结果
+#[derive(serde::Serialize, Clone, Debug)]
+pub(crate) struct ServerProbe {
+    pub(crate) address: String,
+    pub(crate) priority: u32,
+    pub(crate) reachable: bool,
+    pub(crate) latency_ms: Option<u64>,
+    pub(crate) error: Option<String>,
+}
+
+/// 获取用于探测的tcp地址，txt记录无法直接探测
+fn probe_target(address: &str) -> Option<String> {
+    let address = address.trim();
+    if address.starts_with("txt:") {
+        return None;
+    }
+    let (rest, default_port) = if let Some(rest) = address.strip_prefix("wss://") {
+        (rest, Some(443))
+    } else if let Some(rest) = address.strip_prefix("ws://") {
+        (rest, Some(80))
+    } else {
+        let rest = address
+            .strip_prefix("udp://")
+            .or_else(|| address.strip_prefix("tcp://"))
+            .unwrap_or(address);
+        (rest, None)
+    };
+    let host = rest.split('/').next().unwrap_or(rest);
+    match default_port {
+        // websocket地址可以省略端口
+        Some(port) if !host.ends_with(']') && !host.contains(':') => {
+            Some(format!("{}:{}", host, port))
+        }
+        _ => Some(host.to_string()),
+    }
+}
+
+/// 通过tcp连接探测服务器是否可达及延迟，vnts默认在同一端口监听tcp和udp
+pub(crate) fn probe_server(entry: &ServerEntry) -> ServerProbe {
+    let mut probe = ServerProbe {
+        address: entry.address.clone(),
+        priority: entry.priority,
+        reachable: false,
+        latency_ms: None,
+        error: None,
+    };
+    let Some(target) = probe_target(&entry.address) else {
+        probe.reachable = true;
+        return probe;
+    };
+    let addrs = match target.to_socket_addrs() {
+        Ok(addrs) => addrs.collect::<Vec<_>>(),
+        Err(e) => {
+            probe.error = Some(e.to_string());
+            return probe;
+        }
+    };
+    for addr in addrs {
+        let start = Instant::now();
+        match TcpStream::connect_timeout(&addr, PROBE_TIMEOUT) {
+            Ok(_) => {
+                probe.reachable = true;
+                probe.latency_ms = Some(start.elapsed().as_millis() as u64);
+                probe.error = None;
+                break;
+            }
+            Err(e) => probe.error = Some(e.to_string()),
+        }
+    }
+    probe
+}
+
+/// 并行探测所有服务器，可达的按优先级和延迟排序，不可达的排在最后
+pub(crate) fn probe_servers(entries: &[ServerEntry]) -> Vec<ServerProbe> {
+    let mut probes = std::thread::scope(|scope| {
+        entries
+            .iter()
+            .map(|entry| scope.spawn(move || probe_server(entry)))
+            .collect::<Vec<_>>()
+            .into_iter()
+            .zip(entries)
+            .map(|(handle, entry)| {
+                handle.join().unwrap_or_else(|_| ServerProbe {
+                    address: entry.address.clone(),
+                    priority: entry.priority,
+                    reachable: false,
+                    latency_ms: None,
+                    error: Some("probe panicked".to_string()),
+                })
+            })
+            .collect::<Vec<_>>()
+    });
+    probes.sort_by_key(|x| (!x.reachable, x.priority, x.latency_ms.unwrap_or(u64::MAX)));
+    probes
+}
+
+/// 服务器故障切换状态
+pub(crate) struct Failover {
+    /// 本次启动的会话号，用于结束过期的监视线程
+    pub(crate) session: u64,
+    servers: Vec<String>,
+    index: usize,
+    registered: bool,
+    /// 未连接状态开始的时间
+    since: Instant,
+    /// 注册失败，需要立即切换
+    switch_requested: bool,
+}
+
+impl Failover {
+    pub(crate) fn new(session: u64, servers: Vec<String>) -> Self {
+        Self {
+            session,
+            servers,
+            index: 0,
+            registered: false,
+            since: Instant::now(),
+            switch_requested: false,
+        }
+    }
+
+    /// 当前使用的服务器
+    pub(crate) fn active(&self) -> Option<&String> {
+        self.servers.get(self.index)
+    }
+
+    pub(crate) fn on_registered(&mut self) {
+        self.registered = true;
+        self.switch_requested = false;
+    }
+
+    pub(crate) fn on_disconnected(&mut self) {
+        if self.registered {
+            self.registered = false;
+            self.since = Instant::now();
+        }
+    }
+
+    /// 注册前出现错误视为注册失败
+    pub(crate) fn on_error(&mut self) {
+        if !self.registered {
+            self.switch_requested = true;
+        }
+    }
+
+    /// 是否需要切换到下一个服务器
+    pub(crate) fn should_switch(&self) -> bool {
+        self.servers.len() > 1
+            && !self.registered
+            && (self.switch_requested || self.since.elapsed() > DISCONNECT_THRESHOLD)
+    }
+
+    /// 切换到下一个服务器
+    pub(crate) fn advance(&mut self) -> Option<&String> {
+        self.index = (self.index + 1) % self.servers.len().max(1);
+        self.registered = false;
+        self.since = Instant::now();
+        self.switch_requested = false;
+        self.active()
+    }
+}
@@ -0,0 +1,585 @@
+//! 按版本安装工具
+//!
+//! 每个版本解压到`工具目录/installed/<工具>/<版本>`，`active`文件记录当前使用的版本，
+//! 启动工具时从当前版本目录中查找可执行文件。支持zip、tar.gz、tar.xz及单个文件，
+//! 解压时拒绝绝对路径、`..`和链接等可能写到目标目录之外的条目。
+
+use crate::errors::ProgramError;
+use crate::tools::command::ChildrenManager;
+use crate::tools::downloader::{download, DownloadArtifact, DownloadEvent, DownloadManager};
+use crate::tools::tool_registry::tools_dir;
+use log::warn;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+use tauri::{AppHandle, Manager, State};
+
+/// 安装目录，相对于工具目录
+const INSTALLED_DIR: &str = "installed";
+/// 记录当前版本的文件
+const ACTIVE_FILE: &str = "active";
+/// 下载中的安装包所在目录
+const DOWNLOADS_DIR: &str = ".downloads";
+
+/// 安装包格式
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ArchiveFormat {
+    Zip,
+    TarGz,
+    TarXz,
+    /// 单个文件，原样放入版本目录
+    File,
+}
+
+impl ArchiveFormat {
+    /// 按文件名后缀推断格式
+    fn detect(name: &str) -> Self {
+        let name = name.to_ascii_lowercase();
+        if name.ends_with(".zip") {
+            ArchiveFormat::Zip
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            ArchiveFormat::TarGz
+        } else if name.ends_with(".tar.xz") || name.ends_with(".txz") {
+            ArchiveFormat::TarXz
+        } else {
+            ArchiveFormat::File
+        }
+    }
+}
+
+/// 安装来源
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub(crate) struct InstallSource {
+    pub(crate) url: String,
+    /// 安装包的SHA-256，十六进制，下载后必须校验
+    #[serde(default)]
+    pub(crate) sha256: String,
+    #[serde(default)]
+    pub(crate) size: Option<u64>,
+    /// 缺省时按URL的后缀推断
+    #[serde(default)]
+    pub(crate) format: Option<ArchiveFormat>,
+    /// 解压时去掉的前导目录层数，用于安装包内有一层顶层目录的情况
+    #[serde(default)]
+    pub(crate) strip_components: usize,
+}
+
+#[derive(serde::Serialize, Clone, Debug)]
+pub(crate) struct InstalledVersion {
+    version: String,
+    size_bytes: u64,
+}
+
+/// 已安装的工具
+#[derive(serde::Serialize, Clone, Debug)]
+pub(crate) struct InstalledTool {
+    tool: String,
+    pub(crate) active: Option<String>,
+    versions: Vec<InstalledVersion>,
+    /// 全部版本占用的磁盘空间
+    size_bytes: u64,
+}
+
+/// 工具名和版本号只能是单个普通路径段，且不能以`.`开头或与`active`重名
+pub(crate) fn check_name(name: &str) -> Result<(), ProgramError> {
+    let mut components = Path::new(name).components();
+    let single = matches!(
+        (components.next(), components.next()),
+        (Some(Component::Normal(x)), None) if x == name
+    );
+    if !single || name.starts_with('.') || name == ACTIVE_FILE {
+        return Err(ProgramError::InvalidToolName(name.to_string()));
+    }
+    Ok(())
+}
+
+fn installed_dir(app: &AppHandle) -> Result<PathBuf, ProgramError> {
+    tools_dir(app)
+        .map(|x| x.join(INSTALLED_DIR))
+        .map_err(|e| ProgramError::ExtractFailed(e.to_string()))
+}
+
+fn tool_dir(app: &AppHandle, tool: &str) -> Result<PathBuf, ProgramError> {
+    check_name(tool)?;
+    Ok(installed_dir(app)?.join(tool))
+}
+
+fn read_active(tool_dir: &Path) -> Option<String> {
+    let version = fs::read_to_string(tool_dir.join(ACTIVE_FILE)).ok()?;
+    let version = version.trim().to_string();
+    check_name(&version).ok()?;
+    Some(version)
+}
+
+/// 原子地替换当前版本
+fn write_active(tool_dir: &Path, version: &str) -> std::io::Result<()> {
+    let temp = tool_dir.join(format!(".{}.tmp", ACTIVE_FILE));
+    fs::write(&temp, version)?;
+    fs::rename(&temp, tool_dir.join(ACTIVE_FILE))
+}
+
+/// 工具当前版本的目录，未通过安装器安装时为空
+pub(crate) fn active_dir(tools_dir: &Path, tool: &str) -> Option<PathBuf> {
+    check_name(tool).ok()?;
+    let tool_dir = tools_dir.join(INSTALLED_DIR).join(tool);
+    let dir = tool_dir.join(read_active(&tool_dir)?);
+    dir.is_dir().then_some(dir)
+}
+
+/// 目录占用的磁盘空间，不跟随符号链接
+fn disk_usage(path: &Path) -> u64 {
+    let Ok(metadata) = fs::symlink_metadata(path) else {
+        return 0;
+    };
+    if !metadata.is_dir() {
+        return metadata.len();
+    }
+    fs::read_dir(path)
+        .map(|entries| {
+            entries
+                .filter_map(Result::ok)
+                .map(|x| disk_usage(&x.path()))
+                .sum()
+        })
+        .unwrap_or(0)
+}
+
+/// 将安装包内的路径转换为相对路径，去掉前导目录，去掉后为空时返回None
+fn entry_path(path: &Path, strip: usize) -> Result<Option<PathBuf>, ProgramError> {
+    let mut relative = PathBuf::new();
+    let mut skipped = 0;
+    for component in path.components() {
+        match component {
+            Component::Normal(_) if skipped < strip => skipped += 1,
+            Component::Normal(part) => relative.push(part),
+            Component::CurDir => {}
+            _ => {
+                return Err(ProgramError::UnsafeArchiveEntry(
+                    path.to_string_lossy().to_string(),
+                ))
+            }
+        }
+    }
+    Ok((!relative.as_os_str().is_empty()).then_some(relative))
+}
+
+fn extract_error(e: impl std::fmt::Display) -> ProgramError {
+    ProgramError::ExtractFailed(e.to_string())
+}
+
+fn extract_zip(archive: &Path, dest: &Path, strip: usize) -> Result<(), ProgramError> {
+    let mut zip =
+        zip::ZipArchive::new(File::open(archive).map_err(extract_error)?).map_err(extract_error)?;
+    for index in 0..zip.len() {
+        let mut entry = zip.by_index(index).map_err(extract_error)?;
+        let name = entry.name().to_string();
+        // enclosed_name会拒绝绝对路径和..
+        let Some(path) = entry.enclosed_name() else {
+            return Err(ProgramError::UnsafeArchiveEntry(name));
+        };
+        if entry.is_symlink() {
+            return Err(ProgramError::UnsafeArchiveEntry(name));
+        }
+        let Some(relative) = entry_path(&path, strip)? else {
+            continue;
+        };
+        let target = dest.join(relative);
+        if entry.is_dir() {
+            fs::create_dir_all(&target).map_err(extract_error)?;
+            continue;
+        }
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent).map_err(extract_error)?;
+        }
+        let mut file = File::create(&target).map_err(extract_error)?;
+        std::io::copy(&mut entry, &mut file).map_err(extract_error)?;
+        #[cfg(unix)]
+        if let Some(mode) = entry.unix_mode() {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&target, fs::Permissions::from_mode(mode & 0o755))
+                .map_err(extract_error)?;
+        }
+    }
+    Ok(())
+}
+
+fn extract_tar(reader: impl Read, dest: &Path, strip: usize) -> Result<(), ProgramError> {
+    let mut archive = tar::Archive::new(reader);
+    for entry in archive.entries().map_err(extract_error)? {
+        let mut entry = entry.map_err(extract_error)?;
+        let path = entry.path().map_err(extract_error)?.into_owned();
+        let kind = entry.header().entry_type();
+        // 全局扩展头只包含元数据
+        if kind.is_pax_global_extensions() {
+            continue;
+        }
+        if !(kind.is_file() || kind.is_dir()) {
+            // 链接可能指向目标目录之外，设备等特殊文件也不应出现在工具中
+            return Err(ProgramError::UnsafeArchiveEntry(
+                path.to_string_lossy().to_string(),
+            ));
+        }
+        let Some(relative) = entry_path(&path, strip)? else {
+            continue;
+        };
+        let target = dest.join(relative);
+        if kind.is_dir() {
+            fs::create_dir_all(&target).map_err(extract_error)?;
+            continue;
+        }
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent).map_err(extract_error)?;
+        }
+        entry.unpack(&target).map_err(extract_error)?;
+    }
+    Ok(())
+}
+
+/// 解压到`dest`，`name`为下载时的文件名，单个文件按该名称保存
+fn extract(
+    archive: &Path,
+    name: &str,
+    format: ArchiveFormat,
+    dest: &Path,
+    strip: usize,
+) -> Result<(), ProgramError> {
+    fs::create_dir_all(dest).map_err(extract_error)?;
+    match format {
+        ArchiveFormat::Zip => extract_zip(archive, dest, strip),
+        ArchiveFormat::TarGz => {
+            let file = File::open(archive).map_err(extract_error)?;
+            extract_tar(flate2::read::GzDecoder::new(file), dest, strip)
+        }
+        ArchiveFormat::TarXz => {
+            let file = File::open(archive).map_err(extract_error)?;
+            extract_tar(xz2::read::XzDecoder::new(file), dest, strip)
+        }
+        ArchiveFormat::File => {
+            check_name(name)?;
+            let target = dest.join(name);
+            fs::copy(archive, &target).map_err(extract_error)?;
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                fs::set_permissions(&target, fs::Permissions::from_mode(0o755))
+                    .map_err(extract_error)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// URL路径的最后一段
+fn file_name(url: &str) -> String {
+    let path = url.split(['?', '#']).next().unwrap_or_default();
+    path.rsplit('/').next().unwrap_or_default().to_string()
+}
+
+fn installed_tool(tool_dir: &Path, tool: &str) -> InstalledTool {
+    let mut versions: Vec<_> = fs::read_dir(tool_dir)
+        .map(|entries| {
+            entries
+                .filter_map(Result::ok)
+                .filter(|x| x.path().is_dir())
+                .filter_map(|x| x.file_name().into_string().ok())
+                .filter(|x| check_name(x).is_ok())
+                .map(|version| InstalledVersion {
+                    size_bytes: disk_usage(&tool_dir.join(&version)),
+                    version,
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    versions.sort_by(|a, b| a.version.cmp(&b.version));
+    InstalledTool {
+        tool: tool.to_string(),
+        active: read_active(tool_dir),
+        size_bytes: versions.iter().map(|x| x.size_bytes).sum(),
+        versions,
+    }
+}
+
+/// 下载并解压到版本目录，先解压到临时目录，完成后再重命名，避免留下不完整的版本
+///
+/// 来源只能是签名的工具清单，前端不能直接指定安装地址
+pub(crate) fn install(
+    app: &AppHandle,
+    tool: &str,
+    version: &str,
+    source: &InstallSource,
+    activate: bool,
+    on_event: &dyn Fn(DownloadEvent),
+) -> Result<InstalledTool, ProgramError> {
+    check_name(version)?;
+    let tool_dir = tool_dir(app, tool)?;
+    let version_dir = tool_dir.join(version);
+    if !version_dir.is_dir() {
+        let name = file_name(&source.url);
+        let format = source
+            .format
+            .unwrap_or_else(|| ArchiveFormat::detect(&name));
+        // 同一版本的安装包位置固定，中断后再次安装可以续传
+        let archive = tool_dir.join(DOWNLOADS_DIR).join(version);
+        let artifact = DownloadArtifact {
+            url: source.url.clone(),
+            path: format!("{}/{}/{}", tool, version, name),
+            sha256: source.sha256.clone(),
+            size: source.size,
+        };
+        let manager = app.state::<DownloadManager>();
+        let _guard = manager.acquire(vec![version_dir.clone()])?;
+        download(&artifact, &archive, on_event)?;
+        let staging = tool_dir.join(format!(".staging-{}", uuid::Uuid::new_v4()));
+        let result = extract(&archive, &name, format, &staging, source.strip_components)
+            .and_then(|_| fs::rename(&staging, &version_dir).map_err(extract_error));
+        let _ = fs::remove_file(&archive);
+        if let Err(e) = result {
+            let _ = fs::remove_dir_all(&staging);
+            return Err(e);
+        }
+    }
+    if activate || read_active(&tool_dir).is_none() {
+        write_active(&tool_dir, version).map_err(extract_error)?;
+    }
+    Ok(installed_tool(&tool_dir, tool))
+}
+
+/// 切换当前版本，新启动的工具使用该版本
+#[tauri::command]
+pub(crate) fn set_active_version(
+    app: AppHandle,
+    tool: String,
+    version: String,
+) -> Result<InstalledTool, String> {
+    check_name(&version).map_err(|e| e.to_string())?;
+    let tool_dir = tool_dir(&app, &tool).map_err(|e| e.to_string())?;
+    if !tool_dir.join(&version).is_dir() {
+        return Err(ProgramError::VersionNotInstalled(format!("{} {}", tool, version)).to_string());
+    }
+    write_active(&tool_dir, &version).map_err(|e| extract_error(e).to_string())?;
+    Ok(installed_tool(&tool_dir, &tool))
+}
+
+/// 卸载某个版本，未指定版本时卸载全部版本，版本中的程序仍在运行时拒绝
+pub(crate) fn uninstall(
+    app: &AppHandle,
+    manager: &ChildrenManager,
+    tool: &str,
+    version: Option<&str>,
+) -> Result<Option<InstalledTool>, ProgramError> {
+    let tool_dir = tool_dir(app, tool)?;
+    let target = match version {
+        Some(version) => {
+            check_name(version)?;
+            tool_dir.join(version)
+        }
+        None => tool_dir.clone(),
+    };
+    if !target.is_dir() {
+        let name = match version {
+            Some(version) => format!("{} {}", tool, version),
+            None => tool.to_string(),
+        };
+        return Err(ProgramError::VersionNotInstalled(name));
+    }
+    // 子进程记录的是规范化后的路径
+    let canonical = target.canonicalize().unwrap_or_else(|_| target.clone());
+    if manager.is_running_from(&canonical) {
+        return Err(ProgramError::ToolInUse(tool.to_string()));
+    }
+    fs::remove_dir_all(&target).map_err(extract_error)?;
+    let Some(version) = version else {
+        return Ok(None);
+    };
+    if read_active(&tool_dir).as_deref() == Some(version) {
+        if let Err(e) = fs::remove_file(tool_dir.join(ACTIVE_FILE)) {
+            warn!("Failed to clear active version of {}: {}", tool, e);
+        }
+    }
+    Ok(Some(installed_tool(&tool_dir, tool)))
+}
+
+/// 工具的安装情况，未安装任何版本时为空
+pub(crate) fn installed(app: &AppHandle, tool: &str) -> Option<InstalledTool> {
+    let tool_dir = tool_dir(app, tool).ok()?;
+    let installed = installed_tool(&tool_dir, tool);
+    (!installed.versions.is_empty()).then_some(installed)
+}
+
+#[tauri::command]
+pub(crate) fn uninstall_tool(
+    app: AppHandle,
+    tool: String,
+    version: Option<String>,
+    manager: State<ChildrenManager>,
+) -> Result<Option<InstalledTool>, String> {
+    uninstall(&app, &manager, &tool, version.as_deref()).map_err(|e| e.to_string())
+}
+
+/// 列出已安装的工具及各版本占用的磁盘空间
+#[tauri::command]
+pub(crate) fn list_installed_tools(app: AppHandle) -> Result<Vec<InstalledTool>, String> {
+    let dir = installed_dir(&app).map_err(|e| e.to_string())?;
+    let Ok(entries) = fs::read_dir(&dir) else {
+        return Ok(vec![]);
+    };
+    let mut tools: Vec<_> = entries
+        .filter_map(Result::ok)
+        .filter(|x| x.path().is_dir())
+        .filter_map(|x| x.file_name().into_string().ok())
+        .filter(|x| check_name(x).is_ok())
+        .map(|tool| installed_tool(&dir.join(&tool), &tool))
+        .collect();
+    tools.sort_by(|a, b| a.tool.cmp(&b.tool));
+    Ok(tools)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::test_util::temp_dir;
+    use std::io::Write;
+
+    /// 绕过tar::Builder的路径检查，写入任意路径的条目
+    fn tar_entry(
+        builder: &mut tar::Builder<Vec<u8>>,
+        path: &str,
+        kind: tar::EntryType,
+        data: &[u8],
+    ) {
+        let mut header = tar::Header::new_gnu();
+        header.as_old_mut().name[..path.len()].copy_from_slice(path.as_bytes());
+        header.set_entry_type(kind);
+        header.set_mode(0o644);
+        header.set_size(data.len() as u64);
+        if kind.is_symlink() {
+            header.set_link_name("/etc/passwd").unwrap();
+        }
+        header.set_cksum();
+        builder.append(&header, data).unwrap();
+    }
+
+    fn tar_gz(dir: &Path, entries: &[(&str, tar::EntryType)]) -> PathBuf {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (path, kind) in entries {
+            let data: &[u8] = if kind.is_file() { b"data" } else { b"" };
+            tar_entry(&mut builder, path, *kind, data);
+        }
+        let archive = dir.join("tool.tar.gz");
+        let mut encoder =
+            flate2::write::GzEncoder::new(File::create(&archive).unwrap(), Default::default());
+        encoder.write_all(&builder.into_inner().unwrap()).unwrap();
+        encoder.finish().unwrap();
+        archive
+    }
+
+    fn zip(dir: &Path, files: &[&str], symlink: Option<&str>) -> PathBuf {
+        let archive = dir.join("tool.zip");
+        let mut writer = zip::ZipWriter::new(File::create(&archive).unwrap());
+        let options = zip::write::SimpleFileOptions::default()
+            .compression_method(zip::CompressionMethod::Stored);
+        for name in files {
+            writer.start_file(*name, options).unwrap();
+            writer.write_all(b"data").unwrap();
+        }
+        if let Some(name) = symlink {
+            writer.add_symlink(name, "/etc/passwd", options).unwrap();
+        }
+        writer.finish().unwrap();
+        archive
+    }
+
+    fn assert_unsafe(result: Result<(), ProgramError>, dir: &Path) {
+        assert!(matches!(result, Err(ProgramError::UnsafeArchiveEntry(_))));
+        assert!(!dir.join("evil").exists());
+    }
+
+    #[test]
+    fn extracts_tar_with_strip() {
+        let dir = temp_dir();
+        let archive = tar_gz(
+            &dir,
+            &[
+                ("tool-1.0/", tar::EntryType::Directory),
+                ("tool-1.0/bin/tool", tar::EntryType::Regular),
+            ],
+        );
+        let dest = dir.join("out");
+        extract(&archive, "tool.tar.gz", ArchiveFormat::TarGz, &dest, 1).unwrap();
+        assert_eq!(fs::read(dest.join("bin/tool")).unwrap(), b"data");
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn rejects_tar_parent_dir() {
+        let dir = temp_dir();
+        let archive = tar_gz(&dir, &[("../evil", tar::EntryType::Regular)]);
+        let dest = dir.join("out");
+        assert_unsafe(
+            extract(&archive, "tool.tar.gz", ArchiveFormat::TarGz, &dest, 0),
+            &dir,
+        );
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn rejects_tar_absolute_path() {
+        let dir = temp_dir();
+        let evil = dir.join("evil");
+        let archive = tar_gz(&dir, &[(evil.to_str().unwrap(), tar::EntryType::Regular)]);
+        let dest = dir.join("out");
+        assert_unsafe(
+            extract(&archive, "tool.tar.gz", ArchiveFormat::TarGz, &dest, 0),
+            &dir,
+        );
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn rejects_tar_symlink() {
+        let dir = temp_dir();
+        let archive = tar_gz(&dir, &[("evil", tar::EntryType::Symlink)]);
+        let dest = dir.join("out");
+        let result = extract(&archive, "tool.tar.gz", ArchiveFormat::TarGz, &dest, 0);
+        assert!(matches!(result, Err(ProgramError::UnsafeArchiveEntry(_))));
+        assert!(fs::symlink_metadata(dest.join("evil")).is_err());
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn rejects_zip_parent_dir() {
+        let dir = temp_dir();
+        let archive = zip(&dir, &["../evil"], None);
+        let dest = dir.join("out");
+        assert_unsafe(
+            extract(&archive, "tool.zip", ArchiveFormat::Zip, &dest, 0),
+            &dir,
+        );
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn rejects_zip_absolute_path() {
+        let dir = temp_dir();
+        let evil = dir.join("evil");
+        let archive = zip(&dir, &[evil.to_str().unwrap()], None);
+        let dest = dir.join("out");
+        assert_unsafe(
+            extract(&archive, "tool.zip", ArchiveFormat::Zip, &dest, 0),
+            &dir,
+        );
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn rejects_zip_symlink() {
+        let dir = temp_dir();
+        let archive = zip(&dir, &["tool"], Some("evil"));
+        let dest = dir.join("out");
+        let result = extract(&archive, "tool.zip", ArchiveFormat::Zip, &dest, 0);
+        assert!(matches!(result, Err(ProgramError::UnsafeArchiveEntry(_))));
+        assert!(fs::symlink_metadata(dest.join("evil")).is_err());
+        let _ = fs::remove_dir_all(dir);
+    }
+}
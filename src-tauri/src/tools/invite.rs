@@ -0,0 +1,558 @@
+use crate::errors::ProgramError;
+use crate::tools::config_builder::{apply_config, CipherMode, Config, ServerEntry};
+use crate::tools::profile::{save_profiles, upsert_profile, Profile};
+use crate::tools::secret::{decrypt, encrypt};
+use crate::tools::{write_file_atomic, ExternalFilePosition, Status};
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::OsRng;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use log::{error, info};
+use sha2::{Digest, Sha256};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Emitter, Manager, State};
+
+/// 邀请链接前缀
+pub(crate) const INVITE_URI_PREFIX: &str = "lightp2p://join";
+/// 邀请格式版本
+const INVITE_VERSION: u8 = 1;
+/// 核对密码的摘要轮数，持有邀请者可以据此离线猜测密码，不能太低
+const PASSWORD_CHECK_ROUNDS: u32 = 100_000;
+
+/// 邀请内容，字段名尽量短以缩短链接
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
+pub(crate) struct Invite {
+    #[serde(rename = "v")]
+    pub(crate) version: u8,
+    #[serde(rename = "n")]
+    pub(crate) profile: String,
+    #[serde(rename = "s")]
+    pub(crate) server: String,
+    /// 邀请者的服务器列表，为空时只使用`server`
+    #[serde(rename = "l", default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) servers: Vec<ServerEntry>,
+    #[serde(rename = "t")]
+    pub(crate) token: String,
+    #[serde(rename = "c", default)]
+    pub(crate) cipher_mode: CipherMode,
+    #[serde(rename = "p", default, skip_serializing_if = "Option::is_none")]
+    pub(crate) password: Option<String>,
+    /// 网络需要密码但邀请中未包含
+    #[serde(rename = "r", default)]
+    pub(crate) password_required: bool,
+    /// 未包含密码时用于核对输入的密码，`盐.摘要`形式
+    #[serde(rename = "h", default, skip_serializing_if = "Option::is_none")]
+    pub(crate) password_check: Option<String>,
+    /// 过期时间，unix时间戳(秒)
+    #[serde(rename = "e", default, skip_serializing_if = "Option::is_none")]
+    pub(crate) expires_at: Option<u64>,
+    /// 邀请者的签名公钥，base64编码
+    #[serde(rename = "k", default)]
+    pub(crate) inviter: String,
+}
+
+/// 生成的邀请
+#[derive(serde::Serialize, Clone)]
+pub(crate) struct InviteLink {
+    pub(crate) code: String,
+    pub(crate) uri: String,
+    /// 邀请者公钥指纹，供被邀请者通过其他渠道核对
+    pub(crate) fingerprint: String,
+}
+
+/// 邀请预览，不包含token和密码
+#[derive(serde::Serialize, Clone)]
+pub(crate) struct InvitePreview {
+    profile: String,
+    server: String,
+    servers: Vec<String>,
+    cipher_mode: CipherMode,
+    has_password: bool,
+    password_required: bool,
+    expires_at: Option<u64>,
+    /// 邀请者公钥指纹
+    inviter: String,
+}
+
+/// 通过深度链接打开但尚未处理的邀请
+#[derive(Default)]
+pub(crate) struct PendingInvite(pub(crate) Mutex<Option<String>>);
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|x| x.as_secs())
+        .unwrap_or(0)
+}
+
+/// 本机的邀请签名密钥，首次使用时生成，加密保存
+fn signing_key() -> Result<SigningKey, ProgramError> {
+    let path = ExternalFilePosition::InviteKey.to_string();
+    let path = Path::new(&path);
+    if path.exists() {
+        let content =
+            std::fs::read_to_string(path).map_err(|e| ProgramError::ReadFailed(e.to_string()))?;
+        let seed = URL_SAFE_NO_PAD
+            .decode(decrypt(content.trim())?)
+            .ok()
+            .and_then(|x| <[u8; 32]>::try_from(x).ok())
+            .ok_or_else(|| ProgramError::SecretFailed("invalid invite key".to_string()))?;
+        return Ok(SigningKey::from_bytes(&seed));
+    }
+    let mut seed = [0u8; 32];
+    OsRng.fill_bytes(&mut seed);
+    let content = encrypt(&URL_SAFE_NO_PAD.encode(seed))?;
+    write_file_atomic(path, content.as_bytes())
+        .map_err(|_| ProgramError::WriteFailed(path.to_string_lossy().to_string()))?;
+    info!("invite signing key created");
+    Ok(SigningKey::from_bytes(&seed))
+}
+
+/// 由密码和盐计算核对值
+fn password_check(password: &str, salt: &[u8]) -> String {
+    let mut digest = [0u8; 16];
+    pbkdf2::pbkdf2_hmac::<Sha256>(
+        password.as_bytes(),
+        salt,
+        PASSWORD_CHECK_ROUNDS,
+        &mut digest,
+    );
+    format!(
+        "{}.{}",
+        URL_SAFE_NO_PAD.encode(salt),
+        URL_SAFE_NO_PAD.encode(digest)
+    )
+}
+
+/// 公钥指纹，SHA-256前8字节
+fn fingerprint(key: &VerifyingKey) -> String {
+    Sha256::digest(key.as_bytes())[..8]
+        .chunks(2)
+        .map(|x| format!("{:02x}{:02x}", x[0], x[1]))
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+impl Invite {
+    /// 从当前配置生成邀请
+    pub(crate) fn from_config(
+        config: &Config,
+        profile: String,
+        include_password: bool,
+        expires_in: Option<u64>,
+    ) -> Self {
+        let password_check =
+            config
+                .password
+                .as_ref()
+                .filter(|_| !include_password)
+                .map(|password| {
+                    let mut salt = [0u8; 16];
+                    OsRng.fill_bytes(&mut salt);
+                    password_check(password, &salt)
+                });
+        Self {
+            version: INVITE_VERSION,
+            profile,
+            // 只配置了服务器列表时使用优先级最高的地址，供预览核对
+            server: config
+                .server_candidates()
+                .first()
+                .map(|x| x.address.clone())
+                .unwrap_or_default(),
+            servers: config.servers.clone(),
+            token: config.token.clone(),
+            cipher_mode: config.advanced.cipher_mode,
+            password: config.password.clone().filter(|_| include_password),
+            password_required: config.password.is_some() && !include_password,
+            password_check,
+            expires_at: expires_in.map(|x| now_secs().saturating_add(x)),
+            inviter: String::new(),
+        }
+    }
+
+    /// 用邀请者密钥签名，编码为 `负载.签名` 形式
+    pub(crate) fn encode(&self, key: &SigningKey) -> Result<String, ProgramError> {
+        let mut invite = self.clone();
+        invite.inviter = URL_SAFE_NO_PAD.encode(key.verifying_key().as_bytes());
+        let payload =
+            serde_json::to_vec(&invite).map_err(|e| ProgramError::InvalidInvite(e.to_string()))?;
+        let payload = URL_SAFE_NO_PAD.encode(payload);
+        let signature = URL_SAFE_NO_PAD.encode(key.sign(payload.as_bytes()).to_bytes());
+        Ok(format!("{}.{}", payload, signature))
+    }
+
+    fn verifying_key(&self) -> Result<VerifyingKey, ProgramError> {
+        URL_SAFE_NO_PAD
+            .decode(&self.inviter)
+            .ok()
+            .and_then(|x| <[u8; 32]>::try_from(x).ok())
+            .and_then(|x| VerifyingKey::from_bytes(&x).ok())
+            .ok_or_else(|| ProgramError::InvalidInvite("malformed inviter key".to_string()))
+    }
+
+    /// 邀请者公钥指纹
+    pub(crate) fn fingerprint(&self) -> Result<String, ProgramError> {
+        Ok(fingerprint(&self.verifying_key()?))
+    }
+
+    /// 解码邀请码或邀请链接，拒绝签名不符或已过期的邀请
+    ///
+    /// 签名保证内容未在邀请者签发后被改动；重新签名的邀请会带有不同的指纹，
+    /// 因此加入前需要用户核对服务器、网络和邀请者指纹。
+    pub(crate) fn decode(input: &str) -> Result<Self, ProgramError> {
+        let input = input.trim();
+        let code = match input.strip_prefix(INVITE_URI_PREFIX) {
+            Some(query) => query
+                .trim_start_matches('/')
+                .trim_start_matches('?')
+                .split('&')
+                .find_map(|x| x.strip_prefix("invite="))
+                .ok_or_else(|| ProgramError::InvalidInvite("missing invite".to_string()))?,
+            None => input,
+        };
+        let Some((payload, signature)) = code.split_once('.') else {
+            return Err(ProgramError::InvalidInvite("malformed invite".to_string()));
+        };
+        let signature = URL_SAFE_NO_PAD
+            .decode(signature)
+            .ok()
+            .and_then(|x| Signature::from_slice(&x).ok())
+            .ok_or_else(|| ProgramError::InvalidInvite("malformed signature".to_string()))?;
+        let content = URL_SAFE_NO_PAD
+            .decode(payload)
+            .map_err(|_| ProgramError::InvalidInvite("malformed payload".to_string()))?;
+        let invite = serde_json::from_slice::<Self>(&content)
+            .map_err(|e| ProgramError::InvalidInvite(e.to_string()))?;
+        if invite.version != INVITE_VERSION {
+            return Err(ProgramError::InvalidInvite(format!(
+                "unsupported version: {}",
+                invite.version
+            )));
+        }
+        if invite
+            .verifying_key()?
+            .verify_strict(payload.as_bytes(), &signature)
+            .is_err()
+        {
+            return Err(ProgramError::InvalidInvite(
+                "signature mismatch".to_string(),
+            ));
+        }
+        if invite.expires_at.is_some_and(|x| x < now_secs()) {
+            return Err(ProgramError::InvalidInvite("invite expired".to_string()));
+        }
+        Ok(invite)
+    }
+
+    /// 生成配置档案，设备id和昵称沿用本机配置
+    pub(crate) fn to_profile(&self, local: &Config, password: Option<String>) -> Profile {
+        let mut config = local.clone();
+        config.server_address_str = self.server.clone();
+        // 本机的服务器列表属于其他网络
+        config.servers = self.servers.clone();
+        config.token = self.token.clone();
+        config.password = self.password.clone().or(password);
+        config.advanced = Default::default();
+        config.advanced.cipher_mode = self.cipher_mode;
+        Profile {
+            name: self.profile.clone(),
+            config,
+        }
+    }
+
+    /// 用本机密钥签名并生成链接
+    pub(crate) fn to_link(&self) -> Result<InviteLink, ProgramError> {
+        let key = signing_key()?;
+        let code = self.encode(&key)?;
+        let uri = format!("{}?invite={}", INVITE_URI_PREFIX, code);
+        Ok(InviteLink {
+            code,
+            uri,
+            fingerprint: fingerprint(&key.verifying_key()),
+        })
+    }
+
+    /// 核对用户在预览中确认过的服务器和邀请者，避免未经确认就应用邀请
+    pub(crate) fn check_confirmed(&self, server: &str, inviter: &str) -> Result<(), ProgramError> {
+        if self.server != server || self.fingerprint()? != inviter {
+            return Err(ProgramError::InvalidInvite(
+                "invite does not match the confirmed server or inviter".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// 邀请未包含密码时，核对用户输入的密码
+    pub(crate) fn check_password(&self, password: Option<&str>) -> Result<(), ProgramError> {
+        if !self.password_required {
+            return Ok(());
+        }
+        let Some(password) = password.filter(|x| !x.is_empty()) else {
+            return Err(ProgramError::InvalidInvite("password required".to_string()));
+        };
+        if let Some(check) = &self.password_check {
+            let salt = check
+                .split_once('.')
+                .and_then(|(salt, _)| URL_SAFE_NO_PAD.decode(salt).ok())
+                .ok_or_else(|| {
+                    ProgramError::InvalidInvite("malformed password check".to_string())
+                })?;
+            if password_check(password, &salt) != *check {
+                return Err(ProgramError::InvalidInvite("wrong password".to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    /// 校验后保存为配置档案，接受邀请和识别二维码共用
+    pub(crate) fn save_profile(
+        &self,
+        app: &AppHandle,
+        status: &mut Status,
+        password: Option<String>,
+    ) -> Result<Profile, ProgramError> {
+        self.check_password(password.as_deref())?;
+        let profile = self.to_profile(&status.config, password);
+        profile
+            .config
+            .validate()
+            .and_then(|_| profile.config.validate_servers())?;
+        let mut profiles = status.profiles.clone();
+        upsert_profile(&mut profiles, profile.clone());
+        save_profiles(app, &profiles)?;
+        status.profiles = profiles;
+        Ok(profile)
+    }
+}
+
+/// 处理通过深度链接打开的邀请，交由前端确认
+pub(crate) fn open_invite_url(app: &AppHandle, url: &str) {
+    if !url.starts_with(INVITE_URI_PREFIX) {
+        return;
+    }
+    info!("invite link opened");
+    if let Ok(mut pending) = app.state::<PendingInvite>().0.lock() {
+        pending.replace(url.to_string());
+    }
+    if let Some(window) = app.get_webview_window("main") {
+        let _ = window.show();
+        let _ = window.set_focus();
+    }
+    if let Err(e) = app.emit("lers://invite/open", url.to_string()) {
+        error!("Failed to emit invite: {}", e);
+    }
+}
+
+/// 生成当前网络的邀请
+#[tauri::command]
+pub(crate) fn create_invite(
+    profile: Option<String>,
+    include_password: bool,
+    expires_in: Option<u64>,
+    status: State<'_, Mutex<Status>>,
+) -> Result<InviteLink, String> {
+    match status
+        .lock()
+        .map_err(|e| ProgramError::ReadFailed(e.to_string()))
+    {
+        Ok(status) => {
+            if status.secrets_locked {
+                return Err(ProgramError::SecretsLocked.to_string());
+            }
+            let config = &status.config;
+            let profile = profile.unwrap_or_else(|| config.server_address_str.clone());
+            Invite::from_config(config, profile, include_password, expires_in)
+                .to_link()
+                .map_err(|e| e.to_string())
+        }
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// 预览邀请内容
+#[tauri::command]
+pub(crate) fn preview_invite(invite: String) -> Result<InvitePreview, String> {
+    let invite = Invite::decode(&invite).map_err(|e| e.to_string())?;
+    let inviter = invite.fingerprint().map_err(|e| e.to_string())?;
+    Ok(InvitePreview {
+        inviter,
+        profile: invite.profile,
+        servers: invite.servers.iter().map(|x| x.address.clone()).collect(),
+        server: invite.server,
+        cipher_mode: invite.cipher_mode,
+        has_password: invite.password.is_some(),
+        password_required: invite.password_required,
+        expires_at: invite.expires_at,
+    })
+}
+
+/// 取出启动时或运行中通过深度链接打开的邀请
+#[tauri::command]
+pub(crate) fn take_pending_invite(pending: State<'_, PendingInvite>) -> Option<String> {
+    pending.0.lock().ok().and_then(|mut x| x.take())
+}
+
+/// 接受邀请，创建配置档案并切换，返回是否需要重连才能生效
+///
+/// `server`和`inviter`为用户在预览中确认过的服务器和邀请者指纹，与邀请不符时拒绝
+#[tauri::command]
+pub(crate) fn join_invite(
+    app: AppHandle,
+    invite: String,
+    password: Option<String>,
+    server: String,
+    inviter: String,
+    status: State<'_, Mutex<Status>>,
+) -> Result<bool, String> {
+    let invite = Invite::decode(&invite).map_err(|e| e.to_string())?;
+    invite
+        .check_confirmed(&server, &inviter)
+        .map_err(|e| e.to_string())?;
+    match status.lock() {
+        Ok(mut status) => {
+            let profile = invite
+                .save_profile(&app, &mut status, password)
+                .map_err(|e| e.to_string())?;
+            apply_config(&app, &mut status, profile.config).map_err(|e| e.to_string())
+        }
+        Err(e) => {
+            error!("Failed to write status: {}", e);
+            Err(e.to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn invite() -> Invite {
+        let config = Config {
+            server_address_str: "vnt.example.com:29872".to_string(),
+            token: "token".to_string(),
+            ..Default::default()
+        };
+        Invite::from_config(&config, "home".to_string(), false, Some(3600))
+    }
+
+    #[test]
+    fn round_trip() {
+        let key = SigningKey::from_bytes(&[7u8; 32]);
+        let code = invite().encode(&key).unwrap();
+        let decoded = Invite::decode(&code).unwrap();
+        assert_eq!(decoded.server, "vnt.example.com:29872");
+        assert_eq!(
+            decoded.fingerprint().unwrap(),
+            fingerprint(&key.verifying_key())
+        );
+        let uri = format!("{}?invite={}", INVITE_URI_PREFIX, code);
+        assert_eq!(Invite::decode(&uri).unwrap().token, "token");
+    }
+
+    #[test]
+    fn rejects_tampered_payload() {
+        let key = SigningKey::from_bytes(&[7u8; 32]);
+        let code = invite().encode(&key).unwrap();
+        let (payload, signature) = code.split_once('.').unwrap();
+        let mut content: serde_json::Value =
+            serde_json::from_slice(&URL_SAFE_NO_PAD.decode(payload).unwrap()).unwrap();
+        content["s"] = "attacker.example.com:29872".into();
+        let payload = URL_SAFE_NO_PAD.encode(serde_json::to_vec(&content).unwrap());
+        assert!(Invite::decode(&format!("{}.{}", payload, signature)).is_err());
+    }
+
+    #[test]
+    fn re_signed_invite_has_another_inviter() {
+        let key = SigningKey::from_bytes(&[7u8; 32]);
+        let original = Invite::decode(&invite().encode(&key).unwrap()).unwrap();
+        let mut forged = invite();
+        forged.server = "attacker.example.com:29872".to_string();
+        let forged = forged
+            .encode(&SigningKey::from_bytes(&[9u8; 32]))
+            .and_then(|x| Invite::decode(&x))
+            .unwrap();
+        let confirmed = original.fingerprint().unwrap();
+        assert!(original
+            .check_confirmed("vnt.example.com:29872", &confirmed)
+            .is_ok());
+        assert!(forged.check_confirmed(&forged.server, &confirmed).is_err());
+    }
+
+    #[test]
+    fn joined_profile_uses_inviter_servers() {
+        let key = SigningKey::from_bytes(&[7u8; 32]);
+        let local = Config {
+            servers: vec![ServerEntry {
+                address: "local.example.com:29872".to_string(),
+                priority: 0,
+            }],
+            ..Default::default()
+        };
+        let decoded = Invite::decode(&invite().encode(&key).unwrap()).unwrap();
+        let profile = decoded.to_profile(&local, None);
+        assert!(profile.config.servers.is_empty());
+        assert_eq!(
+            profile.config.server_candidates()[0].address,
+            "vnt.example.com:29872"
+        );
+        // 邀请者使用服务器列表时随邀请一起签发
+        let servers = vec![
+            ServerEntry {
+                address: "backup.example.com:29872".to_string(),
+                priority: 1,
+            },
+            ServerEntry {
+                address: "main.example.com:29872".to_string(),
+                priority: 0,
+            },
+        ];
+        let inviter = Config {
+            server_address_str: String::new(),
+            token: "token".to_string(),
+            servers: servers.clone(),
+            ..Default::default()
+        };
+        let code = Invite::from_config(&inviter, "home".to_string(), false, None)
+            .encode(&key)
+            .unwrap();
+        let decoded = Invite::decode(&code).unwrap();
+        assert_eq!(decoded.server, "main.example.com:29872");
+        let profile = decoded.to_profile(&local, None);
+        assert_eq!(profile.config.servers, servers);
+        profile.config.validate_servers().unwrap();
+    }
+
+    #[test]
+    fn checks_omitted_password() {
+        let key = SigningKey::from_bytes(&[7u8; 32]);
+        let config = Config {
+            server_address_str: "vnt.example.com:29872".to_string(),
+            token: "token".to_string(),
+            password: Some("password".to_string()),
+            ..Default::default()
+        };
+        let invite = Invite::from_config(&config, "home".to_string(), false, None);
+        assert!(invite.password.is_none());
+        let decoded = Invite::decode(&invite.encode(&key).unwrap()).unwrap();
+        assert!(decoded.check_password(None).is_err());
+        assert!(decoded.check_password(Some("")).is_err());
+        assert!(decoded.check_password(Some("wrong")).is_err());
+        decoded.check_password(Some("password")).unwrap();
+        // 包含密码的邀请不需要输入
+        let included = Invite::from_config(&config, "home".to_string(), true, None);
+        assert!(included.password_check.is_none());
+        included.check_password(None).unwrap();
+    }
+
+    #[test]
+    fn rejects_expired_invite() {
+        let key = SigningKey::from_bytes(&[7u8; 32]);
+        let mut expired = invite();
+        expired.expires_at = Some(1);
+        assert!(Invite::decode(&expired.encode(&key).unwrap()).is_err());
+    }
+}
@@ -0,0 +1,372 @@
+use crate::errors::ProgramError;
+use crate::tools::config_builder::{AdvancedConfig, Config};
+use crate::tools::profile::Profile;
+use crate::tools::updater::UpdateSettings;
+use crate::tools::{write_file_atomic, ExternalFilePosition};
+use log::{error, info};
+use serde_json::{Map, Value};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, State};
+use tauri_plugin_store::StoreExt;
+
+/// 配置版本号所在的键
+pub(crate) const VERSION_KEY: &str = "config_version";
+/// vnt配置所在的键
+pub(crate) const CONFIG_KEY: &str = "vnt_config";
+/// 配置档案所在的键
+pub(crate) const PROFILES_KEY: &str = "profiles";
+/// 受监管工具定义所在的键
+pub(crate) const SUPERVISED_KEY: &str = "supervised_tools";
+/// 工具目录清单来源所在的键
+pub(crate) const CATALOG_SOURCE_KEY: &str = "catalog_source";
+/// 更新设置所在的键
+pub(crate) const UPDATE_KEY: &str = "update";
+
+type Migration = fn(&mut Map<String, Value>) -> Result<(), ProgramError>;
+
+/// 按顺序排列的迁移步骤，第n项将版本n迁移到版本n+1
+const MIGRATIONS: &[Migration] = &[
+    migrate_v0_to_v1,
+    migrate_v1_to_v2,
+    migrate_v2_to_v3,
+    migrate_v3_to_v4,
+    migrate_v4_to_v5,
+];
+
+/// 当前配置版本
+pub(crate) const CURRENT_VERSION: u64 = MIGRATIONS.len() as u64;
+
+/// v0 -> v1: 补全高级配置
+fn migrate_v0_to_v1(root: &mut Map<String, Value>) -> Result<(), ProgramError> {
+    if let Some(Value::Object(config)) = root.get_mut(CONFIG_KEY) {
+        if !config.contains_key("advanced") {
+            let advanced = serde_json::to_value(AdvancedConfig::default())
+                .map_err(|e| ProgramError::ConfigParseFailed(e.to_string()))?;
+            config.insert("advanced".to_string(), advanced);
+        }
+    }
+    Ok(())
+}
+
+/// v1 -> v2: 新增配置档案列表
+fn migrate_v1_to_v2(root: &mut Map<String, Value>) -> Result<(), ProgramError> {
+    if !root.contains_key(PROFILES_KEY) {
+        root.insert(PROFILES_KEY.to_string(), Value::Array(vec![]));
+    }
+    Ok(())
+}
+
+/// v2 -> v3: 新增受监管工具列表
+fn migrate_v2_to_v3(root: &mut Map<String, Value>) -> Result<(), ProgramError> {
+    if !root.contains_key(SUPERVISED_KEY) {
+        root.insert(SUPERVISED_KEY.to_string(), Value::Array(vec![]));
+    }
+    Ok(())
+}
+
+/// v3 -> v4: 新增工具目录清单来源，默认不从外部加载
+fn migrate_v3_to_v4(root: &mut Map<String, Value>) -> Result<(), ProgramError> {
+    if !root.contains_key(CATALOG_SOURCE_KEY) {
+        root.insert(CATALOG_SOURCE_KEY.to_string(), Value::Null);
+    }
+    Ok(())
+}
+
+/// v4 -> v5: 新增更新设置，默认使用稳定通道且不自动安装
+fn migrate_v4_to_v5(root: &mut Map<String, Value>) -> Result<(), ProgramError> {
+    if !root.contains_key(UPDATE_KEY) {
+        let settings = serde_json::to_value(UpdateSettings::default())
+            .map_err(|e| ProgramError::ConfigParseFailed(e.to_string()))?;
+        root.insert(UPDATE_KEY.to_string(), settings);
+    }
+    Ok(())
+}
+
+/// 读取配置版本号，没有版本号的旧配置视为v0
+fn read_version(root: &Map<String, Value>) -> Result<u64, ProgramError> {
+    match root.get(VERSION_KEY) {
+        None => Ok(0),
+        Some(version) => version.as_u64().ok_or_else(|| {
+            ProgramError::ConfigParseFailed(format!("invalid {}: {}", VERSION_KEY, version))
+        }),
+    }
+}
+
+/// 将配置迁移到当前版本，返回迁移前的版本号
+pub(crate) fn migrate(root: &mut Map<String, Value>) -> Result<u64, ProgramError> {
+    let version = read_version(root)?;
+    if version > CURRENT_VERSION {
+        return Err(ProgramError::ConfigParseFailed(format!(
+            "config version {} is newer than supported version {}",
+            version, CURRENT_VERSION
+        )));
+    }
+    for (step, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        migration(root)?;
+        info!("config migrated from v{} to v{}", step, step + 1);
+    }
+    root.insert(VERSION_KEY.to_string(), Value::from(CURRENT_VERSION));
+    Ok(version)
+}
+
+/// 新建配置文件的内容，各项均为默认值
+fn default_root() -> Result<Map<String, Value>, ProgramError> {
+    let mut root = Map::new();
+    let config = serde_json::to_value(Config::default())
+        .map_err(|e| ProgramError::ConfigParseFailed(e.to_string()))?;
+    root.insert(CONFIG_KEY.to_string(), config);
+    // 从v0开始迁移，由各迁移步骤补全默认值
+    migrate(&mut root)?;
+    Ok(root)
+}
+
+/// 写入配置文件
+fn write_config_file(path: &Path, root: &Map<String, Value>) -> Result<(), ProgramError> {
+    let content = serde_json::to_vec_pretty(root)
+        .map_err(|e| ProgramError::ConfigParseFailed(e.to_string()))?;
+    write_file_atomic(path, &content).map_err(|e| {
+        error!("Failed to write config file: {}", e);
+        ProgramError::WriteFailed(path.to_string_lossy().to_string())
+    })
+}
+
+/// 准备配置文件：不存在时新建，旧版本先备份再迁移
+pub(crate) fn prepare_config_file(path: &Path) -> Result<(), ProgramError> {
+    let content = if path.exists() {
+        std::fs::read_to_string(path).map_err(|e| ProgramError::ReadFailed(e.to_string()))?
+    } else {
+        String::new()
+    };
+    // 新建配置文件
+    if content.trim().is_empty() {
+        return write_config_file(path, &default_root()?);
+    }
+    let mut root = serde_json::from_str::<Map<String, Value>>(&content)
+        .map_err(|e| ProgramError::ConfigParseFailed(e.to_string()))?;
+    let version = read_version(&root)?;
+    if version == CURRENT_VERSION {
+        return Ok(());
+    }
+    // 迁移前备份原文件
+    let backup = ExternalFilePosition::ConfigBackup(version).to_string();
+    if let Err(e) = std::fs::copy(path, &backup) {
+        error!("Failed to backup config file: {}", e);
+        return Err(ProgramError::WriteFailed(backup));
+    }
+    info!("config backed up to {}", backup);
+    migrate(&mut root)?;
+    write_config_file(path, &root)
+}
+
+/// 启动时配置文件无法读取的原因
+///
+/// 存在时不写入配置文件，避免用默认值覆盖原有的token、服务器和设备id，由用户修复文件后重启或确认重置
+#[derive(Default)]
+pub(crate) struct ConfigLoadError(pub(crate) Mutex<Option<String>>);
+
+impl ConfigLoadError {
+    /// 配置文件无法读取时返回错误
+    pub(crate) fn check(&self) -> Result<(), ProgramError> {
+        match &*self.0.lock().unwrap() {
+            Some(e) => Err(ProgramError::ConfigUnavailable(e.clone())),
+            None => Ok(()),
+        }
+    }
+}
+
+/// 读取已迁移到当前版本的配置和档案
+pub(crate) fn load_config(app: &AppHandle) -> Result<(Config, Vec<Profile>), ProgramError> {
+    let path = ExternalFilePosition::Config.to_string();
+    let store = app
+        .store(&path)
+        .map_err(|_| ProgramError::MissingFile(path))?;
+    let config = serde_json::from_value::<Config>(store.get(CONFIG_KEY).unwrap_or_default())
+        .map_err(|e| ProgramError::ConfigParseFailed(format!("{}: {}", CONFIG_KEY, e)))?;
+    let profiles = serde_json::from_value::<Vec<Profile>>(
+        store.get(PROFILES_KEY).unwrap_or(Value::Array(vec![])),
+    )
+    .map_err(|e| ProgramError::ConfigParseFailed(format!("{}: {}", PROFILES_KEY, e)))?;
+    Ok((config, profiles))
+}
+
+/// 备份无法解析的配置文件，返回备份路径
+fn backup_broken_config(path: &Path) -> Result<String, ProgramError> {
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let backup = ExternalFilePosition::ConfigBroken(time).to_string();
+    if let Err(e) = std::fs::copy(path, &backup) {
+        error!("Failed to backup broken config file: {}", e);
+        return Err(ProgramError::WriteFailed(backup));
+    }
+    info!("broken config backed up to {}", backup);
+    Ok(backup)
+}
+
+/// 备份无法解析的配置文件后重新生成默认配置，返回备份路径
+pub(crate) fn reset_config_file(path: &Path) -> Result<String, ProgramError> {
+    let backup = backup_broken_config(path)?;
+    write_config_file(path, &default_root()?)?;
+    Ok(backup)
+}
+
+/// 启动时配置文件无法读取的原因，可以读取时为空
+#[tauri::command]
+pub(crate) fn config_load_error(error: State<ConfigLoadError>) -> Option<String> {
+    error.0.lock().unwrap().clone()
+}
+
+/// 用户确认后备份无法读取的配置文件并恢复默认配置，随后重启以重新加载
+#[tauri::command]
+pub(crate) fn reset_broken_config(
+    app: AppHandle,
+    error: State<ConfigLoadError>,
+) -> Result<(), String> {
+    if error.check().is_ok() {
+        return Err(ProgramError::InvalidConfig("config file is readable".to_string()).to_string());
+    }
+    let path = ExternalFilePosition::Config.to_string();
+    let backup = reset_config_file(Path::new(&path)).map_err(|e| e.to_string())?;
+    info!("broken config reset, backup at {}", backup);
+    app.restart()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::supervisor::SupervisedTool;
+    use crate::tools::updater::UpdateChannel;
+    use serde_json::json;
+
+    /// 第一版的config.json，只有vnt配置
+    fn v0_file() -> Value {
+        json!({
+            "vnt_config": {
+                "token": "token",
+                "device_id": "device",
+                "name": "name",
+                "server_address_str": "vnt.example.com:29872",
+                "stun_server": null
+            }
+        })
+    }
+
+    fn temp_file(content: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("config-{}.json", uuid::Uuid::new_v4()));
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+
+    /// 检查迁移后的内容能被当前版本读取
+    fn assert_current(root: &Map<String, Value>) {
+        assert_eq!(root[VERSION_KEY], Value::from(CURRENT_VERSION));
+        let config = serde_json::from_value::<Config>(root[CONFIG_KEY].clone()).unwrap();
+        assert_eq!(config.token, "token");
+        serde_json::from_value::<Vec<Profile>>(root[PROFILES_KEY].clone()).unwrap();
+        serde_json::from_value::<Vec<SupervisedTool>>(root[SUPERVISED_KEY].clone()).unwrap();
+        assert!(root[CATALOG_SOURCE_KEY].is_null());
+        let update = serde_json::from_value::<UpdateSettings>(root[UPDATE_KEY].clone()).unwrap();
+        assert_eq!(update.channel, UpdateChannel::Stable);
+        assert!(!update.auto_install);
+    }
+
+    #[test]
+    fn migrates_v0_to_current() {
+        let Value::Object(mut root) = v0_file() else {
+            unreachable!()
+        };
+        assert_eq!(migrate(&mut root).unwrap(), 0);
+        assert!(root[CONFIG_KEY]["advanced"].is_object());
+        assert_current(&root);
+    }
+
+    #[test]
+    fn migrates_each_version_to_current() {
+        let Value::Object(mut root) = v0_file() else {
+            unreachable!()
+        };
+        // 逐版本生成旧文件，再从该版本迁移到当前版本
+        for (version, migration) in MIGRATIONS.iter().enumerate() {
+            let mut old = root.clone();
+            if version > 0 {
+                old.insert(VERSION_KEY.to_string(), Value::from(version as u64));
+            }
+            assert_eq!(migrate(&mut old).unwrap(), version as u64);
+            assert_current(&old);
+            migration(&mut root).unwrap();
+        }
+    }
+
+    #[test]
+    fn keeps_existing_values() {
+        let Value::Object(mut root) = v0_file() else {
+            unreachable!()
+        };
+        root.insert(VERSION_KEY.to_string(), Value::from(4));
+        root.insert(PROFILES_KEY.to_string(), json!([]));
+        root.insert(SUPERVISED_KEY.to_string(), json!([]));
+        root.insert(UPDATE_KEY.to_string(), json!({"channel": "beta"}));
+        root.insert(
+            CATALOG_SOURCE_KEY.to_string(),
+            json!({"url": "https://example.com/catalog.json"}),
+        );
+        migrate(&mut root).unwrap();
+        assert_eq!(root[UPDATE_KEY]["channel"], "beta");
+        assert_eq!(
+            root[CATALOG_SOURCE_KEY]["url"],
+            "https://example.com/catalog.json"
+        );
+    }
+
+    #[test]
+    fn rejects_newer_version() {
+        let Value::Object(mut root) = v0_file() else {
+            unreachable!()
+        };
+        root.insert(VERSION_KEY.to_string(), Value::from(CURRENT_VERSION + 1));
+        assert!(matches!(
+            migrate(&mut root),
+            Err(ProgramError::ConfigParseFailed(_))
+        ));
+    }
+
+    #[test]
+    fn creates_file_with_all_defaults() {
+        let path = temp_file("");
+        prepare_config_file(&path).unwrap();
+        let root =
+            serde_json::from_str::<Map<String, Value>>(&std::fs::read_to_string(&path).unwrap())
+                .unwrap();
+        for key in [PROFILES_KEY, SUPERVISED_KEY, CATALOG_SOURCE_KEY, UPDATE_KEY] {
+            assert!(root.contains_key(key), "missing {}", key);
+        }
+        assert_eq!(root[VERSION_KEY], Value::from(CURRENT_VERSION));
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn rejects_broken_file() {
+        let path = temp_file("{\"vnt_config\": ");
+        assert!(matches!(
+            prepare_config_file(&path),
+            Err(ProgramError::ConfigParseFailed(_))
+        ));
+        // 原文件保持不变，由调用方决定备份和重置
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "{\"vnt_config\": ");
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn load_error_blocks_writes() {
+        let error = ConfigLoadError::default();
+        error.check().unwrap();
+        *error.0.lock().unwrap() = Some("broken".to_string());
+        assert!(matches!(
+            error.check(),
+            Err(ProgramError::ConfigUnavailable(e)) if e == "broken"
+        ));
+    }
+}
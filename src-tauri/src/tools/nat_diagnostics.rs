@@ -0,0 +1,359 @@
+use crate::errors::ProgramError;
+use crate::tools::port_mapping::{port_mapping_status, PortMappingStatus};
+use crate::tools::secret::redact;
+use crate::tools::stun::{binding_request, resolve_stun, BindingResponse, StunCache};
+use crate::tools::Status;
+use log::{error, info};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::sync::Mutex;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Emitter, Manager};
+
+/// 端口变化量不超过该值时视为可预测
+const PREDICTABLE_PORT_DELTA: i32 = 10;
+
+/// RFC 5780 中的映射/过滤行为
+#[derive(serde::Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum NatBehavior {
+    /// 无nat
+    NoNat,
+    EndpointIndependent,
+    AddressDependent,
+    AddressAndPortDependent,
+    Unknown,
+}
+
+/// 单个stun服务器观察到的地址
+#[derive(serde::Serialize, Clone, Debug)]
+pub(crate) struct StunObservation {
+    server: String,
+    mapped_address: Option<String>,
+    /// 服务器是否支持RFC 5780的备用地址
+    other_address: Option<String>,
+    latency_ms: Option<u64>,
+    error: Option<String>,
+}
+
+/// 本机nat诊断报告
+#[derive(serde::Serialize, Clone, Debug)]
+pub(crate) struct NatReport {
+    /// 生成时间，unix时间戳(秒)
+    generated_at: u64,
+    udp_blocked: bool,
+    /// RFC 3489 的经典nat类型
+    nat_type: String,
+    mapping: NatBehavior,
+    filtering: NatBehavior,
+    local_address: Option<String>,
+    public_addresses: Vec<String>,
+    observations: Vec<StunObservation>,
+    /// 对不同目标的映射端口是否可预测
+    port_predictable: Option<bool>,
+    port_delta: Option<i32>,
+    ipv6_available: bool,
+    ipv6_address: Option<String>,
+    /// vnt自身检测到的nat类型
+    vnt_nat_type: Option<String>,
+    /// 路由器端口映射状态
+    port_mapping: Option<PortMappingStatus>,
+}
+
+/// 最近一次的诊断报告
+#[derive(Default)]
+pub(crate) struct NatReportCache(pub(crate) Mutex<Option<NatReport>>);
+
+/// 诊断导出内容
+#[derive(serde::Serialize)]
+struct DiagnosticsExport {
+    app_version: String,
+    os: String,
+    nat: NatReport,
+    stun: Vec<crate::tools::stun::StunProbe>,
+    config: crate::tools::config_builder::Config,
+    running: bool,
+    active_server: Option<String>,
+}
+
+/// 获取访问外网时使用的本机地址，不会真正发送数据
+pub(crate) fn local_ip(ipv6: bool) -> Option<IpAddr> {
+    let (bind, target): (SocketAddr, SocketAddr) = if ipv6 {
+        (
+            (Ipv6Addr::UNSPECIFIED, 0).into(),
+            (
+                Ipv6Addr::new(0x2001, 0x4860, 0x4860, 0, 0, 0, 0, 0x8888),
+                53,
+            )
+                .into(),
+        )
+    } else {
+        (
+            (Ipv4Addr::UNSPECIFIED, 0).into(),
+            (Ipv4Addr::new(8, 8, 8, 8), 53).into(),
+        )
+    };
+    let socket = UdpSocket::bind(bind).ok()?;
+    socket.connect(target).ok()?;
+    socket.local_addr().ok().map(|x| x.ip())
+}
+
+/// 判断ipv6是否为全球单播地址
+fn is_global_ipv6(ip: &Ipv6Addr) -> bool {
+    (ip.segments()[0] & 0xe000) == 0x2000
+}
+
+fn classic_type(mapping: NatBehavior, filtering: NatBehavior, udp_blocked: bool) -> &'static str {
+    if udp_blocked {
+        return "udp_blocked";
+    }
+    match (mapping, filtering) {
+        (NatBehavior::NoNat, _) => "open_internet",
+        (NatBehavior::EndpointIndependent, NatBehavior::EndpointIndependent) => "full_cone",
+        (NatBehavior::EndpointIndependent, NatBehavior::AddressDependent) => "restricted_cone",
+        (NatBehavior::EndpointIndependent, NatBehavior::AddressAndPortDependent) => {
+            "port_restricted_cone"
+        }
+        (NatBehavior::AddressDependent | NatBehavior::AddressAndPortDependent, _) => "symmetric",
+        _ => "unknown",
+    }
+}
+
+/// RFC 5780 映射行为测试，需要服务器支持备用地址
+fn mapping_test(socket: &UdpSocket, server: SocketAddr, first: &BindingResponse) -> NatBehavior {
+    let Some(other) = first.other else {
+        return NatBehavior::Unknown;
+    };
+    let Ok(second) = binding_request(
+        socket,
+        SocketAddr::new(other.ip(), server.port()),
+        false,
+        false,
+    ) else {
+        return NatBehavior::Unknown;
+    };
+    if second.mapped == first.mapped {
+        return NatBehavior::EndpointIndependent;
+    }
+    match binding_request(socket, other, false, false) {
+        Ok(third) if third.mapped == second.mapped => NatBehavior::AddressDependent,
+        Ok(_) => NatBehavior::AddressAndPortDependent,
+        Err(_) => NatBehavior::Unknown,
+    }
+}
+
+/// RFC 5780 过滤行为测试，需要服务器支持备用地址
+///
+/// 使用新socket，保证nat上只有到主地址的映射，否则映射测试向备用地址发出的请求会放行备用地址的响应
+fn filtering_test(server: SocketAddr) -> NatBehavior {
+    let Ok(socket) = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)) else {
+        return NatBehavior::Unknown;
+    };
+    match binding_request(&socket, server, false, false) {
+        Ok(first) if first.other.is_some() => {}
+        _ => return NatBehavior::Unknown,
+    }
+    if binding_request(&socket, server, true, true).is_ok() {
+        return NatBehavior::EndpointIndependent;
+    }
+    if binding_request(&socket, server, false, true).is_ok() {
+        NatBehavior::AddressDependent
+    } else {
+        NatBehavior::AddressAndPortDependent
+    }
+}
+
+/// 生成nat诊断报告，耗时较长，不能在持有锁时调用
+pub(crate) fn build_report(servers: &[String], vnt_nat_type: Option<String>) -> NatReport {
+    let local = local_ip(false);
+    let mut observations = vec![];
+    let mut responses = vec![];
+    // 使用同一个socket依次请求，以便比较映射端口
+    if let Ok(socket) = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)) {
+        for server in servers {
+            let mut observation = StunObservation {
+                server: server.clone(),
+                mapped_address: None,
+                other_address: None,
+                latency_ms: None,
+                error: None,
+            };
+            let start = Instant::now();
+            match resolve_stun(server, false)
+                .and_then(|addr| binding_request(&socket, addr, false, false).map(|x| (addr, x)))
+            {
+                Ok((addr, response)) => {
+                    observation.mapped_address = Some(response.mapped.to_string());
+                    observation.other_address = response.other.map(|x| x.to_string());
+                    observation.latency_ms = Some(start.elapsed().as_millis() as u64);
+                    responses.push((addr, response));
+                }
+                Err(e) => observation.error = Some(e.to_string()),
+            }
+            observations.push(observation);
+        }
+    }
+    let udp_blocked = responses.is_empty();
+    let mut public_addresses = responses
+        .iter()
+        .map(|(_, x)| x.mapped.to_string())
+        .collect::<Vec<_>>();
+    public_addresses.dedup();
+    let (mapping, filtering) = match responses.first() {
+        None => (NatBehavior::Unknown, NatBehavior::Unknown),
+        Some((_, first)) if Some(first.mapped.ip()) == local => {
+            (NatBehavior::NoNat, NatBehavior::NoNat)
+        }
+        Some(_) => {
+            // 优先使用支持备用地址的服务器，映射测试使用新socket避免之前的请求影响结果
+            let rfc5780 = responses.iter().find(|(_, x)| x.other.is_some());
+            let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).ok();
+            let first = match (rfc5780, &socket) {
+                (Some((addr, _)), Some(socket)) => binding_request(socket, *addr, false, false)
+                    .ok()
+                    .map(|x| (*addr, x)),
+                _ => None,
+            };
+            match (first, &socket) {
+                (Some((addr, first)), Some(socket)) => {
+                    (mapping_test(socket, addr, &first), filtering_test(addr))
+                }
+                // 不支持RFC 5780时只能比较不同服务器看到的映射地址
+                _ => {
+                    let same = responses.windows(2).all(|x| x[0].1.mapped == x[1].1.mapped);
+                    let mapping = if responses.len() < 2 {
+                        NatBehavior::Unknown
+                    } else if same {
+                        NatBehavior::EndpointIndependent
+                    } else {
+                        NatBehavior::AddressAndPortDependent
+                    };
+                    (mapping, NatBehavior::Unknown)
+                }
+            }
+        }
+    };
+    // 端口变化量恒定时认为可预测
+    let deltas = responses
+        .windows(2)
+        .map(|x| x[1].1.mapped.port() as i32 - x[0].1.mapped.port() as i32)
+        .collect::<Vec<_>>();
+    let (port_predictable, port_delta) = match deltas.first() {
+        None => (None, None),
+        Some(&delta) => (
+            Some(deltas.iter().all(|&x| x == delta) && delta.abs() <= PREDICTABLE_PORT_DELTA),
+            Some(delta),
+        ),
+    };
+    // ipv6优先使用stun确认，否则检查是否存在全球单播地址
+    let ipv6_mapped = UdpSocket::bind((Ipv6Addr::UNSPECIFIED, 0))
+        .ok()
+        .and_then(|socket| {
+            servers.iter().find_map(|server| {
+                resolve_stun(server, true)
+                    .and_then(|addr| binding_request(&socket, addr, false, false))
+                    .ok()
+            })
+        })
+        .map(|x| x.mapped.ip());
+    let ipv6_address = ipv6_mapped
+        .or_else(|| local_ip(true).filter(|ip| matches!(ip, IpAddr::V6(ip) if is_global_ipv6(ip))));
+    NatReport {
+        generated_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|x| x.as_secs())
+            .unwrap_or(0),
+        udp_blocked,
+        nat_type: classic_type(mapping, filtering, udp_blocked).to_string(),
+        mapping,
+        filtering,
+        local_address: local.map(|x| x.to_string()),
+        public_addresses,
+        observations,
+        port_predictable,
+        port_delta,
+        ipv6_available: ipv6_address.is_some(),
+        ipv6_address: ipv6_address.map(|x| x.to_string()),
+        vnt_nat_type,
+        port_mapping: None,
+    }
+}
+
+/// 获取诊断报告，没有缓存或要求刷新时重新生成
+pub(crate) fn nat_report(app: &AppHandle, refresh: bool) -> Result<NatReport, ProgramError> {
+    let cache = app.state::<NatReportCache>();
+    if !refresh {
+        if let Some(report) = cache.0.lock().ok().and_then(|x| x.clone()) {
+            return Ok(report);
+        }
+    }
+    let (servers, vnt_nat_type) = match app.state::<Mutex<Status>>().lock() {
+        Ok(status) => (
+            status.config.stun_candidates(),
+            status
+                .vnt
+                .as_ref()
+                .map(|vnt| match vnt.nat_info().nat_type {
+                    vnt::channel::punch::NatType::Cone => "圆锥形".to_string(),
+                    vnt::channel::punch::NatType::Symmetric => "对称型".to_string(),
+                }),
+        ),
+        Err(e) => return Err(ProgramError::ReadFailed(e.to_string())),
+    };
+    let mut report = build_report(&servers, vnt_nat_type);
+    report.port_mapping = port_mapping_status(app);
+    info!("nat report: {:?}", report);
+    if let Ok(mut cache) = cache.0.lock() {
+        cache.replace(report.clone());
+    }
+    if let Err(e) = app.emit("lers://nat/report", report.clone()) {
+        error!("Failed to emit nat report: {}", e);
+    }
+    Ok(report)
+}
+
+/// 获取nat诊断报告，探测耗时较长，在阻塞线程中执行
+#[tauri::command]
+pub(crate) async fn get_nat_report(app: AppHandle, refresh: bool) -> Result<NatReport, String> {
+    tauri::async_runtime::spawn_blocking(move || nat_report(&app, refresh))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())
+}
+
+/// 导出诊断信息，配置中的密钥已脱敏
+#[tauri::command]
+pub(crate) async fn export_diagnostics(app: AppHandle, path: String) -> Result<(), String> {
+    let handle = app.clone();
+    let nat = tauri::async_runtime::spawn_blocking(move || nat_report(&handle, false))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())?;
+    let stun = app
+        .state::<StunCache>()
+        .0
+        .lock()
+        .ok()
+        .and_then(|x| x.as_ref().map(|(_, probes)| probes.clone()))
+        .unwrap_or_default();
+    let export = match app.state::<Mutex<Status>>().lock() {
+        Ok(status) => {
+            let mut config = status.config.clone();
+            redact(&mut config);
+            DiagnosticsExport {
+                app_version: app.package_info().version.to_string(),
+                os: format!("{} {}", std::env::consts::OS, std::env::consts::ARCH),
+                nat,
+                stun,
+                config,
+                running: status.vnt.is_some(),
+                active_server: status.failover.as_ref().and_then(|x| x.active()).cloned(),
+            }
+        }
+        Err(e) => return Err(e.to_string()),
+    };
+    let content = serde_json::to_vec_pretty(&export).map_err(|e| e.to_string())?;
+    std::fs::write(&path, content).map_err(|e| {
+        error!("Failed to export diagnostics: {}", e);
+        ProgramError::WriteFailed(path).to_string()
+    })
+}
@@ -0,0 +1,311 @@
+//! 子进程输出记录
+//!
+//! 每个子进程保留最近若干行输出，可选同时写入按大小轮换的日志文件。
+//! 重新附加时先回放缓冲区，再继续转发实时输出。
+
+use crate::tools::command::{CommandEvent, EventSink};
+use crate::tools::ExternalFilePosition;
+use log::warn;
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::ipc::Channel;
+
+/// 默认缓冲行数
+const DEFAULT_BUFFER_LINES: usize = 1000;
+/// 缓冲行数上限
+const MAX_BUFFER_LINES: usize = 10000;
+/// 日志文件超过该大小后轮换
+const LOG_FILE_LIMIT: u64 = 1024 * 1024;
+/// 保留的历史日志文件数
+const LOG_FILE_KEEP: u32 = 3;
+
+struct LogFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+}
+
+impl LogFile {
+    fn open(tool: &str) -> std::io::Result<Self> {
+        let path = PathBuf::from(ExternalFilePosition::ToolLog(tool.to_string()).to_string());
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(LogFile { path, file, size })
+    }
+
+    fn rotated(&self, index: u32) -> PathBuf {
+        let mut path = self.path.as_os_str().to_owned();
+        path.push(format!(".{}", index));
+        PathBuf::from(path)
+    }
+
+    /// tool.log -> tool.log.1 -> ... -> tool.log.N，最旧的被覆盖
+    fn rotate(&mut self) -> std::io::Result<()> {
+        for index in (1..LOG_FILE_KEEP).rev() {
+            let from = self.rotated(index);
+            if from.exists() {
+                std::fs::rename(&from, self.rotated(index + 1))?;
+            }
+        }
+        std::fs::rename(&self.path, self.rotated(1))?;
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+
+    fn write(&mut self, stream: &str, text: &str) -> std::io::Result<()> {
+        if self.size >= LOG_FILE_LIMIT {
+            self.rotate()?;
+        }
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|x| x.as_millis())
+            .unwrap_or(0);
+        let line = format!("{} {} | {}\n", time, stream, text);
+        self.file.write_all(line.as_bytes())?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+
+    /// 伪终端的输出原样写入，保留控制序列
+    fn write_raw(&mut self, data: &[u8]) -> std::io::Result<()> {
+        if self.size >= LOG_FILE_LIMIT {
+            self.rotate()?;
+        }
+        self.file.write_all(data)?;
+        self.size += data.len() as u64;
+        Ok(())
+    }
+}
+
+struct Inner {
+    /// 伪终端模式下每项为一个输出块
+    lines: VecDeque<CommandEvent>,
+    capacity: usize,
+    /// 退出事件，附加到已退出的进程时最后回放
+    terminated: Option<CommandEvent>,
+    attached: Vec<Channel<CommandEvent>>,
+    file: Option<LogFile>,
+}
+
+/// 单个子进程的输出记录
+pub(crate) struct OutputLog {
+    inner: Mutex<Inner>,
+}
+
+impl OutputLog {
+    /// `log_file`为真时额外写入以工具名命名的日志文件
+    pub(crate) fn new(tool: &str, buffer_lines: Option<usize>, log_file: bool) -> Self {
+        let file = if log_file {
+            match LogFile::open(tool) {
+                Ok(file) => Some(file),
+                Err(e) => {
+                    warn!("Failed to open log file of {}: {}", tool, e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        let capacity = buffer_lines
+            .unwrap_or(DEFAULT_BUFFER_LINES)
+            .min(MAX_BUFFER_LINES);
+        OutputLog {
+            inner: Mutex::new(Inner {
+                lines: VecDeque::with_capacity(capacity.min(DEFAULT_BUFFER_LINES)),
+                capacity,
+                terminated: None,
+                attached: vec![],
+                file,
+            }),
+        }
+    }
+
+    /// 记录事件并转发给附加的通道，记录与转发在同一临界区内，附加时不会漏行或重复
+    fn record(&self, event: &CommandEvent) {
+        let mut guard = self.inner.lock().unwrap();
+        let inner = &mut *guard;
+        let written = match (inner.file.as_mut(), event) {
+            (_, CommandEvent::Terminated { .. }) => None,
+            (None, _) => Some(Ok(())),
+            (Some(file), CommandEvent::Stdout(text)) => Some(file.write("stdout", text)),
+            (Some(file), CommandEvent::Error(text)) => Some(file.write("stderr", text)),
+            (Some(file), CommandEvent::Data(data)) => Some(file.write_raw(data)),
+        };
+        match written {
+            Some(result) => {
+                if let Err(e) = result {
+                    warn!("Failed to write log file: {}", e);
+                    inner.file = None;
+                }
+                if inner.capacity > 0 {
+                    if inner.lines.len() >= inner.capacity {
+                        inner.lines.pop_front();
+                    }
+                    inner.lines.push_back(event.clone());
+                }
+            }
+            None => {
+                inner.terminated = Some(event.clone());
+                inner.file = None;
+            }
+        }
+        // 通道发送失败说明前端已关闭，移除该通道
+        inner
+            .attached
+            .retain(|channel| channel.send(event.clone()).is_ok());
+    }
+
+    /// 包装事件接收方，事件先记录再交给原接收方
+    pub(crate) fn wrap(self: &Arc<Self>, sink: EventSink) -> EventSink {
+        let log = self.clone();
+        Arc::new(move |event| {
+            log.record(&event);
+            sink(event);
+        })
+    }
+
+    /// 回放缓冲的输出，进程仍在运行时继续转发实时输出
+    pub(crate) fn attach(&self, channel: Channel<CommandEvent>) {
+        let mut guard = self.inner.lock().unwrap();
+        let inner = &mut *guard;
+        for event in inner.lines.iter().chain(inner.terminated.iter()) {
+            if channel.send(event.clone()).is_err() {
+                return;
+            }
+        }
+        if inner.terminated.is_none() {
+            inner.attached.push(channel);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tauri::ipc::InvokeResponseBody;
+
+    /// 收集通道收到的事件，以JSON表示
+    fn channel() -> (Channel<CommandEvent>, Arc<Mutex<Vec<serde_json::Value>>>) {
+        let events = Arc::new(Mutex::new(vec![]));
+        let received = events.clone();
+        let channel = Channel::new(move |body| {
+            if let InvokeResponseBody::Json(json) = body {
+                received
+                    .lock()
+                    .unwrap()
+                    .push(serde_json::from_str(&json).unwrap());
+            }
+            Ok(())
+        });
+        (channel, events)
+    }
+
+    fn stdout(index: usize) -> CommandEvent {
+        CommandEvent::Stdout(index.to_string())
+    }
+
+    /// 收到的输出行序号
+    fn lines(events: &Mutex<Vec<serde_json::Value>>) -> Vec<usize> {
+        events
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|x| x["event"] == "stdout")
+            .map(|x| x["payload"].as_str().unwrap().parse().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn keeps_latest_lines() {
+        let log = OutputLog::new("tool", Some(3), false);
+        for index in 0..5 {
+            log.record(&stdout(index));
+        }
+        let (channel, events) = channel();
+        log.attach(channel);
+        assert_eq!(lines(&events), vec![2, 3, 4]);
+    }
+
+    #[test]
+    fn limits_capacity() {
+        let inner = |log: OutputLog| log.inner.into_inner().unwrap().capacity;
+        assert_eq!(
+            inner(OutputLog::new("tool", None, false)),
+            DEFAULT_BUFFER_LINES
+        );
+        assert_eq!(
+            inner(OutputLog::new("tool", Some(usize::MAX), false)),
+            MAX_BUFFER_LINES
+        );
+        // 不缓冲时只转发实时输出
+        let log = OutputLog::new("tool", Some(0), false);
+        log.record(&stdout(0));
+        let (channel, events) = channel();
+        log.attach(channel);
+        log.record(&stdout(1));
+        assert_eq!(lines(&events), vec![1]);
+    }
+
+    #[test]
+    fn replays_then_forwards_live_output() {
+        let log = OutputLog::new("tool", None, false);
+        for index in 0..3 {
+            log.record(&stdout(index));
+        }
+        let (channel, events) = channel();
+        log.attach(channel);
+        for index in 3..6 {
+            log.record(&stdout(index));
+        }
+        assert_eq!(lines(&events), (0..6).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn attach_during_output_has_no_gaps() {
+        let log = Arc::new(OutputLog::new("tool", Some(MAX_BUFFER_LINES), false));
+        let sink = log.wrap(Arc::new(|_| {}));
+        let writer = std::thread::spawn(move || {
+            for index in 0..5000 {
+                sink(stdout(index));
+            }
+        });
+        // 输出进行中附加，回放与实时输出之间不能漏行或重复
+        while log.inner.lock().unwrap().lines.len() < 100 {
+            std::thread::yield_now();
+        }
+        let (channel, events) = channel();
+        log.attach(channel);
+        writer.join().unwrap();
+        assert_eq!(lines(&events), (0..5000).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn replays_exit_after_output() {
+        let log = OutputLog::new("tool", None, false);
+        log.record(&stdout(0));
+        log.record(&CommandEvent::Terminated {
+            code: Some(0),
+            signal: None,
+        });
+        let (channel, events) = channel();
+        log.attach(channel);
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0]["payload"], "0");
+        assert_eq!(events[1]["event"], "terminated");
+        assert_eq!(events[1]["payload"]["code"], 0);
+        // 已退出的进程不再保留通道
+        assert!(log.inner.lock().unwrap().attached.is_empty());
+    }
+}
@@ -0,0 +1,651 @@
+use crate::tools::nat_diagnostics::local_ip;
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::OsRng;
+use igd_next::{PortMappingProtocol, SearchOptions};
+use log::{error, info, warn};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, UdpSocket};
+use std::sync::{Arc, Mutex};
+use std::thread::sleep;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Emitter, Manager};
+
+/// 映射租期(秒)，连接期间每半个租期续期一次
+const LEASE_SECS: u32 = 3600;
+const DISCOVERY_TIMEOUT: Duration = Duration::from_secs(3);
+const NAT_PMP_PORT: u16 = 5351;
+const MAPPING_DESCRIPTION: &str = "LightP2P";
+
+/// 映射方式
+#[derive(serde::Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum MappingMethod {
+    UpnpIgd,
+    NatPmp,
+    Pcp,
+}
+
+/// 传输协议
+#[derive(serde::Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum TransportProtocol {
+    Udp,
+    Tcp,
+}
+
+impl From<TransportProtocol> for PortMappingProtocol {
+    fn from(value: TransportProtocol) -> Self {
+        match value {
+            TransportProtocol::Udp => Self::UDP,
+            TransportProtocol::Tcp => Self::TCP,
+        }
+    }
+}
+
+/// 已建立的单个端口映射
+#[derive(serde::Serialize, Clone, Debug)]
+pub(crate) struct MappedPort {
+    protocol: TransportProtocol,
+    internal_port: u16,
+    external_port: u16,
+}
+
+/// 端口映射状态
+#[derive(serde::Serialize, Clone, Debug, Default)]
+pub(crate) struct PortMappingStatus {
+    method: Option<MappingMethod>,
+    gateway: Option<String>,
+    external_ip: Option<String>,
+    mappings: Vec<MappedPort>,
+    /// 最近一次续期时间，unix时间戳(秒)
+    renewed_at: Option<u64>,
+    error: Option<String>,
+}
+
+/// 发现的网关
+enum Gateway {
+    Igd(igd_next::Gateway),
+    /// NAT-PMP和PCP使用同一端口，由method区分
+    Pmp(SocketAddr, MappingMethod),
+}
+
+/// 映射使用的网关，续期时在锁外使用
+struct MappingTarget {
+    gateway: Gateway,
+    local: Ipv4Addr,
+    /// PCP请求的随机nonce，续期和删除时必须与建立映射时相同
+    nonce: [u8; 12],
+}
+
+struct ActiveMapping {
+    session: u64,
+    target: Arc<MappingTarget>,
+    status: PortMappingStatus,
+}
+
+/// 当前端口映射
+#[derive(Default)]
+pub(crate) struct PortMapper {
+    active: Mutex<Option<ActiveMapping>>,
+    /// 每次开始映射时递增，用于结束过期的续期线程
+    session: Mutex<u64>,
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|x| x.as_secs())
+        .unwrap_or(0)
+}
+
+fn pmp_request(gateway: SocketAddr, request: &[u8]) -> std::io::Result<Vec<u8>> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+    socket.connect(gateway)?;
+    // RFC 6886 建议从250ms开始指数退避
+    let mut timeout = Duration::from_millis(250);
+    let mut buf = [0u8; 1100];
+    let deadline = Instant::now() + DISCOVERY_TIMEOUT;
+    while Instant::now() < deadline {
+        socket.send(request)?;
+        socket.set_read_timeout(Some(timeout))?;
+        match socket.recv(&mut buf) {
+            Ok(len) => return Ok(buf[..len].to_vec()),
+            Err(e)
+                if e.kind() == std::io::ErrorKind::WouldBlock
+                    || e.kind() == std::io::ErrorKind::TimedOut =>
+            {
+                timeout *= 2;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    Err(std::io::Error::new(
+        std::io::ErrorKind::TimedOut,
+        "gateway did not respond",
+    ))
+}
+
+/// NAT-PMP 映射，lifetime为0时删除映射
+fn nat_pmp_map(
+    gateway: SocketAddr,
+    protocol: TransportProtocol,
+    internal_port: u16,
+    lifetime: u32,
+) -> std::io::Result<u16> {
+    let opcode = match protocol {
+        TransportProtocol::Udp => 1u8,
+        TransportProtocol::Tcp => 2u8,
+    };
+    let external_port = if lifetime == 0 { 0 } else { internal_port };
+    let mut request = vec![0, opcode, 0, 0];
+    request.extend(internal_port.to_be_bytes());
+    request.extend(external_port.to_be_bytes());
+    request.extend(lifetime.to_be_bytes());
+    let response = pmp_request(gateway, &request)?;
+    if response.len() < 16 || response[1] != 128 + opcode {
+        return Err(std::io::Error::other("invalid NAT-PMP response"));
+    }
+    let result = u16::from_be_bytes([response[2], response[3]]);
+    if result != 0 {
+        return Err(std::io::Error::other(format!(
+            "NAT-PMP result code {}",
+            result
+        )));
+    }
+    Ok(u16::from_be_bytes([response[10], response[11]]))
+}
+
+/// NAT-PMP 获取外网地址，同时用于探测网关是否支持
+fn nat_pmp_external_ip(gateway: SocketAddr) -> std::io::Result<Ipv4Addr> {
+    let response = pmp_request(gateway, &[0, 0])?;
+    if response.len() < 12 || response[0] != 0 || response[1] != 128 {
+        return Err(std::io::Error::other("invalid NAT-PMP response"));
+    }
+    let result = u16::from_be_bytes([response[2], response[3]]);
+    if result != 0 {
+        return Err(std::io::Error::other(format!(
+            "NAT-PMP result code {}",
+            result
+        )));
+    }
+    Ok(Ipv4Addr::new(
+        response[8],
+        response[9],
+        response[10],
+        response[11],
+    ))
+}
+
+/// PCP(RFC 6887) MAP 请求，lifetime为0时删除映射，返回外网端口和地址
+fn pcp_map(
+    gateway: SocketAddr,
+    local: Ipv4Addr,
+    nonce: &[u8; 12],
+    protocol: TransportProtocol,
+    internal_port: u16,
+    lifetime: u32,
+) -> std::io::Result<(u16, IpAddr)> {
+    let mut request = vec![2u8, 1, 0, 0];
+    request.extend(lifetime.to_be_bytes());
+    request.extend(local.to_ipv6_mapped().octets());
+    request.extend(nonce);
+    request.push(match protocol {
+        TransportProtocol::Udp => 17,
+        TransportProtocol::Tcp => 6,
+    });
+    request.extend([0u8; 3]);
+    request.extend(internal_port.to_be_bytes());
+    request.extend(if lifetime == 0 { 0 } else { internal_port }.to_be_bytes());
+    request.extend(Ipv6Addr::UNSPECIFIED.octets());
+    let response = pmp_request(gateway, &request)?;
+    if response.len() < 60 || response[0] != 2 || response[1] != 0x81 {
+        return Err(std::io::Error::other("invalid PCP response"));
+    }
+    if response[3] != 0 {
+        return Err(std::io::Error::other(format!(
+            "PCP result code {}",
+            response[3]
+        )));
+    }
+    if response[24..36] != nonce[..] {
+        return Err(std::io::Error::other("PCP nonce mismatch"));
+    }
+    let external_port = u16::from_be_bytes([response[42], response[43]]);
+    let mut ip = [0u8; 16];
+    ip.copy_from_slice(&response[44..60]);
+    let ip = Ipv6Addr::from(ip);
+    let ip = ip
+        .to_ipv4_mapped()
+        .map(IpAddr::V4)
+        .unwrap_or(IpAddr::V6(ip));
+    Ok((external_port, ip))
+}
+
+/// PCP ANNOUNCE 请求，用于探测网关是否支持PCP，仅支持NAT-PMP的网关会返回版本错误
+fn pcp_announce(gateway: SocketAddr, local: Ipv4Addr) -> std::io::Result<()> {
+    let mut request = vec![2u8, 0, 0, 0];
+    request.extend(0u32.to_be_bytes());
+    request.extend(local.to_ipv6_mapped().octets());
+    let response = pmp_request(gateway, &request)?;
+    if response.len() < 24 || response[0] != 2 || response[1] != 0x80 || response[3] != 0 {
+        return Err(std::io::Error::other("PCP not supported"));
+    }
+    Ok(())
+}
+
+/// 依次尝试 UPnP-IGD、PCP 和 NAT-PMP 发现网关
+fn discover(local: Ipv4Addr) -> Result<(Gateway, Option<IpAddr>), String> {
+    let options = SearchOptions {
+        bind_addr: SocketAddr::V4(SocketAddrV4::new(local, 0)),
+        timeout: Some(DISCOVERY_TIMEOUT),
+        ..Default::default()
+    };
+    match igd_next::search_gateway(options) {
+        Ok(gateway) => {
+            let external_ip = gateway.get_external_ip().ok();
+            return Ok((Gateway::Igd(gateway), external_ip));
+        }
+        Err(e) => info!("UPnP-IGD gateway not found: {}", e),
+    }
+    let Some(gateway_ip) = netdev::get_default_gateway()
+        .ok()
+        .and_then(|x| x.ipv4.first().copied())
+    else {
+        return Err("no default gateway".to_string());
+    };
+    let gateway = SocketAddr::new(IpAddr::V4(gateway_ip), NAT_PMP_PORT);
+    if pcp_announce(gateway, local).is_ok() {
+        return Ok((Gateway::Pmp(gateway, MappingMethod::Pcp), None));
+    }
+    match nat_pmp_external_ip(gateway) {
+        Ok(ip) => Ok((
+            Gateway::Pmp(gateway, MappingMethod::NatPmp),
+            Some(IpAddr::V4(ip)),
+        )),
+        Err(e) => Err(format!("no port mapping gateway found: {}", e)),
+    }
+}
+
+impl MappingTarget {
+    fn new(gateway: Gateway, local: Ipv4Addr) -> Self {
+        let mut nonce = [0u8; 12];
+        OsRng.fill_bytes(&mut nonce);
+        Self {
+            gateway,
+            local,
+            nonce,
+        }
+    }
+
+    fn method(&self) -> MappingMethod {
+        match self.gateway {
+            Gateway::Igd(_) => MappingMethod::UpnpIgd,
+            Gateway::Pmp(_, method) => method,
+        }
+    }
+
+    /// 建立或续期映射，返回外网端口，PCP会同时返回外网地址
+    fn map(
+        &self,
+        protocol: TransportProtocol,
+        port: u16,
+        lifetime: u32,
+    ) -> Result<(u16, Option<IpAddr>), String> {
+        match &self.gateway {
+            Gateway::Igd(gateway) => {
+                let local = SocketAddr::V4(SocketAddrV4::new(self.local, port));
+                let result = if lifetime == 0 {
+                    gateway
+                        .remove_port(protocol.into(), port)
+                        .map_err(|e| e.to_string())
+                } else {
+                    gateway
+                        .add_port(protocol.into(), port, local, lifetime, MAPPING_DESCRIPTION)
+                        .map_err(|e| e.to_string())
+                };
+                result.map(|_| (port, None))
+            }
+            Gateway::Pmp(gateway, MappingMethod::Pcp) => {
+                pcp_map(*gateway, self.local, &self.nonce, protocol, port, lifetime)
+                    .map(|(port, ip)| (port, Some(ip)))
+                    .map_err(|e| e.to_string())
+            }
+            Gateway::Pmp(gateway, _) => nat_pmp_map(*gateway, protocol, port, lifetime)
+                .map(|port| (port, None))
+                .map_err(|e| e.to_string()),
+        }
+    }
+
+    /// 建立或续期所有映射，结果写入status，耗时较长，不能在持有锁时调用
+    fn map_all(&self, ports: &[(TransportProtocol, u16)], status: &mut PortMappingStatus) {
+        let mut mappings = vec![];
+        let mut errors = vec![];
+        for &(protocol, port) in ports {
+            match self.map(protocol, port, LEASE_SECS) {
+                Ok((external_port, external_ip)) => {
+                    if let Some(ip) = external_ip {
+                        status.external_ip = Some(ip.to_string());
+                    }
+                    mappings.push(MappedPort {
+                        protocol,
+                        internal_port: port,
+                        external_port,
+                    })
+                }
+                Err(e) => errors.push(format!("{:?} {}: {}", protocol, port, e)),
+            }
+        }
+        status.mappings = mappings;
+        status.renewed_at = Some(now_secs());
+        status.error = if errors.is_empty() {
+            None
+        } else {
+            Some(errors.join("; "))
+        };
+    }
+
+    fn unmap_all(&self, mappings: &[MappedPort]) {
+        for mapping in mappings {
+            if let Err(e) = self.map(mapping.protocol, mapping.internal_port, 0) {
+                warn!("Failed to remove port mapping: {}", e);
+            }
+        }
+    }
+}
+
+fn emit_status(app: &AppHandle, status: &PortMappingStatus) {
+    if let Err(e) = app.emit("lers://vnt/port_mapping", status.clone()) {
+        error!("Failed to emit port mapping: {}", e);
+    }
+}
+
+/// vnt需要映射的端口：所有udp端口，第一个端口同时用于tcp
+pub(crate) fn mapping_ports(ports: &[u16]) -> Vec<(TransportProtocol, u16)> {
+    let mut result = ports
+        .iter()
+        .filter(|&&x| x != 0)
+        .map(|&x| (TransportProtocol::Udp, x))
+        .collect::<Vec<_>>();
+    if let Some(&port) = ports.first().filter(|&&x| x != 0) {
+        result.push((TransportProtocol::Tcp, port));
+    }
+    result
+}
+
+/// 在后台发现网关并建立映射，连接期间定期续期
+pub(crate) fn start_port_mapping(app: AppHandle, ports: Vec<(TransportProtocol, u16)>) {
+    let mapper = app.state::<PortMapper>();
+    let session = match mapper.session.lock() {
+        Ok(mut session) => {
+            *session += 1;
+            *session
+        }
+        Err(_) => return,
+    };
+    std::thread::spawn(move || {
+        let mapper = app.state::<PortMapper>();
+        if ports.is_empty() {
+            let status = PortMappingStatus {
+                error: Some("no fixed ports configured".to_string()),
+                ..Default::default()
+            };
+            emit_status(&app, &status);
+            return;
+        }
+        let Some(IpAddr::V4(local)) = local_ip(false) else {
+            error!("Failed to get local address for port mapping");
+            return;
+        };
+        let (gateway, external_ip) = match discover(local) {
+            Ok(x) => x,
+            Err(e) => {
+                warn!("{}", e);
+                emit_status(
+                    &app,
+                    &PortMappingStatus {
+                        error: Some(e),
+                        ..Default::default()
+                    },
+                );
+                return;
+            }
+        };
+        let target = Arc::new(MappingTarget::new(gateway, local));
+        let mut status = PortMappingStatus {
+            method: Some(target.method()),
+            gateway: Some(match &target.gateway {
+                Gateway::Igd(x) => x.addr.to_string(),
+                Gateway::Pmp(x, _) => x.to_string(),
+            }),
+            external_ip: external_ip.map(|x| x.to_string()),
+            ..Default::default()
+        };
+        target.map_all(&ports, &mut status);
+        info!("port mapping: {:?}", status);
+        emit_status(&app, &status);
+        match mapper.active.lock() {
+            // 映射期间已经停止
+            Ok(mut x) if *mapper.session.lock().unwrap() == session => {
+                x.replace(ActiveMapping {
+                    session,
+                    target: target.clone(),
+                    status,
+                });
+            }
+            _ => {
+                target.unmap_all(&status.mappings);
+                return;
+            }
+        }
+        loop {
+            sleep(Duration::from_secs(LEASE_SECS as u64 / 2));
+            // 续期期间不持有锁，避免阻塞停止映射和状态查询
+            let mut status = {
+                let Ok(x) = mapper.active.lock() else {
+                    break;
+                };
+                match x.as_ref().filter(|x| x.session == session) {
+                    Some(active) => active.status.clone(),
+                    None => break,
+                }
+            };
+            target.map_all(&ports, &mut status);
+            let Ok(mut x) = mapper.active.lock() else {
+                break;
+            };
+            let Some(active) = x.as_mut().filter(|x| x.session == session) else {
+                // 续期期间已经停止，删除刚续期的映射
+                drop(x);
+                target.unmap_all(&status.mappings);
+                break;
+            };
+            active.status = status;
+            emit_status(&app, &active.status);
+        }
+        info!("port mapping thread stopped")
+    });
+}
+
+/// 删除端口映射
+pub(crate) fn stop_port_mapping(app: &AppHandle) {
+    let mapper = app.state::<PortMapper>();
+    if let Ok(mut session) = mapper.session.lock() {
+        *session += 1;
+    }
+    let active = mapper.active.lock().ok().and_then(|mut x| x.take());
+    if let Some(active) = active {
+        active.target.unmap_all(&active.status.mappings);
+        info!("port mapping removed");
+        emit_status(app, &PortMappingStatus::default());
+    }
+}
+
+/// 当前端口映射状态
+pub(crate) fn port_mapping_status(app: &AppHandle) -> Option<PortMappingStatus> {
+    app.state::<PortMapper>()
+        .active
+        .lock()
+        .ok()
+        .and_then(|x| x.as_ref().map(|x| x.status.clone()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread::JoinHandle;
+
+    /// 本地网关，按顺序回复请求，返回收到的请求
+    fn gateway<F>(count: usize, reply: F) -> (SocketAddr, JoinHandle<Vec<Vec<u8>>>)
+    where
+        F: Fn(&[u8]) -> Vec<u8> + Send + 'static,
+    {
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let addr = socket.local_addr().unwrap();
+        let handle = std::thread::spawn(move || {
+            let mut requests = vec![];
+            let mut buf = [0u8; 1100];
+            for _ in 0..count {
+                let (len, from) = socket.recv_from(&mut buf).unwrap();
+                socket.send_to(&reply(&buf[..len]), from).unwrap();
+                requests.push(buf[..len].to_vec());
+            }
+            requests
+        });
+        (addr, handle)
+    }
+
+    /// NAT-PMP 映射响应，外网端口为内部端口加1000
+    fn pmp_reply(request: &[u8]) -> Vec<u8> {
+        let mut response = vec![0, 128 + request[1], 0, 0];
+        response.extend(1u32.to_be_bytes());
+        response.extend(&request[4..6]);
+        let internal = u16::from_be_bytes([request[4], request[5]]);
+        response.extend((internal + 1000).to_be_bytes());
+        response.extend(&request[8..12]);
+        response
+    }
+
+    /// PCP MAP 响应，回显nonce和协议，外网端口为内部端口加1000
+    fn pcp_reply(request: &[u8]) -> Vec<u8> {
+        let mut response = vec![2, 0x81, 0, 0];
+        response.extend(&request[4..8]);
+        response.extend(1u32.to_be_bytes());
+        response.extend([0u8; 12]);
+        response.extend(&request[24..40]);
+        response.extend(&request[40..42]);
+        let internal = u16::from_be_bytes([request[40], request[41]]);
+        response.extend((internal + 1000).to_be_bytes());
+        response.extend(Ipv4Addr::new(203, 0, 113, 7).to_ipv6_mapped().octets());
+        response
+    }
+
+    #[test]
+    fn nat_pmp_external_ip_round_trip() {
+        let (addr, handle) = gateway(1, |_| vec![0, 128, 0, 0, 0, 0, 0, 1, 203, 0, 113, 7]);
+        assert_eq!(
+            nat_pmp_external_ip(addr).unwrap(),
+            Ipv4Addr::new(203, 0, 113, 7)
+        );
+        assert_eq!(handle.join().unwrap(), vec![vec![0, 0]]);
+    }
+
+    #[test]
+    fn nat_pmp_map_round_trip() {
+        let (addr, handle) = gateway(2, pmp_reply);
+        assert_eq!(
+            nat_pmp_map(addr, TransportProtocol::Udp, 29872, LEASE_SECS).unwrap(),
+            30872
+        );
+        nat_pmp_map(addr, TransportProtocol::Tcp, 29872, 0).unwrap();
+        let requests = handle.join().unwrap();
+        let mut expected = vec![0, 1, 0, 0];
+        expected.extend(29872u16.to_be_bytes());
+        expected.extend(29872u16.to_be_bytes());
+        expected.extend(LEASE_SECS.to_be_bytes());
+        assert_eq!(requests[0], expected);
+        // 删除映射时外网端口和租期都为0
+        let mut expected = vec![0, 2, 0, 0];
+        expected.extend(29872u16.to_be_bytes());
+        expected.extend([0u8; 6]);
+        assert_eq!(requests[1], expected);
+    }
+
+    #[test]
+    fn nat_pmp_map_rejects_result_code() {
+        let (addr, handle) = gateway(1, |request| {
+            let mut response = pmp_reply(request);
+            response[3] = 2;
+            response
+        });
+        assert!(nat_pmp_map(addr, TransportProtocol::Udp, 29872, LEASE_SECS).is_err());
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn pcp_map_round_trip() {
+        let (addr, handle) = gateway(2, pcp_reply);
+        let local = Ipv4Addr::new(192, 168, 1, 10);
+        let target = MappingTarget::new(Gateway::Pmp(addr, MappingMethod::Pcp), local);
+        let (port, ip) = target
+            .map(TransportProtocol::Udp, 29872, LEASE_SECS)
+            .unwrap();
+        assert_eq!(port, 30872);
+        assert_eq!(ip, Some(IpAddr::V4(Ipv4Addr::new(203, 0, 113, 7))));
+        target.map(TransportProtocol::Udp, 29872, 0).unwrap();
+        let requests = handle.join().unwrap();
+        let request = &requests[0];
+        assert_eq!(request.len(), 60);
+        assert_eq!(request[..4], [2, 1, 0, 0]);
+        assert_eq!(request[4..8], LEASE_SECS.to_be_bytes());
+        assert_eq!(request[8..24], local.to_ipv6_mapped().octets());
+        assert_eq!(request[36], 17);
+        assert_eq!(request[40..42], 29872u16.to_be_bytes());
+        // 删除映射时使用与建立时相同的nonce
+        assert_eq!(request[24..36], target.nonce);
+        assert_eq!(requests[1][24..36], target.nonce);
+        assert_eq!(requests[1][4..8], [0u8; 4]);
+    }
+
+    #[test]
+    fn pcp_map_rejects_nonce_mismatch() {
+        let (addr, handle) = gateway(1, |request| {
+            let mut response = pcp_reply(request);
+            response[24] ^= 0xff;
+            response
+        });
+        let nonce = [1u8; 12];
+        let result = pcp_map(
+            addr,
+            Ipv4Addr::LOCALHOST,
+            &nonce,
+            TransportProtocol::Tcp,
+            29872,
+            LEASE_SECS,
+        );
+        assert!(result.is_err());
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn pcp_nonce_is_random() {
+        let gateway = || Gateway::Pmp(SocketAddr::from(([127, 0, 0, 1], 5351)), MappingMethod::Pcp);
+        let a = MappingTarget::new(gateway(), Ipv4Addr::LOCALHOST);
+        let b = MappingTarget::new(gateway(), Ipv4Addr::LOCALHOST);
+        assert_ne!(a.nonce, b.nonce);
+    }
+
+    #[test]
+    fn pcp_announce_detects_nat_pmp_only_gateway() {
+        // 仅支持NAT-PMP的网关返回版本0和不支持的版本错误
+        let (addr, handle) = gateway(1, |_| vec![0, 128, 0, 1, 0, 0, 0, 1]);
+        assert!(pcp_announce(addr, Ipv4Addr::LOCALHOST).is_err());
+        handle.join().unwrap();
+        let (addr, handle) = gateway(1, |request| {
+            let mut response = vec![2, 0x80, 0, 0];
+            response.extend([0u8; 20]);
+            assert_eq!(request.len(), 24);
+            response
+        });
+        pcp_announce(addr, Ipv4Addr::LOCALHOST).unwrap();
+        handle.join().unwrap();
+    }
+}
@@ -0,0 +1,372 @@
+//! 子进程树管理
+//!
+//! Windows下通过作业对象(Job Object)追踪子进程及其派生的全部后代进程，
+//! 子进程以挂起状态创建，加入作业对象后才开始运行，避免在加入前派生的进程逃出作业对象。
+//! Unix下让子进程成为新进程组的组长，通过向进程组发送信号终止整棵进程树。
+
+use std::process::{Child, Command};
+
+/// 隐藏控制台窗口
+#[cfg(windows)]
+const CREATE_NO_WINDOW: u32 = 0x08000000;
+/// 创建新的进程组，使控制台中断事件只发往子进程
+#[cfg(windows)]
+const CREATE_NEW_PROCESS_GROUP: u32 = 0x00000200;
+/// 以挂起状态创建，由`ProcessTree::attach`加入作业对象后恢复
+#[cfg(windows)]
+const CREATE_SUSPENDED: u32 = 0x00000004;
+
+/// 设置子进程的创建参数，必须在spawn之前调用，spawn之后必须调用`ProcessTree::attach`
+pub(crate) fn configure(command: &mut Command, hide: bool) {
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::CommandExt;
+        let mut flags = CREATE_NEW_PROCESS_GROUP | CREATE_SUSPENDED;
+        if hide {
+            flags |= CREATE_NO_WINDOW;
+        }
+        command.creation_flags(flags);
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        let _ = hide;
+        command.process_group(0);
+    }
+}
+
+/// 以某个子进程为根的进程树
+pub(crate) struct ProcessTree {
+    pid: u32,
+    #[cfg(windows)]
+    job: Option<windows::Job>,
+}
+
+impl ProcessTree {
+    /// 追踪刚启动的子进程，之后由它派生的进程都会归入同一棵树
+    ///
+    /// Windows下子进程由`configure`设置为挂起状态，加入作业对象后在此恢复运行
+    pub(crate) fn attach(child: &Child) -> Self {
+        let pid = child.id();
+        #[cfg(windows)]
+        {
+            use std::os::windows::io::AsRawHandle;
+            let tree = Self::track(pid, windows::Job::assign(child.as_raw_handle() as _));
+            windows::resume(pid);
+            tree
+        }
+        #[cfg(not(windows))]
+        ProcessTree { pid }
+    }
+
+    /// 按PID追踪不由`Command`启动的子进程，Unix下要求其已是进程组组长
+    ///
+    /// 进程已在运行，加入作业对象前派生的进程不会被追踪
+    pub(crate) fn attach_pid(pid: u32) -> Self {
+        #[cfg(windows)]
+        {
+            Self::track(pid, windows::Job::assign_pid(pid))
+        }
+        #[cfg(not(windows))]
+        ProcessTree { pid }
+    }
+
+    #[cfg(windows)]
+    fn track(pid: u32, job: std::io::Result<windows::Job>) -> Self {
+        let job = match job {
+            Ok(job) => Some(job),
+            Err(e) => {
+                log::warn!("Failed to assign process {} to job object: {}", pid, e);
+                None
+            }
+        };
+        ProcessTree { pid, job }
+    }
+
+    pub(crate) fn pid(&self) -> u32 {
+        self.pid
+    }
+
+    /// 请求进程树正常退出
+    ///
+    /// Windows下向根进程的窗口发送WM_CLOSE并向其进程组发送CTRL_BREAK，
+    /// Unix下向整个进程组发送SIGTERM
+    pub(crate) fn terminate(&self) {
+        #[cfg(windows)]
+        {
+            windows::close_windows(self.pid);
+            windows::send_ctrl_break(self.pid);
+        }
+        #[cfg(unix)]
+        unsafe {
+            libc::kill(-(self.pid as libc::pid_t), libc::SIGTERM);
+        }
+    }
+
+    /// 强制结束进程树中仍存活的全部进程
+    pub(crate) fn kill(&self) {
+        #[cfg(windows)]
+        {
+            match &self.job {
+                Some(job) => job.terminate(),
+                None => windows::terminate_process(self.pid),
+            }
+        }
+        #[cfg(unix)]
+        unsafe {
+            libc::kill(-(self.pid as libc::pid_t), libc::SIGKILL);
+        }
+    }
+}
+
+/// 各进程的线程数，Windows下由进程快照获取
+#[cfg(windows)]
+pub(crate) fn thread_counts() -> std::collections::HashMap<u32, u32> {
+    windows::thread_counts()
+}
+
+#[cfg(windows)]
+mod windows {
+    use std::collections::HashMap;
+    use std::io;
+    use std::sync::Mutex;
+    use windows_sys::Win32::Foundation::{
+        CloseHandle, BOOL, FALSE, HANDLE, HWND, INVALID_HANDLE_VALUE, LPARAM, TRUE,
+    };
+    use windows_sys::Win32::System::Console::{
+        AttachConsole, FreeConsole, GenerateConsoleCtrlEvent, GetConsoleWindow,
+        SetConsoleCtrlHandler, CTRL_BREAK_EVENT,
+    };
+    use windows_sys::Win32::System::Diagnostics::ToolHelp::{
+        CreateToolhelp32Snapshot, Process32FirstW, Process32NextW, Thread32First, Thread32Next,
+        PROCESSENTRY32W, TH32CS_SNAPPROCESS, TH32CS_SNAPTHREAD, THREADENTRY32,
+    };
+    use windows_sys::Win32::System::JobObjects::{
+        AssignProcessToJobObject, CreateJobObjectW, TerminateJobObject,
+    };
+    use windows_sys::Win32::System::Threading::{
+        OpenProcess, OpenThread, ResumeThread, TerminateProcess, PROCESS_SET_QUOTA,
+        PROCESS_TERMINATE, THREAD_SUSPEND_RESUME,
+    };
+    use windows_sys::Win32::UI::WindowsAndMessaging::{
+        EnumWindows, GetWindowThreadProcessId, PostMessageW, WM_CLOSE,
+    };
+
+    /// 进程被强制结束时的退出码
+    const KILLED_EXIT_CODE: u32 = 1;
+
+    /// 控制台同一时间只能附加一个，发送中断事件时需要串行
+    static CONSOLE_LOCK: Mutex<()> = Mutex::new(());
+
+    pub(super) struct Job(HANDLE);
+
+    // 作业对象句柄可在线程间共享
+    unsafe impl Send for Job {}
+    unsafe impl Sync for Job {}
+
+    impl Job {
+        pub(super) fn assign(process: HANDLE) -> io::Result<Self> {
+            unsafe {
+                let handle = CreateJobObjectW(std::ptr::null(), std::ptr::null());
+                if handle.is_null() {
+                    return Err(io::Error::last_os_error());
+                }
+                let job = Job(handle);
+                if AssignProcessToJobObject(job.0, process) == FALSE {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(job)
+            }
+        }
+
+        pub(super) fn assign_pid(pid: u32) -> io::Result<Self> {
+            unsafe {
+                let process = OpenProcess(PROCESS_SET_QUOTA | PROCESS_TERMINATE, FALSE, pid);
+                if process.is_null() {
+                    return Err(io::Error::last_os_error());
+                }
+                let job = Self::assign(process);
+                CloseHandle(process);
+                job
+            }
+        }
+
+        pub(super) fn terminate(&self) {
+            unsafe {
+                TerminateJobObject(self.0, KILLED_EXIT_CODE);
+            }
+        }
+    }
+
+    impl Drop for Job {
+        fn drop(&mut self) {
+            unsafe {
+                CloseHandle(self.0);
+            }
+        }
+    }
+
+    unsafe extern "system" fn close_window(hwnd: HWND, lparam: LPARAM) -> BOOL {
+        let mut pid = 0u32;
+        GetWindowThreadProcessId(hwnd, &mut pid);
+        if pid == lparam as u32 {
+            PostMessageW(hwnd, WM_CLOSE, 0, 0);
+        }
+        TRUE
+    }
+
+    /// 关闭进程拥有的顶层窗口
+    pub(super) fn close_windows(pid: u32) {
+        unsafe {
+            EnumWindows(Some(close_window), pid as LPARAM);
+        }
+    }
+
+    /// 恢复以挂起状态创建的进程的全部线程
+    pub(super) fn resume(pid: u32) {
+        unsafe {
+            let snapshot = CreateToolhelp32Snapshot(TH32CS_SNAPTHREAD, 0);
+            if snapshot == INVALID_HANDLE_VALUE {
+                log::error!(
+                    "Failed to resume process {}: {}",
+                    pid,
+                    io::Error::last_os_error()
+                );
+                return;
+            }
+            let mut entry: THREADENTRY32 = std::mem::zeroed();
+            entry.dwSize = std::mem::size_of::<THREADENTRY32>() as u32;
+            let mut ok = Thread32First(snapshot, &mut entry);
+            while ok != FALSE {
+                if entry.th32OwnerProcessID == pid {
+                    let thread = OpenThread(THREAD_SUSPEND_RESUME, FALSE, entry.th32ThreadID);
+                    if !thread.is_null() {
+                        ResumeThread(thread);
+                        CloseHandle(thread);
+                    }
+                }
+                ok = Thread32Next(snapshot, &mut entry);
+            }
+            CloseHandle(snapshot);
+        }
+    }
+
+    /// 向子进程的进程组发送CTRL_BREAK
+    ///
+    /// 本进程有控制台时子进程与其共用，直接发送；没有控制台时临时附加到子进程的控制台，
+    /// 不会释放本进程自己的控制台
+    pub(super) fn send_ctrl_break(pid: u32) {
+        let _guard = CONSOLE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        unsafe {
+            if !GetConsoleWindow().is_null() {
+                GenerateConsoleCtrlEvent(CTRL_BREAK_EVENT, pid);
+                return;
+            }
+            if AttachConsole(pid) == FALSE {
+                return;
+            }
+            // 忽略发往自身的中断事件
+            SetConsoleCtrlHandler(None, TRUE);
+            GenerateConsoleCtrlEvent(CTRL_BREAK_EVENT, pid);
+            FreeConsole();
+            // 事件是异步派发的，稍等片刻再恢复处理
+            std::thread::sleep(std::time::Duration::from_millis(100));
+            SetConsoleCtrlHandler(None, FALSE);
+        }
+    }
+
+    /// 未能加入作业对象时，仅结束根进程
+    pub(super) fn terminate_process(pid: u32) {
+        unsafe {
+            let handle = OpenProcess(PROCESS_TERMINATE, FALSE, pid);
+            if !handle.is_null() {
+                TerminateProcess(handle, KILLED_EXIT_CODE);
+                CloseHandle(handle);
+            }
+        }
+    }
+
+    pub(super) fn thread_counts() -> HashMap<u32, u32> {
+        let mut counts = HashMap::new();
+        unsafe {
+            let snapshot = CreateToolhelp32Snapshot(TH32CS_SNAPPROCESS, 0);
+            if snapshot == INVALID_HANDLE_VALUE {
+                return counts;
+            }
+            let mut entry: PROCESSENTRY32W = std::mem::zeroed();
+            entry.dwSize = std::mem::size_of::<PROCESSENTRY32W>() as u32;
+            let mut ok = Process32FirstW(snapshot, &mut entry);
+            while ok != FALSE {
+                counts.insert(entry.th32ProcessID, entry.cntThreads);
+                ok = Process32NextW(snapshot, &mut entry);
+            }
+            CloseHandle(snapshot);
+        }
+        counts
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::Stdio;
+    use std::time::{Duration, Instant};
+
+    /// 启动一个派生了后台进程的shell，返回shell和后台进程的PID
+    fn spawn_tree() -> (Child, u32) {
+        let mut command = Command::new("/bin/sh");
+        command
+            .args(["-c", "sleep 30 & echo $!; wait"])
+            .stdout(Stdio::piped());
+        configure(&mut command, true);
+        let mut child = command.spawn().unwrap();
+        let mut line = String::new();
+        BufReader::new(child.stdout.take().unwrap())
+            .read_line(&mut line)
+            .unwrap();
+        (child, line.trim().parse().unwrap())
+    }
+
+    /// 进程存在且不是僵尸进程
+    fn is_running(pid: u32) -> bool {
+        std::fs::read_to_string(format!("/proc/{}/stat", pid))
+            .map(|stat| {
+                stat.rsplit_once(") ")
+                    .is_some_and(|(_, rest)| !rest.starts_with('Z'))
+            })
+            .unwrap_or(false)
+    }
+
+    fn wait_stopped(pid: u32) -> bool {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while Instant::now() < deadline {
+            if !is_running(pid) {
+                return true;
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        false
+    }
+
+    #[test]
+    fn kill_stops_whole_tree() {
+        let (mut child, grandchild) = spawn_tree();
+        let tree = ProcessTree::attach(&child);
+        assert_eq!(tree.pid(), child.id());
+        assert!(is_running(grandchild));
+        tree.kill();
+        assert_eq!(child.wait().unwrap().signal(), Some(libc::SIGKILL));
+        assert!(wait_stopped(grandchild));
+    }
+
+    #[test]
+    fn terminate_stops_whole_tree() {
+        let (mut child, grandchild) = spawn_tree();
+        let tree = ProcessTree::attach_pid(child.id());
+        tree.terminate();
+        assert_eq!(child.wait().unwrap().signal(), Some(libc::SIGTERM));
+        assert!(wait_stopped(grandchild));
+    }
+}
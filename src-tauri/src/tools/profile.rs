@@ -0,0 +1,108 @@
+use crate::errors::ProgramError;
+use crate::tools::config_builder::{apply_config, persist_store_value, Config};
+use crate::tools::migration::PROFILES_KEY;
+use crate::tools::secret::{encrypt_config, redact};
+use crate::tools::Status;
+use log::error;
+use std::sync::Mutex;
+use tauri::{AppHandle, State};
+
+/// 配置档案，每个档案对应一个网络
+#[derive(serde::Serialize, serde::Deserialize, Clone, PartialEq)]
+pub(crate) struct Profile {
+    pub(crate) name: String,
+    pub(crate) config: Config,
+}
+
+/// 新增配置档案，同名档案会被替换
+pub(crate) fn upsert_profile(profiles: &mut Vec<Profile>, profile: Profile) {
+    match profiles.iter_mut().find(|x| x.name == profile.name) {
+        Some(exist) => *exist = profile,
+        None => profiles.push(profile),
+    }
+}
+
+/// 立即持久化配置档案，密钥加密后落盘
+pub(crate) fn save_profiles(app: &AppHandle, profiles: &[Profile]) -> Result<(), ProgramError> {
+    persist_store_value(app, PROFILES_KEY, profiles_value(profiles)?)
+}
+
+/// 落盘用的配置档案，密钥已加密
+pub(crate) fn profiles_value(profiles: &[Profile]) -> Result<serde_json::Value, ProgramError> {
+    let profiles = profiles
+        .iter()
+        .map(|x| {
+            Ok(Profile {
+                name: x.name.clone(),
+                config: encrypt_config(&x.config)?,
+            })
+        })
+        .collect::<Result<Vec<_>, ProgramError>>()?;
+    serde_json::to_value(profiles).map_err(|e| ProgramError::ConfigParseFailed(e.to_string()))
+}
+
+/// 获取配置档案列表，密钥已脱敏
+#[tauri::command]
+pub(crate) fn list_profiles(status: State<'_, Mutex<Status>>) -> Result<Vec<Profile>, String> {
+    match status
+        .lock()
+        .map_err(|e| ProgramError::ReadFailed(e.to_string()))
+    {
+        Ok(status) => {
+            let mut profiles = status.profiles.clone();
+            profiles.iter_mut().for_each(|x| redact(&mut x.config));
+            Ok(profiles)
+        }
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// 切换到指定配置档案，返回是否需要重连才能生效
+#[tauri::command]
+pub(crate) fn use_profile(
+    app: AppHandle,
+    name: String,
+    status: State<'_, Mutex<Status>>,
+) -> Result<bool, String> {
+    match status.lock() {
+        Ok(mut status) => {
+            let Some(profile) = status.profiles.iter().find(|x| x.name == name) else {
+                return Err(ProgramError::ProfileNotFound(name).to_string());
+            };
+            // 设备id和昵称属于本机，不随档案切换
+            let mut config = profile.config.clone();
+            config.device_id = status.config.device_id.clone();
+            config.name = status.config.name.clone();
+            apply_config(&app, &mut status, config).map_err(|e| e.to_string())
+        }
+        Err(e) => {
+            error!("Failed to write status: {}", e);
+            Err(e.to_string())
+        }
+    }
+}
+
+/// 删除配置档案
+#[tauri::command]
+pub(crate) fn delete_profile(
+    app: AppHandle,
+    name: String,
+    status: State<'_, Mutex<Status>>,
+) -> Result<(), String> {
+    match status.lock() {
+        Ok(mut status) => {
+            let mut profiles = status.profiles.clone();
+            profiles.retain(|x| x.name != name);
+            if profiles.len() == status.profiles.len() {
+                return Err(ProgramError::ProfileNotFound(name).to_string());
+            }
+            save_profiles(&app, &profiles).map_err(|e| e.to_string())?;
+            status.profiles = profiles;
+            Ok(())
+        }
+        Err(e) => {
+            error!("Failed to write status: {}", e);
+            Err(e.to_string())
+        }
+    }
+}
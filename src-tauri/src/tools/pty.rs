@@ -0,0 +1,105 @@
+//! 伪终端模式
+//!
+//! 部分控制台工具在标准输出为管道时会改变行为或一直缓冲输出，
+//! 伪终端模式下工具认为自己运行在真实终端中，输出以原始字节转发，保留ANSI控制序列。
+//! Windows下基于ConPTY，Unix下子进程通过setsid成为新会话及进程组的组长。
+
+use crate::tools::command::{CommandEvent, EventSink};
+use portable_pty::{native_pty_system, Child, CommandBuilder, MasterPty, PtySize};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::path::Path;
+
+/// 单次读取的最大字节数
+const READ_CHUNK: usize = 8192;
+
+/// 终端尺寸，以字符为单位
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug)]
+pub(crate) struct TerminalSize {
+    pub(crate) cols: u16,
+    pub(crate) rows: u16,
+}
+
+impl From<TerminalSize> for PtySize {
+    fn from(size: TerminalSize) -> Self {
+        PtySize {
+            rows: size.rows.max(1),
+            cols: size.cols.max(1),
+            pixel_width: 0,
+            pixel_height: 0,
+        }
+    }
+}
+
+/// 运行在伪终端中的子进程
+pub(crate) struct PtyProcess {
+    pub(crate) pid: u32,
+    pub(crate) child: Box<dyn Child + Send + Sync>,
+    pub(crate) master: Box<dyn MasterPty + Send>,
+    pub(crate) reader: Box<dyn Read + Send>,
+    pub(crate) writer: Box<dyn Write + Send>,
+}
+
+/// 在新的伪终端中启动程序
+pub(crate) fn spawn(
+    executable: &Path,
+    args: &[String],
+    env: &HashMap<String, String>,
+    cwd: &Path,
+    size: TerminalSize,
+) -> anyhow::Result<PtyProcess> {
+    let pair = native_pty_system().openpty(size.into())?;
+    let mut command = CommandBuilder::new(executable);
+    command.args(args);
+    command.cwd(cwd);
+    // 未指定时声明终端类型，否则部分工具不输出颜色
+    if !env.contains_key("TERM") {
+        command.env("TERM", "xterm-256color");
+    }
+    for (key, value) in env {
+        command.env(key, value);
+    }
+    let child = pair.slave.spawn_command(command)?;
+    // 从端只由子进程持有，子进程退出后主端才能读到结束
+    drop(pair.slave);
+    let pid = child
+        .process_id()
+        .ok_or_else(|| anyhow::anyhow!("process id is unavailable"))?;
+    let reader = pair.master.try_clone_reader()?;
+    let writer = pair.master.take_writer()?;
+    Ok(PtyProcess {
+        pid,
+        child,
+        master: pair.master,
+        reader,
+        writer,
+    })
+}
+
+/// 转发伪终端的原始输出，直到子进程退出且输出读完
+pub(crate) fn forward_output(mut reader: Box<dyn Read + Send>, sink: EventSink) {
+    let mut buffer = vec![0u8; READ_CHUNK];
+    loop {
+        match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(n) => sink(CommandEvent::Data(buffer[..n].to_vec())),
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            // Unix下子进程退出后读取主端返回EIO
+            Err(_) => break,
+        }
+    }
+}
+
+/// 等待子进程退出，被信号结束时只能取得信号名称，此时退出码和信号均为空
+pub(crate) fn wait(mut child: Box<dyn Child + Send + Sync>) -> CommandEvent {
+    match child.wait() {
+        Ok(status) if status.signal().is_none() => CommandEvent::Terminated {
+            code: Some(status.exit_code() as i32),
+            signal: None,
+        },
+        _ => CommandEvent::Terminated {
+            code: None,
+            signal: None,
+        },
+    }
+}
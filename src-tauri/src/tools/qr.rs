@@ -0,0 +1,163 @@
+use crate::errors::ProgramError;
+use crate::tools::invite::Invite;
+use crate::tools::profile::Profile;
+use crate::tools::Status;
+use image::{ImageFormat, Luma};
+use log::error;
+use qrcode::QrCode;
+use std::io::Cursor;
+use std::sync::Mutex;
+use tauri::ipc::Response;
+use tauri::{AppHandle, State};
+
+/// 二维码最小边长(像素)
+const QR_MIN_SIZE: u32 = 256;
+
+/// 二维码输出格式
+#[derive(serde::Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum QrFormat {
+    #[default]
+    Svg,
+    Png,
+}
+
+/// 将内容渲染为二维码
+pub(crate) fn render_qr(content: &str, format: QrFormat) -> Result<Vec<u8>, ProgramError> {
+    let code =
+        QrCode::new(content.as_bytes()).map_err(|e| ProgramError::QrFailed(e.to_string()))?;
+    match format {
+        QrFormat::Svg => Ok(code
+            .render::<qrcode::render::svg::Color>()
+            .min_dimensions(QR_MIN_SIZE, QR_MIN_SIZE)
+            .build()
+            .into_bytes()),
+        QrFormat::Png => {
+            let image = code
+                .render::<Luma<u8>>()
+                .min_dimensions(QR_MIN_SIZE, QR_MIN_SIZE)
+                .build();
+            let mut bytes = vec![];
+            image
+                .write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)
+                .map_err(|e| ProgramError::QrFailed(e.to_string()))?;
+            Ok(bytes)
+        }
+    }
+}
+
+/// 从图片文件中识别二维码内容
+pub(crate) fn decode_qr_file(path: &str) -> Result<String, ProgramError> {
+    let image = image::open(path)
+        .map_err(|e| ProgramError::ReadFailed(e.to_string()))?
+        .to_luma8();
+    let mut image = rqrr::PreparedImage::prepare(image);
+    image
+        .detect_grids()
+        .iter()
+        .find_map(|grid| grid.decode().ok().map(|(_, content)| content))
+        .ok_or_else(|| ProgramError::QrFailed("no qr code found".to_string()))
+}
+
+/// 生成邀请二维码，不包含设备id，默认不包含密码
+#[tauri::command]
+pub(crate) fn generate_invite_qr(
+    profile: Option<String>,
+    include_password: bool,
+    format: Option<QrFormat>,
+    status: State<'_, Mutex<Status>>,
+) -> Result<Response, String> {
+    let invite = match status
+        .lock()
+        .map_err(|e| ProgramError::ReadFailed(e.to_string()))
+    {
+        Ok(status) if status.secrets_locked => return Err(ProgramError::SecretsLocked.to_string()),
+        Ok(status) => match profile {
+            Some(name) => {
+                let Some(profile) = status.profiles.iter().find(|x| x.name == name) else {
+                    return Err(ProgramError::ProfileNotFound(name).to_string());
+                };
+                Invite::from_config(&profile.config, name, include_password, None)
+            }
+            None => Invite::from_config(
+                &status.config,
+                status.config.server_address_str.clone(),
+                include_password,
+                None,
+            ),
+        },
+        Err(e) => return Err(e.to_string()),
+    };
+    let link = invite.to_link().map_err(|e| e.to_string())?;
+    render_qr(&link.uri, format.unwrap_or_default())
+        .map(Response::new)
+        .map_err(|e| e.to_string())
+}
+
+/// 识别二维码图片并导入为配置档案，与接受邀请相同的校验，但不切换配置
+#[tauri::command]
+pub(crate) fn import_invite_qr(
+    app: AppHandle,
+    path: String,
+    password: Option<String>,
+    status: State<'_, Mutex<Status>>,
+) -> Result<Profile, String> {
+    let content = decode_qr_file(&path).map_err(|e| e.to_string())?;
+    let invite = Invite::decode(&content).map_err(|e| e.to_string())?;
+    match status.lock() {
+        Ok(mut status) => invite
+            .save_profile(&app, &mut status, password)
+            .map_err(|e| e.to_string()),
+        Err(e) => {
+            error!("Failed to write status: {}", e);
+            Err(e.to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::config_builder::Config;
+    use crate::tools::invite::INVITE_URI_PREFIX;
+    use crate::tools::test_util::temp_dir;
+    use ed25519_dalek::SigningKey;
+
+    #[test]
+    fn decodes_rendered_invite() {
+        let config = Config {
+            server_address_str: "vnt.example.com:29872".to_string(),
+            token: "token".to_string(),
+            password: Some("password".to_string()),
+            ..Default::default()
+        };
+        let code = Invite::from_config(&config, "home".to_string(), false, Some(3600))
+            .encode(&SigningKey::from_bytes(&[7u8; 32]))
+            .unwrap();
+        let uri = format!("{}?invite={}", INVITE_URI_PREFIX, code);
+        let dir = temp_dir();
+        let path = dir.join("invite.png");
+        std::fs::write(&path, render_qr(&uri, QrFormat::Png).unwrap()).unwrap();
+        let content = decode_qr_file(path.to_str().unwrap()).unwrap();
+        assert_eq!(content, uri);
+        // 与识别二维码导入时相同的校验
+        let invite = Invite::decode(&content).unwrap();
+        assert_eq!(invite.server, "vnt.example.com:29872");
+        assert_eq!(invite.token, "token");
+        assert!(invite.password.is_none());
+        assert!(invite.check_password(Some("wrong")).is_err());
+        invite.check_password(Some("password")).unwrap();
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn rejects_image_without_qr() {
+        let dir = temp_dir();
+        let path = dir.join("blank.png");
+        image::GrayImage::from_pixel(64, 64, Luma([255u8]))
+            .save(&path)
+            .unwrap();
+        assert!(decode_qr_file(path.to_str().unwrap()).is_err());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
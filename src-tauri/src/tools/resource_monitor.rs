@@ -0,0 +1,317 @@
+//! 子进程资源占用监控
+//!
+//! 后台线程定期采样每个子进程及其进程树的CPU、内存、线程数和运行时长，
+//! 通过`lers://child/resources`事件推送，超出限制时按配置告警或结束进程树。
+
+use crate::tools::command::ChildrenManager;
+use log::{error, warn};
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+use sysinfo::{Pid, ProcessRefreshKind, ProcessesToUpdate, System};
+use tauri::{AppHandle, Emitter, Manager};
+
+/// 采样间隔
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(2);
+
+/// 超限后的处理方式
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum LimitAction {
+    #[default]
+    Warn,
+    Kill,
+}
+
+/// 进程树的资源限制
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub(crate) struct ResourceLimits {
+    /// CPU占用上限，100表示占满一个核心
+    #[serde(default)]
+    pub(crate) cpu_percent: Option<f32>,
+    /// 常驻内存上限(字节)
+    #[serde(default)]
+    pub(crate) rss_bytes: Option<u64>,
+    #[serde(default)]
+    pub(crate) action: LimitAction,
+    /// 连续超限多少次采样后才处理，避免瞬时峰值误判
+    #[serde(default = "default_sustained")]
+    pub(crate) sustained: u32,
+}
+
+fn default_sustained() -> u32 {
+    3
+}
+
+/// 资源占用
+#[derive(serde::Serialize, Clone, Debug, Default)]
+pub(crate) struct Usage {
+    /// 100表示占满一个核心，多核时可超过100
+    pub(crate) cpu_percent: f32,
+    pub(crate) rss_bytes: u64,
+    /// 只有Linux和Windows能获取，其他平台为空
+    pub(crate) threads: Option<u32>,
+}
+
+impl Usage {
+    fn add(&mut self, other: &Usage) {
+        self.cpu_percent += other.cpu_percent;
+        self.rss_bytes += other.rss_bytes;
+        self.threads = match (self.threads, other.threads) {
+            (Some(a), Some(b)) => Some(a + b),
+            _ => None,
+        };
+    }
+}
+
+/// 单次采样结果
+#[derive(serde::Serialize, Clone, Debug)]
+pub(crate) struct ResourceSample {
+    pub(crate) id: String,
+    pub(crate) tool: String,
+    pub(crate) pid: u32,
+    pub(crate) uptime_secs: u64,
+    /// 根进程自身
+    pub(crate) process: Usage,
+    /// 整棵进程树，包含根进程
+    pub(crate) tree: Usage,
+    pub(crate) tree_processes: usize,
+}
+
+/// 超限通知
+#[derive(serde::Serialize, Clone, Debug)]
+pub(crate) struct LimitExceeded {
+    id: String,
+    tool: String,
+    /// cpu或memory
+    resource: &'static str,
+    value: f64,
+    limit: f64,
+    action: LimitAction,
+}
+
+/// 被监控子进程的基本信息
+pub(crate) struct MonitorTarget {
+    pub(crate) id: String,
+    pub(crate) tool: String,
+    pub(crate) pid: u32,
+    pub(crate) started_at: u64,
+    pub(crate) limits: Option<ResourceLimits>,
+}
+
+fn usage_of(system: &System, pid: Pid, threads: &HashMap<u32, u32>) -> Usage {
+    let Some(process) = system.process(pid) else {
+        return Usage::default();
+    };
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    // tasks不包含主线程
+    let thread_count = process
+        .tasks()
+        .map(|x| x.len() as u32 + 1)
+        .or_else(|| threads.get(&pid.as_u32()).copied());
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    let thread_count = threads.get(&pid.as_u32()).copied();
+    Usage {
+        cpu_percent: process.cpu_usage(),
+        rss_bytes: process.memory(),
+        threads: thread_count,
+    }
+}
+
+/// 按父进程关系收集进程树
+fn tree_of(root: Pid, children: &HashMap<Pid, Vec<Pid>>) -> Vec<Pid> {
+    let mut tree = vec![root];
+    let mut visited = HashSet::from([root]);
+    let mut index = 0;
+    while index < tree.len() {
+        if let Some(list) = children.get(&tree[index]) {
+            for child in list {
+                if visited.insert(*child) {
+                    tree.push(*child);
+                }
+            }
+        }
+        index += 1;
+    }
+    tree
+}
+
+fn sample(system: &System, target: &MonitorTarget, now_ms: u64) -> ResourceSample {
+    #[cfg(windows)]
+    let threads = crate::tools::process::thread_counts();
+    #[cfg(not(windows))]
+    let threads = HashMap::new();
+    let mut children: HashMap<Pid, Vec<Pid>> = HashMap::new();
+    for (pid, process) in system.processes() {
+        // Linux下线程也作为进程出现，只统计真正的进程
+        if process.thread_kind().is_some() {
+            continue;
+        }
+        if let Some(parent) = process.parent() {
+            children.entry(parent).or_default().push(*pid);
+        }
+    }
+    let root = Pid::from_u32(target.pid);
+    let process = usage_of(system, root, &threads);
+    let members = tree_of(root, &children);
+    let mut tree = Usage {
+        threads: Some(0),
+        ..Usage::default()
+    };
+    for pid in &members {
+        tree.add(&usage_of(system, *pid, &threads));
+    }
+    ResourceSample {
+        id: target.id.clone(),
+        tool: target.tool.clone(),
+        pid: target.pid,
+        uptime_secs: now_ms.saturating_sub(target.started_at) / 1000,
+        process,
+        tree,
+        tree_processes: members.len(),
+    }
+}
+
+/// 检查限制，返回超限的项目
+fn exceeded(sample: &ResourceSample, limits: &ResourceLimits) -> Vec<(&'static str, f64, f64)> {
+    let mut exceeded = vec![];
+    if let Some(limit) = limits.cpu_percent {
+        if sample.tree.cpu_percent > limit {
+            exceeded.push(("cpu", sample.tree.cpu_percent as f64, limit as f64));
+        }
+    }
+    if let Some(limit) = limits.rss_bytes {
+        if sample.tree.rss_bytes > limit {
+            exceeded.push(("memory", sample.tree.rss_bytes as f64, limit as f64));
+        }
+    }
+    exceeded
+}
+
+/// 刷新全部进程，sysinfo 0.33在Linux上刷新时总会读取`/proc/<pid>/task`，
+/// 不需要额外的选项就能得到线程；Windows的线程数另由Toolhelp快照统计
+fn refresh(system: &mut System) {
+    system.refresh_processes_specifics(
+        ProcessesToUpdate::All,
+        true,
+        ProcessRefreshKind::nothing().with_cpu().with_memory(),
+    );
+}
+
+/// 启动采样线程，没有运行中的子进程时不刷新系统进程表
+pub(crate) fn start_resource_monitor(app: AppHandle) {
+    std::thread::spawn(move || {
+        let mut system = System::new();
+        // 每个子进程连续超限的次数
+        let mut strikes: HashMap<String, u32> = HashMap::new();
+        loop {
+            std::thread::sleep(SAMPLE_INTERVAL);
+            let manager = app.state::<ChildrenManager>();
+            let targets = manager.monitor_targets();
+            strikes.retain(|id, _| targets.iter().any(|x| &x.id == id));
+            if targets.is_empty() {
+                continue;
+            }
+            refresh(&mut system);
+            let now_ms = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|x| x.as_millis() as u64)
+                .unwrap_or(0);
+            let mut samples = Vec::with_capacity(targets.len());
+            for target in &targets {
+                let sample = sample(&system, target, now_ms);
+                if let Some(limits) = &target.limits {
+                    let over = exceeded(&sample, limits);
+                    let count = strikes.entry(target.id.clone()).or_default();
+                    if over.is_empty() {
+                        *count = 0;
+                    } else {
+                        *count += 1;
+                    }
+                    // 达到次数时处理一次，告警不会在持续超限期间重复发送
+                    if *count == limits.sustained.max(1) {
+                        for (resource, value, limit) in over {
+                            warn!(
+                                "{} ({}) exceeded {} limit: {} > {}",
+                                target.tool, target.id, resource, value, limit
+                            );
+                            let event = LimitExceeded {
+                                id: target.id.clone(),
+                                tool: target.tool.clone(),
+                                resource,
+                                value,
+                                limit,
+                                action: limits.action,
+                            };
+                            if let Err(e) = app.emit("lers://child/resource_limit", event) {
+                                error!("Failed to emit resource limit: {}", e);
+                            }
+                        }
+                        if limits.action == LimitAction::Kill {
+                            let _ = manager.stop(&target.id);
+                        }
+                    }
+                }
+                manager.set_resources(&target.id, sample.clone());
+                samples.push(sample);
+            }
+            if let Err(e) = app.emit("lers://child/resources", samples) {
+                error!("Failed to emit resource samples: {}", e);
+            }
+        }
+    });
+}
+
+#[cfg(all(test, any(target_os = "linux", target_os = "android")))]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    #[test]
+    fn counts_threads() {
+        let (sender, receiver) = std::sync::mpsc::channel::<()>();
+        let handle = std::thread::spawn(move || {
+            let _ = receiver.recv();
+        });
+        let mut system = System::new();
+        refresh(&mut system);
+        let usage = usage_of(&system, Pid::from_u32(std::process::id()), &HashMap::new());
+        drop(sender);
+        handle.join().unwrap();
+        assert!(usage.threads.is_some_and(|x| x >= 2), "{:?}", usage);
+    }
+
+    #[test]
+    fn samples_process_tree() {
+        let mut child = Command::new("sh")
+            .args(["-c", "sleep 10 & wait"])
+            .spawn()
+            .unwrap();
+        let mut system = System::new();
+        // 等待sh启动sleep
+        let mut result = None;
+        for _ in 0..50 {
+            std::thread::sleep(Duration::from_millis(100));
+            refresh(&mut system);
+            let target = MonitorTarget {
+                id: "1".to_string(),
+                tool: "sh".to_string(),
+                pid: child.id(),
+                started_at: 0,
+                limits: None,
+            };
+            let sample = sample(&system, &target, 5_000);
+            if sample.tree_processes >= 2 {
+                result = Some(sample);
+                break;
+            }
+        }
+        let _ = child.kill();
+        let _ = child.wait();
+        let sample = result.expect("child process not found in tree");
+        assert_eq!(sample.uptime_secs, 5);
+        assert!(sample.tree.rss_bytes > sample.process.rss_bytes);
+        // sh和sleep都是单线程
+        assert_eq!(sample.process.threads, Some(1));
+        assert_eq!(sample.tree.threads, Some(2));
+    }
+}
@@ -0,0 +1,598 @@
+use crate::errors::ProgramError;
+use crate::tools::config_builder::{save_config, Config};
+use crate::tools::profile::save_profiles;
+use crate::tools::{write_file_atomic, ExternalFilePosition, Status};
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::{Aead, OsRng};
+use aes_gcm::{AeadCore, Aes256Gcm, Key, KeyInit, Nonce};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use log::{info, warn};
+use sha2::Sha256;
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
+use tauri::{AppHandle, Manager, State};
+
+/// 脱敏后的占位符
+pub(crate) const REDACTED: &str = "<redacted>";
+/// 加密后的值前缀
+const ENCRYPTED_PREFIX: &str = "enc:v1:";
+/// 系统凭据管理器中的条目
+const KEYRING_SERVICE: &str = "light-p2p";
+const KEYRING_USER: &str = "config-key";
+/// 无凭据管理器时用于派生密钥的口令
+const PASSPHRASE_ENV: &str = "LIGHTP2P_PASSPHRASE";
+const PBKDF2_ROUNDS: u32 = 600_000;
+const NONCE_LEN: usize = 12;
+
+/// 已解锁的配置密钥，未解锁时为空
+static KEY: Mutex<Option<UnlockedKey>> = Mutex::new(None);
+/// 加载、设置或重置密钥时持有，避免并发生成不同的密钥，
+/// 访问凭据管理器和派生密钥较慢，期间不持有KEY
+static KEY_UPDATE: Mutex<()> = Mutex::new(());
+
+/// 凭据管理器不可用时的密钥文件
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum KeyFile {
+    /// 由口令和盐派生密钥，文件中只保存盐
+    Passphrase { salt: String },
+    /// 旧版本在未设置口令时保存的明文随机密钥，只用于读取旧配置，不再新建
+    Random { key: String },
+}
+
+/// 密钥来源
+#[derive(Clone, Copy, PartialEq)]
+enum KeySource {
+    Keyring,
+    Passphrase,
+    /// 旧版本的明文密钥文件，可以解密但拒绝加密，需要设置口令后才能保存密钥
+    Legacy,
+}
+
+#[derive(Clone, Copy)]
+struct UnlockedKey {
+    key: [u8; 32],
+    source: KeySource,
+}
+
+/// 密钥状态，供前端决定是否需要输入口令
+#[derive(serde::Serialize, Clone)]
+pub(crate) struct SecretStatus {
+    /// 配置中的密钥无法解密
+    pub(crate) locked: bool,
+    /// 没有可用于加密的密钥，需要输入或设置口令
+    pub(crate) passphrase_required: bool,
+}
+
+fn decode_key(value: &str) -> Result<[u8; 32], ProgramError> {
+    STANDARD
+        .decode(value)
+        .ok()
+        .and_then(|x| <[u8; 32]>::try_from(x).ok())
+        .ok_or_else(|| ProgramError::SecretFailed("invalid key".to_string()))
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> [u8; 32] {
+    let mut key = [0u8; 32];
+    pbkdf2::pbkdf2_hmac::<Sha256>(passphrase.as_bytes(), salt, PBKDF2_ROUNDS, &mut key);
+    key
+}
+
+fn read_key_file(path: &Path) -> Result<KeyFile, ProgramError> {
+    let content =
+        std::fs::read_to_string(path).map_err(|e| ProgramError::ReadFailed(e.to_string()))?;
+    serde_json::from_str::<KeyFile>(&content).map_err(|e| ProgramError::SecretFailed(e.to_string()))
+}
+
+fn write_key_file(path: &Path, key_file: &KeyFile) -> Result<(), ProgramError> {
+    let content =
+        serde_json::to_vec(key_file).map_err(|e| ProgramError::SecretFailed(e.to_string()))?;
+    write_file_atomic(path, &content)
+        .map_err(|_| ProgramError::WriteFailed(path.to_string_lossy().to_string()))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let _ = std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600));
+    }
+    Ok(())
+}
+
+/// 由口令新建密钥文件内容
+fn new_passphrase_key(passphrase: &str) -> ([u8; 32], KeyFile) {
+    let mut salt = [0u8; 16];
+    OsRng.fill_bytes(&mut salt);
+    let key_file = KeyFile::Passphrase {
+        salt: STANDARD.encode(salt),
+    };
+    (derive_key(passphrase, &salt), key_file)
+}
+
+fn keyring_entry() -> Result<keyring::Entry, keyring::Error> {
+    keyring::Entry::new(KEYRING_SERVICE, KEYRING_USER)
+}
+
+/// 从系统凭据管理器读取或新建密钥
+fn load_keyring_key() -> Result<[u8; 32], keyring::Error> {
+    let entry = keyring_entry()?;
+    match entry.get_password() {
+        Ok(key) => {
+            decode_key(&key).map_err(|e| keyring::Error::Invalid("key".to_string(), e.to_string()))
+        }
+        Err(keyring::Error::NoEntry) => {
+            let key: [u8; 32] = Aes256Gcm::generate_key(OsRng).into();
+            entry.set_password(&STANDARD.encode(key))?;
+            info!("config key stored in credential store");
+            Ok(key)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// 不借助用户输入获取密钥：已有密钥文件时优先使用，否则使用系统凭据管理器，
+/// 都不可用时使用环境变量中的口令，仍没有时需要用户输入口令
+fn load_key() -> Result<UnlockedKey, ProgramError> {
+    let path = ExternalFilePosition::SecretKey.to_string();
+    let path = Path::new(&path);
+    let passphrase = std::env::var(PASSPHRASE_ENV).ok().filter(|x| !x.is_empty());
+    if path.exists() {
+        return match read_key_file(path)? {
+            KeyFile::Passphrase { salt } => {
+                let passphrase = passphrase.ok_or(ProgramError::SecretsLocked)?;
+                let salt = STANDARD
+                    .decode(salt)
+                    .map_err(|e| ProgramError::SecretFailed(e.to_string()))?;
+                Ok(UnlockedKey {
+                    key: derive_key(&passphrase, &salt),
+                    source: KeySource::Passphrase,
+                })
+            }
+            KeyFile::Random { key } => {
+                let key = decode_key(&key)?;
+                // 能写入凭据管理器时直接迁移，删除明文密钥文件
+                match keyring_entry().and_then(|x| x.set_password(&STANDARD.encode(key))) {
+                    Ok(_) => {
+                        info!("legacy key file moved to credential store");
+                        if let Err(e) = std::fs::remove_file(path) {
+                            warn!("Failed to remove legacy key file: {}", e);
+                        }
+                        Ok(UnlockedKey {
+                            key,
+                            source: KeySource::Keyring,
+                        })
+                    }
+                    Err(e) => {
+                        warn!("legacy key file in use, a passphrase is required: {}", e);
+                        Ok(UnlockedKey {
+                            key,
+                            source: KeySource::Legacy,
+                        })
+                    }
+                }
+            }
+        };
+    }
+    match load_keyring_key() {
+        Ok(key) => {
+            return Ok(UnlockedKey {
+                key,
+                source: KeySource::Keyring,
+            })
+        }
+        Err(e) => warn!("credential store unavailable: {}", e),
+    }
+    let passphrase = passphrase.ok_or(ProgramError::SecretsLocked)?;
+    let (key, key_file) = new_passphrase_key(&passphrase);
+    write_key_file(path, &key_file)?;
+    Ok(UnlockedKey {
+        key,
+        source: KeySource::Passphrase,
+    })
+}
+
+fn lock<T>(mutex: &'static Mutex<T>) -> Result<MutexGuard<'static, T>, ProgramError> {
+    mutex
+        .lock()
+        .map_err(|e| ProgramError::SecretFailed(e.to_string()))
+}
+
+/// 获取配置加密密钥，首次调用时加载
+fn key() -> Result<UnlockedKey, ProgramError> {
+    if let Some(key) = *lock(&KEY)? {
+        return Ok(key);
+    }
+    let _update = lock(&KEY_UPDATE)?;
+    // 等待期间可能已由其他线程加载
+    if let Some(key) = *lock(&KEY)? {
+        return Ok(key);
+    }
+    let key = load_key()?;
+    lock(&KEY)?.replace(key);
+    Ok(key)
+}
+
+/// 当前是否缺少可用于加密的密钥
+pub(crate) fn passphrase_required() -> bool {
+    key().map_or(true, |x| x.source == KeySource::Legacy)
+}
+
+/// 用口令解锁或设置口令
+///
+/// 已有口令密钥文件时由口令派生密钥；否则用口令新建密钥文件，旧版本的明文密钥文件随之被替换。
+/// `verify`用于确认新密钥能解密现有配置，失败时不保存任何内容。
+pub(crate) fn unlock(
+    passphrase: &str,
+    verify: impl Fn(&[u8; 32]) -> bool,
+) -> Result<(), ProgramError> {
+    if passphrase.is_empty() {
+        return Err(ProgramError::SecretFailed("empty passphrase".to_string()));
+    }
+    let path = ExternalFilePosition::SecretKey.to_string();
+    let path = Path::new(&path);
+    let _update = lock(&KEY_UPDATE)?;
+    let (key, key_file) = match path.exists().then(|| read_key_file(path)).transpose()? {
+        Some(KeyFile::Passphrase { salt }) => {
+            let salt = STANDARD
+                .decode(salt)
+                .map_err(|e| ProgramError::SecretFailed(e.to_string()))?;
+            (derive_key(passphrase, &salt), None)
+        }
+        _ => {
+            let (key, key_file) = new_passphrase_key(passphrase);
+            (key, Some(key_file))
+        }
+    };
+    if !verify(&key) {
+        return Err(ProgramError::SecretFailed("wrong passphrase".to_string()));
+    }
+    if let Some(key_file) = key_file {
+        // 邀请密钥由旧密钥加密，能解密时重新加密，否则删除后重新生成
+        let invite_key = ExternalFilePosition::InviteKey.to_string();
+        let invite_key = Path::new(&invite_key);
+        if invite_key.exists() {
+            let old = *lock(&KEY)?;
+            let content = std::fs::read_to_string(invite_key)
+                .ok()
+                .zip(old)
+                .and_then(|(x, old)| decrypt_with(&old.key, x.trim()).ok())
+                .map(|x| encrypt_with(&key, &x))
+                .transpose()?;
+            match content {
+                Some(content) => {
+                    write_file_atomic(invite_key, content.as_bytes()).map_err(|_| {
+                        ProgramError::WriteFailed(invite_key.to_string_lossy().to_string())
+                    })?
+                }
+                None => {
+                    let _ = std::fs::remove_file(invite_key);
+                }
+            }
+        }
+        write_key_file(path, &key_file)?;
+        info!("passphrase key file created");
+    }
+    lock(&KEY)?.replace(UnlockedKey {
+        key,
+        source: KeySource::Passphrase,
+    });
+    Ok(())
+}
+
+/// 放弃无法解密的密钥，删除密钥文件、邀请密钥和凭据管理器中的条目
+pub(crate) fn reset() -> Result<(), ProgramError> {
+    let _update = lock(&KEY_UPDATE)?;
+    for path in [
+        ExternalFilePosition::SecretKey,
+        ExternalFilePosition::InviteKey,
+    ] {
+        let path = path.to_string();
+        if let Err(e) = std::fs::remove_file(&path) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                return Err(ProgramError::WriteFailed(format!("{}: {}", path, e)));
+            }
+        }
+    }
+    if let Err(e) = keyring_entry().and_then(|x| x.delete_credential()) {
+        warn!("Failed to delete key from credential store: {}", e);
+    }
+    lock(&KEY)?.take();
+    warn!("config key reset");
+    Ok(())
+}
+
+fn encrypt_with(key: &[u8; 32], value: &str) -> Result<String, ProgramError> {
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let mut data = nonce.to_vec();
+    data.extend(
+        cipher
+            .encrypt(&nonce, value.as_bytes())
+            .map_err(|e| ProgramError::SecretFailed(e.to_string()))?,
+    );
+    Ok(format!("{}{}", ENCRYPTED_PREFIX, STANDARD.encode(data)))
+}
+
+fn decrypt_with(key: &[u8; 32], value: &str) -> Result<String, ProgramError> {
+    let Some(data) = value.strip_prefix(ENCRYPTED_PREFIX) else {
+        return Ok(value.to_string());
+    };
+    let data = STANDARD
+        .decode(data)
+        .map_err(|e| ProgramError::SecretFailed(e.to_string()))?;
+    if data.len() < NONCE_LEN {
+        return Err(ProgramError::SecretFailed("invalid ciphertext".to_string()));
+    }
+    let (nonce, data) = data.split_at(NONCE_LEN);
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
+    let plain = cipher
+        .decrypt(Nonce::from_slice(nonce), data)
+        .map_err(|e| ProgramError::SecretFailed(e.to_string()))?;
+    String::from_utf8(plain).map_err(|e| ProgramError::SecretFailed(e.to_string()))
+}
+
+/// 加密字符串，已加密的值原样返回，明文密钥文件不用于加密
+pub(crate) fn encrypt(value: &str) -> Result<String, ProgramError> {
+    if is_encrypted(value) {
+        return Ok(value.to_string());
+    }
+    let key = key()?;
+    if key.source == KeySource::Legacy {
+        return Err(ProgramError::SecretsLocked);
+    }
+    encrypt_with(&key.key, value)
+}
+
+/// 解密字符串，未加密的值原样返回
+pub(crate) fn decrypt(value: &str) -> Result<String, ProgramError> {
+    if !is_encrypted(value) {
+        return Ok(value.to_string());
+    }
+    decrypt_with(&key()?.key, value)
+}
+
+/// 判断配置中已加密的密钥能否用指定密钥解密
+pub(crate) fn can_decrypt(config: &Config, key: &[u8; 32]) -> bool {
+    [Some(&config.token), config.password.as_ref()]
+        .into_iter()
+        .flatten()
+        .filter(|x| is_encrypted(x))
+        .all(|x| decrypt_with(key, x).is_ok())
+}
+
+pub(crate) fn is_encrypted(value: &str) -> bool {
+    value.starts_with(ENCRYPTED_PREFIX)
+}
+
+/// 生成用于落盘的配置副本，密钥字段加密
+pub(crate) fn encrypt_config(config: &Config) -> Result<Config, ProgramError> {
+    let mut config = config.clone();
+    config.token = encrypt(&config.token)?;
+    if let Some(password) = &config.password {
+        config.password = Some(encrypt(password)?);
+    }
+    Ok(config)
+}
+
+/// 解密读取到的配置，返回是否存在明文密钥需要重新保存
+pub(crate) fn decrypt_config(config: &mut Config) -> Result<bool, ProgramError> {
+    let mut plain = false;
+    if is_encrypted(&config.token) {
+        config.token = decrypt(&config.token)?;
+    } else {
+        plain = true;
+    }
+    if let Some(password) = &config.password {
+        if is_encrypted(password) {
+            config.password = Some(decrypt(password)?);
+        } else {
+            plain = true;
+        }
+    }
+    if plain {
+        info!("plain text secrets found in config, they will be encrypted");
+    }
+    Ok(plain)
+}
+
+/// 替换配置中的密钥，避免明文返回给前端
+pub(crate) fn redact(config: &mut Config) {
+    config.token = REDACTED.to_string();
+    if config.password.is_some() {
+        config.password = Some(REDACTED.to_string());
+    }
+}
+
+pub(crate) fn is_redacted(config: &Config) -> bool {
+    config.token == REDACTED || config.password.as_deref() == Some(REDACTED)
+}
+
+/// 用已有配置中的密钥补全脱敏字段，没有可用密钥时置空
+pub(crate) fn restore_secrets(config: &mut Config, exist: Option<&Config>) {
+    if config.token == REDACTED {
+        config.token = exist.map(|x| x.token.clone()).unwrap_or_default();
+    }
+    if config.password.as_deref() == Some(REDACTED) {
+        config.password = exist.and_then(|x| x.password.clone());
+    }
+}
+
+/// 用口令解锁后解密内存中的配置，并用当前密钥重新保存
+fn unlock_config(app: &AppHandle, passphrase: &str) -> Result<(), ProgramError> {
+    // 派生密钥较慢，期间不持有锁
+    let configs = match app.state::<Mutex<Status>>().lock() {
+        Ok(status) => std::iter::once(status.config.clone())
+            .chain(status.profiles.iter().map(|x| x.config.clone()))
+            .collect::<Vec<_>>(),
+        Err(e) => return Err(ProgramError::ReadFailed(e.to_string())),
+    };
+    unlock(passphrase, |key| {
+        configs.iter().all(|x| can_decrypt(x, key))
+    })?;
+    let binding = app.state::<Mutex<Status>>();
+    let mut status = binding
+        .lock()
+        .map_err(|e| ProgramError::ReadFailed(e.to_string()))?;
+    if status.secrets_locked {
+        decrypt_config(&mut status.config)?;
+        for profile in status.profiles.iter_mut() {
+            decrypt_config(&mut profile.config)?;
+        }
+        status.secrets_locked = false;
+    }
+    save_config(app, &status.config)?;
+    save_profiles(app, &status.profiles)
+}
+
+/// 重置密钥，清空无法解密的token和密码，可同时设置新口令
+fn reset_config_secrets(app: &AppHandle, passphrase: Option<String>) -> Result<(), ProgramError> {
+    reset()?;
+    if let Some(passphrase) = passphrase.filter(|x| !x.is_empty()) {
+        unlock(&passphrase, |_| true)?;
+    }
+    let binding = app.state::<Mutex<Status>>();
+    let mut status = binding
+        .lock()
+        .map_err(|e| ProgramError::ReadFailed(e.to_string()))?;
+    let status = &mut *status;
+    for config in
+        std::iter::once(&mut status.config).chain(status.profiles.iter_mut().map(|x| &mut x.config))
+    {
+        if is_encrypted(&config.token) {
+            config.token = String::new();
+        }
+        if config.password.as_deref().is_some_and(is_encrypted) {
+            config.password = None;
+        }
+    }
+    status.secrets_locked = false;
+    save_config(app, &status.config)?;
+    save_profiles(app, &status.profiles)
+}
+
+/// 获取密钥状态
+#[tauri::command]
+pub(crate) fn secret_status(status: State<'_, Mutex<Status>>) -> Result<SecretStatus, String> {
+    let locked = match status.lock() {
+        Ok(status) => status.secrets_locked,
+        Err(e) => return Err(ProgramError::ReadFailed(e.to_string()).to_string()),
+    };
+    Ok(SecretStatus {
+        locked,
+        passphrase_required: passphrase_required(),
+    })
+}
+
+/// 用口令解锁配置中的密钥，没有可用密钥时以该口令新建
+#[tauri::command]
+pub(crate) async fn unlock_secrets(app: AppHandle, passphrase: String) -> Result<(), String> {
+    tauri::async_runtime::spawn_blocking(move || unlock_config(&app, &passphrase))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())
+}
+
+/// 无法解锁时重置密钥，已保存的token和组网密码需要重新填写
+#[tauri::command]
+pub(crate) async fn reset_secrets(
+    app: AppHandle,
+    passphrase: Option<String>,
+) -> Result<(), String> {
+    tauri::async_runtime::spawn_blocking(move || reset_config_secrets(&app, passphrase))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY_A: [u8; 32] = [1; 32];
+    const KEY_B: [u8; 32] = [2; 32];
+
+    fn config(token: &str, password: Option<&str>) -> Config {
+        Config {
+            token: token.to_string(),
+            password: password.map(str::to_string),
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn round_trips_encrypted_values() {
+        for value in ["", "token", "组网密码"] {
+            let encrypted = encrypt_with(&KEY_A, value).unwrap();
+            assert!(is_encrypted(&encrypted));
+            assert!(!encrypted.contains(value) || value.is_empty());
+            assert_eq!(decrypt_with(&KEY_A, &encrypted).unwrap(), value);
+        }
+        // 每次加密使用不同的nonce
+        assert_ne!(
+            encrypt_with(&KEY_A, "token").unwrap(),
+            encrypt_with(&KEY_A, "token").unwrap()
+        );
+        // 未加密的值原样返回
+        assert_eq!(decrypt_with(&KEY_A, "plain").unwrap(), "plain");
+    }
+
+    #[test]
+    fn rejects_tampered_values() {
+        let encrypted = encrypt_with(&KEY_A, "token").unwrap();
+        assert!(decrypt_with(&KEY_B, &encrypted).is_err());
+        let mut data = STANDARD
+            .decode(encrypted.strip_prefix(ENCRYPTED_PREFIX).unwrap())
+            .unwrap();
+        let last = data.len() - 1;
+        data[last] ^= 1;
+        let tampered = format!("{}{}", ENCRYPTED_PREFIX, STANDARD.encode(&data));
+        assert!(decrypt_with(&KEY_A, &tampered).is_err());
+        let truncated = format!("{}{}", ENCRYPTED_PREFIX, STANDARD.encode(&data[..8]));
+        assert!(decrypt_with(&KEY_A, &truncated).is_err());
+        assert!(decrypt_with(&KEY_A, &format!("{}!", ENCRYPTED_PREFIX)).is_err());
+    }
+
+    #[test]
+    fn redacts_and_restores_secrets() {
+        let exist = config("token", Some("password"));
+        let mut redacted = exist.clone();
+        redact(&mut redacted);
+        assert!(is_redacted(&redacted));
+        assert_eq!(redacted.token, REDACTED);
+        assert_eq!(redacted.password.as_deref(), Some(REDACTED));
+        restore_secrets(&mut redacted, Some(&exist));
+        assert_eq!(redacted.token, "token");
+        assert_eq!(redacted.password.as_deref(), Some("password"));
+        // 没有密码时不会凭空添加
+        let mut no_password = config("token", None);
+        redact(&mut no_password);
+        assert_eq!(no_password.password, None);
+        // 没有可用密钥时置空
+        let mut orphan = config(REDACTED, Some(REDACTED));
+        restore_secrets(&mut orphan, None);
+        assert_eq!(orphan.token, "");
+        assert_eq!(orphan.password, None);
+        // 未脱敏的字段保持不变
+        let mut edited = config("new token", Some(REDACTED));
+        restore_secrets(&mut edited, Some(&exist));
+        assert_eq!(edited.token, "new token");
+        assert_eq!(edited.password.as_deref(), Some("password"));
+    }
+
+    #[test]
+    fn checks_decryptable_config() {
+        let encrypted = config(
+            &encrypt_with(&KEY_A, "token").unwrap(),
+            Some(&encrypt_with(&KEY_A, "password").unwrap()),
+        );
+        assert!(can_decrypt(&encrypted, &KEY_A));
+        assert!(!can_decrypt(&encrypted, &KEY_B));
+        // 只有密码由其他密钥加密
+        let mixed = config(
+            &encrypt_with(&KEY_A, "token").unwrap(),
+            Some(&encrypt_with(&KEY_B, "password").unwrap()),
+        );
+        assert!(!can_decrypt(&mixed, &KEY_A));
+        // 明文配置对任意密钥都可用
+        assert!(can_decrypt(&config("token", None), &KEY_B));
+    }
+}
@@ -0,0 +1,92 @@
+//! 发布内容的签名校验
+//!
+//! 工具目录等由发布方下发的清单使用Ed25519签名，公钥在构建时通过环境变量
+//! `LERS_SIGNING_PUBLIC_KEYS`写入程序，多个公钥以逗号分隔，便于轮换密钥。
+//! 未配置公钥的构建拒绝一切签名内容。
+
+use crate::errors::ProgramError;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use ed25519_dalek::{Signature, VerifyingKey};
+
+/// 可信公钥，base64编码
+const PUBLIC_KEYS: Option<&str> = option_env!("LERS_SIGNING_PUBLIC_KEYS");
+
+/// 签名信封，payload为原始清单的base64编码，签名针对解码后的字节
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub(crate) struct SignedEnvelope {
+    pub(crate) payload: String,
+    pub(crate) signature: String,
+}
+
+fn trusted_keys() -> Vec<VerifyingKey> {
+    let keys = PUBLIC_KEYS
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(|key| {
+            let bytes = STANDARD.decode(key.trim()).ok()?;
+            VerifyingKey::from_bytes(&bytes.try_into().ok()?).ok()
+        });
+    #[cfg(test)]
+    let keys = keys.chain(std::iter::once(test_key().verifying_key()));
+    keys.collect()
+}
+
+/// 校验`message`的签名，任一可信公钥验证通过即可
+pub(crate) fn verify(message: &[u8], signature: &str) -> Result<(), ProgramError> {
+    let keys = trusted_keys();
+    if keys.is_empty() {
+        return Err(ProgramError::SignatureInvalid(
+            "no signing key is configured in this build".to_string(),
+        ));
+    }
+    let signature = STANDARD
+        .decode(signature.trim())
+        .ok()
+        .and_then(|x| Signature::from_slice(&x).ok())
+        .ok_or_else(|| ProgramError::SignatureInvalid("malformed signature".to_string()))?;
+    if keys
+        .iter()
+        .any(|key| key.verify_strict(message, &signature).is_ok())
+    {
+        Ok(())
+    } else {
+        Err(ProgramError::SignatureInvalid(
+            "signature does not match any trusted key".to_string(),
+        ))
+    }
+}
+
+/// 解析并校验签名信封，返回清单原文
+pub(crate) fn open_envelope(content: &[u8]) -> Result<Vec<u8>, ProgramError> {
+    let envelope = serde_json::from_slice::<SignedEnvelope>(content)
+        .map_err(|e| ProgramError::SignatureInvalid(e.to_string()))?;
+    let payload = STANDARD
+        .decode(envelope.payload.trim())
+        .map_err(|e| ProgramError::SignatureInvalid(e.to_string()))?;
+    verify(&payload, &envelope.signature)?;
+    Ok(payload)
+}
+
+/// 测试用的签名密钥，只在测试构建中受信任
+#[cfg(test)]
+pub(crate) fn test_key() -> ed25519_dalek::SigningKey {
+    ed25519_dalek::SigningKey::from_bytes(&[7u8; 32])
+}
+
+/// 用指定密钥签名，返回base64编码的签名
+#[cfg(test)]
+pub(crate) fn sign_with(key: &ed25519_dalek::SigningKey, message: &[u8]) -> String {
+    use ed25519_dalek::Signer;
+    STANDARD.encode(key.sign(message).to_bytes())
+}
+
+/// 用指定密钥把清单封装为签名信封
+#[cfg(test)]
+pub(crate) fn envelope_with(key: &ed25519_dalek::SigningKey, payload: &[u8]) -> Vec<u8> {
+    serde_json::to_vec(&SignedEnvelope {
+        payload: STANDARD.encode(payload),
+        signature: sign_with(key, payload),
+    })
+    .unwrap()
+}
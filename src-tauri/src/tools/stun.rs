@@ -0,0 +1,431 @@
+use crate::tools::Status;
+use log::{error, info};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, Manager, State};
+
+/// 未配置stun服务器时使用的公共服务器
+pub(crate) const PUB_STUN: [&str; 3] =
+    ["stun.miwifi.com", "stun.chat.bilibili.com", "stun.hitv.com"];
+const DEFAULT_STUN_PORT: u16 = 3478;
+/// 单次请求超时
+const STUN_TIMEOUT: Duration = Duration::from_millis(1000);
+const STUN_RETRIES: usize = 2;
+/// 探测结果缓存时间
+const CACHE_TTL: Duration = Duration::from_secs(600);
+/// 最多选用的stun服务器数量
+const MAX_SELECTED: usize = 3;
+
+const MAGIC_COOKIE: u32 = 0x2112_A442;
+const BINDING_REQUEST: u16 = 0x0001;
+const BINDING_SUCCESS: u16 = 0x0101;
+const ATTR_MAPPED_ADDRESS: u16 = 0x0001;
+const ATTR_CHANGE_REQUEST: u16 = 0x0003;
+const ATTR_CHANGED_ADDRESS: u16 = 0x0005;
+const ATTR_XOR_MAPPED_ADDRESS: u16 = 0x0020;
+const ATTR_OTHER_ADDRESS: u16 = 0x802C;
+
+/// stun绑定请求的响应
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct BindingResponse {
+    /// 服务器看到的本机公网地址
+    pub(crate) mapped: SocketAddr,
+    /// 服务器的备用地址，用于判断nat行为
+    pub(crate) other: Option<SocketAddr>,
+    /// 实际响应的地址
+    pub(crate) responder: SocketAddr,
+}
+
+/// 单个stun服务器的探测结果
+#[derive(serde::Serialize, Clone, Debug)]
+pub(crate) struct StunProbe {
+    pub(crate) server: String,
+    pub(crate) reachable: bool,
+    pub(crate) mapped_address: Option<String>,
+    pub(crate) latency_ms: Option<u64>,
+    pub(crate) error: Option<String>,
+}
+
+/// stun探测结果缓存
+#[derive(Default)]
+pub(crate) struct StunCache(pub(crate) Mutex<Option<(Instant, Vec<StunProbe>)>>);
+
+fn encode_request(transaction: &[u8; 12], change_ip: bool, change_port: bool) -> Vec<u8> {
+    let mut attrs = vec![];
+    if change_ip || change_port {
+        let flags = (change_ip as u32) << 2 | (change_port as u32) << 1;
+        attrs.extend(ATTR_CHANGE_REQUEST.to_be_bytes());
+        attrs.extend(4u16.to_be_bytes());
+        attrs.extend(flags.to_be_bytes());
+    }
+    let mut message = Vec::with_capacity(20 + attrs.len());
+    message.extend(BINDING_REQUEST.to_be_bytes());
+    message.extend((attrs.len() as u16).to_be_bytes());
+    message.extend(MAGIC_COOKIE.to_be_bytes());
+    message.extend(transaction);
+    message.extend(attrs);
+    message
+}
+
+/// 解析地址类属性，xor为true时按XOR-MAPPED-ADDRESS解码
+fn decode_address(value: &[u8], xor: Option<&[u8; 12]>) -> Option<SocketAddr> {
+    if value.len() < 4 {
+        return None;
+    }
+    let cookie = MAGIC_COOKIE.to_be_bytes();
+    let mut port = u16::from_be_bytes([value[2], value[3]]);
+    if xor.is_some() {
+        port ^= (MAGIC_COOKIE >> 16) as u16;
+    }
+    let ip = match value[1] {
+        0x01 if value.len() >= 8 => {
+            let mut octets = [value[4], value[5], value[6], value[7]];
+            if xor.is_some() {
+                octets.iter_mut().zip(cookie).for_each(|(x, y)| *x ^= y);
+            }
+            IpAddr::V4(Ipv4Addr::from(octets))
+        }
+        0x02 if value.len() >= 20 => {
+            let mut octets = [0u8; 16];
+            octets.copy_from_slice(&value[4..20]);
+            if let Some(transaction) = xor {
+                let key = cookie.iter().chain(transaction.iter());
+                octets.iter_mut().zip(key).for_each(|(x, y)| *x ^= y);
+            }
+            IpAddr::V6(Ipv6Addr::from(octets))
+        }
+        _ => return None,
+    };
+    Some(SocketAddr::new(ip, port))
+}
+
+fn decode_response(
+    data: &[u8],
+    transaction: &[u8; 12],
+    responder: SocketAddr,
+) -> Option<BindingResponse> {
+    if data.len() < 20
+        || u16::from_be_bytes([data[0], data[1]]) != BINDING_SUCCESS
+        || data[8..20] != transaction[..]
+    {
+        return None;
+    }
+    let length = u16::from_be_bytes([data[2], data[3]]) as usize;
+    let body = data.get(20..20 + length)?;
+    let (mut mapped, mut xor_mapped, mut other) = (None, None, None);
+    let mut offset = 0;
+    while offset + 4 <= body.len() {
+        let attr = u16::from_be_bytes([body[offset], body[offset + 1]]);
+        let len = u16::from_be_bytes([body[offset + 2], body[offset + 3]]) as usize;
+        let value = body.get(offset + 4..offset + 4 + len)?;
+        match attr {
+            ATTR_MAPPED_ADDRESS => mapped = decode_address(value, None),
+            ATTR_XOR_MAPPED_ADDRESS => xor_mapped = decode_address(value, Some(transaction)),
+            ATTR_OTHER_ADDRESS | ATTR_CHANGED_ADDRESS => other = decode_address(value, None),
+            _ => {}
+        }
+        // 属性按4字节对齐
+        offset += 4 + len.div_ceil(4) * 4;
+    }
+    Some(BindingResponse {
+        mapped: xor_mapped.or(mapped)?,
+        other,
+        responder,
+    })
+}
+
+/// 发送stun绑定请求，超时后重试
+pub(crate) fn binding_request(
+    socket: &UdpSocket,
+    server: SocketAddr,
+    change_ip: bool,
+    change_port: bool,
+) -> std::io::Result<BindingResponse> {
+    let mut transaction = [0u8; 12];
+    transaction.copy_from_slice(&uuid::Uuid::new_v4().as_bytes()[..12]);
+    let request = encode_request(&transaction, change_ip, change_port);
+    socket.set_read_timeout(Some(STUN_TIMEOUT))?;
+    let mut buf = [0u8; 1024];
+    for _ in 0..STUN_RETRIES {
+        socket.send_to(&request, server)?;
+        let deadline = Instant::now() + STUN_TIMEOUT;
+        // 丢弃不属于本次请求的报文
+        while Instant::now() < deadline {
+            match socket.recv_from(&mut buf) {
+                Ok((len, from)) => {
+                    if let Some(response) = decode_response(&buf[..len], &transaction, from) {
+                        return Ok(response);
+                    }
+                }
+                Err(e)
+                    if e.kind() == std::io::ErrorKind::WouldBlock
+                        || e.kind() == std::io::ErrorKind::TimedOut =>
+                {
+                    break
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+    Err(std::io::Error::new(
+        std::io::ErrorKind::TimedOut,
+        "stun request timed out",
+    ))
+}
+
+/// 解析stun服务器地址，未指定端口时使用3478
+pub(crate) fn resolve_stun(server: &str, ipv6: bool) -> std::io::Result<SocketAddr> {
+    let server = server.trim();
+    let has_port = match server.rsplit_once(':') {
+        Some((host, port)) => {
+            port.parse::<u16>().is_ok() && (!host.contains(':') || host.ends_with(']'))
+        }
+        None => false,
+    };
+    let addrs = if has_port {
+        server.to_socket_addrs()?
+    } else {
+        (
+            server.trim_start_matches('[').trim_end_matches(']'),
+            DEFAULT_STUN_PORT,
+        )
+            .to_socket_addrs()?
+    };
+    addrs
+        .into_iter()
+        .find(|x| x.is_ipv6() == ipv6)
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "no address found"))
+}
+
+/// 探测单个stun服务器
+pub(crate) fn probe_stun(server: &str) -> StunProbe {
+    let mut probe = StunProbe {
+        server: server.to_string(),
+        reachable: false,
+        mapped_address: None,
+        latency_ms: None,
+        error: None,
+    };
+    let result = resolve_stun(server, false).and_then(|addr| {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+        let start = Instant::now();
+        let response = binding_request(&socket, addr, false, false)?;
+        Ok((response, start.elapsed()))
+    });
+    match result {
+        Ok((response, latency)) => {
+            probe.reachable = true;
+            probe.mapped_address = Some(response.mapped.to_string());
+            probe.latency_ms = Some(latency.as_millis() as u64);
+        }
+        Err(e) => probe.error = Some(e.to_string()),
+    }
+    probe
+}
+
+/// 并行探测stun服务器，可达的按延迟排序
+pub(crate) fn probe_stun_servers(servers: &[String]) -> Vec<StunProbe> {
+    let mut probes = std::thread::scope(|scope| {
+        servers
+            .iter()
+            .map(|server| scope.spawn(move || probe_stun(server)))
+            .collect::<Vec<_>>()
+            .into_iter()
+            .filter_map(|handle| handle.join().ok())
+            .collect::<Vec<_>>()
+    });
+    probes.sort_by_key(|x| (!x.reachable, x.latency_ms.unwrap_or(u64::MAX)));
+    probes
+}
+
+/// 获取探测结果，缓存过期、候选列表变化或要求刷新时重新探测
+pub(crate) fn stun_probes(app: &AppHandle, candidates: &[String], refresh: bool) -> Vec<StunProbe> {
+    let cache = app.state::<StunCache>();
+    if !refresh {
+        if let Ok(cache) = cache.0.lock() {
+            if let Some((time, probes)) = cache.as_ref() {
+                let same = probes.len() == candidates.len()
+                    && probes.iter().all(|x| candidates.contains(&x.server));
+                if same && time.elapsed() < CACHE_TTL {
+                    return probes.clone();
+                }
+            }
+        }
+    }
+    let probes = probe_stun_servers(candidates);
+    info!("stun probes: {:?}", probes);
+    if let Ok(mut cache) = cache.0.lock() {
+        cache.replace((Instant::now(), probes.clone()));
+    }
+    if let Err(e) = app.emit("lers://stun/probes", probes.clone()) {
+        error!("Failed to emit stun probes: {}", e);
+    }
+    probes
+}
+
+/// 选出可用的stun服务器，全部不可用时保留原列表交给vnt处理
+pub(crate) fn select_stun_servers(app: &AppHandle, candidates: &[String]) -> Vec<String> {
+    let selected = stun_probes(app, candidates, false)
+        .into_iter()
+        .filter(|x| x.reachable)
+        .take(MAX_SELECTED)
+        .map(|x| x.server)
+        .collect::<Vec<_>>();
+    if selected.is_empty() {
+        candidates.to_vec()
+    } else {
+        selected
+    }
+}
+
+/// 获取stun服务器探测结果
+#[tauri::command]
+pub(crate) fn get_stun_probes(
+    app: AppHandle,
+    refresh: bool,
+    status: State<'_, Mutex<Status>>,
+) -> Result<Vec<StunProbe>, String> {
+    let candidates = match status.lock() {
+        Ok(status) => status.config.stun_candidates(),
+        Err(e) => return Err(e.to_string()),
+    };
+    Ok(stun_probes(&app, &candidates, refresh))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 编码地址类属性
+    fn encode_address(attr: u16, addr: SocketAddr, transaction: Option<&[u8]>) -> Vec<u8> {
+        let cookie = MAGIC_COOKIE.to_be_bytes();
+        let (family, mut octets) = match addr.ip() {
+            IpAddr::V4(ip) => (0x01u8, ip.octets().to_vec()),
+            IpAddr::V6(ip) => (0x02u8, ip.octets().to_vec()),
+        };
+        let mut port = addr.port();
+        if let Some(transaction) = transaction {
+            port ^= (MAGIC_COOKIE >> 16) as u16;
+            let key = cookie.iter().chain(transaction.iter());
+            octets.iter_mut().zip(key).for_each(|(x, y)| *x ^= y);
+        }
+        let mut value = vec![0, family];
+        value.extend(port.to_be_bytes());
+        value.extend(octets);
+        let mut data = attr.to_be_bytes().to_vec();
+        data.extend((value.len() as u16).to_be_bytes());
+        data.extend(value);
+        data
+    }
+
+    /// 按请求中的事务id构造绑定响应
+    fn encode_response(request: &[u8], attrs: &[u8]) -> Vec<u8> {
+        let mut message = BINDING_SUCCESS.to_be_bytes().to_vec();
+        message.extend((attrs.len() as u16).to_be_bytes());
+        message.extend(&request[4..20]);
+        message.extend(attrs);
+        message
+    }
+
+    /// 本地stun服务器，先回复一个无关事务的响应，再回复正确的响应
+    fn responder(other: SocketAddr) -> SocketAddr {
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let addr = socket.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let mut buf = [0u8; 1024];
+            let (len, from) = socket.recv_from(&mut buf).unwrap();
+            let request = &buf[..len];
+            assert_eq!(
+                u16::from_be_bytes([request[0], request[1]]),
+                BINDING_REQUEST
+            );
+            let mut stale = request[..20].to_vec();
+            stale[8] ^= 0xff;
+            let attrs = encode_address(ATTR_XOR_MAPPED_ADDRESS, other, Some(&stale[8..20]));
+            socket
+                .send_to(&encode_response(&stale, &attrs), from)
+                .unwrap();
+            let mut attrs = encode_address(ATTR_XOR_MAPPED_ADDRESS, from, Some(&request[8..20]));
+            attrs.extend(encode_address(ATTR_OTHER_ADDRESS, other, None));
+            socket
+                .send_to(&encode_response(request, &attrs), from)
+                .unwrap();
+        });
+        addr
+    }
+
+    #[test]
+    fn binding_request_parses_xor_mapped_address() {
+        let other = SocketAddr::from(([127, 0, 0, 2], 3479));
+        let server = responder(other);
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let response = binding_request(&socket, server, false, false).unwrap();
+        assert_eq!(response.mapped, socket.local_addr().unwrap());
+        assert_eq!(response.other, Some(other));
+        assert_eq!(response.responder, server);
+    }
+
+    #[test]
+    fn binding_request_times_out() {
+        let server = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let start = Instant::now();
+        let result = binding_request(&socket, server.local_addr().unwrap(), false, false);
+        assert_eq!(result.unwrap_err().kind(), std::io::ErrorKind::TimedOut);
+        assert!(start.elapsed() >= STUN_TIMEOUT * STUN_RETRIES as u32);
+        // 每次重试都重新发送请求
+        server.set_nonblocking(true).unwrap();
+        let mut buf = [0u8; 1024];
+        let received = std::iter::from_fn(|| server.recv_from(&mut buf).ok()).count();
+        assert_eq!(received, STUN_RETRIES);
+    }
+
+    #[test]
+    fn decodes_ipv6_and_mapped_address() {
+        let transaction = [7u8; 12];
+        let request = encode_request(&transaction, false, false);
+        let responder = SocketAddr::from(([127, 0, 0, 1], 3478));
+        let v6 = "[2001:db8::1]:40000".parse::<SocketAddr>().unwrap();
+        let attrs = encode_address(ATTR_XOR_MAPPED_ADDRESS, v6, Some(&transaction));
+        let response = decode_response(&encode_response(&request, &attrs), &transaction, responder);
+        assert_eq!(response.unwrap().mapped, v6);
+        // 旧服务器只返回MAPPED-ADDRESS
+        let v4 = SocketAddr::from(([203, 0, 113, 1], 50000));
+        let attrs = encode_address(ATTR_MAPPED_ADDRESS, v4, None);
+        let response = decode_response(&encode_response(&request, &attrs), &transaction, responder);
+        assert_eq!(response.unwrap().mapped, v4);
+        // 属性长度超出报文时丢弃
+        let mut data = encode_response(&request, &attrs);
+        data.truncate(data.len() - 2);
+        assert!(decode_response(&data, &transaction, responder).is_none());
+    }
+
+    #[test]
+    fn encodes_change_request() {
+        let request = encode_request(&[0u8; 12], true, true);
+        assert_eq!(u16::from_be_bytes([request[2], request[3]]), 8);
+        assert_eq!(
+            u16::from_be_bytes([request[20], request[21]]),
+            ATTR_CHANGE_REQUEST
+        );
+        assert_eq!(request[27], 0x06);
+    }
+
+    #[test]
+    fn resolves_default_port() {
+        assert_eq!(
+            resolve_stun("127.0.0.1", false).unwrap(),
+            SocketAddr::from(([127, 0, 0, 1], DEFAULT_STUN_PORT))
+        );
+        assert_eq!(
+            resolve_stun("127.0.0.1:19302", false).unwrap().port(),
+            19302
+        );
+        assert_eq!(
+            resolve_stun("[::1]", true).unwrap().port(),
+            DEFAULT_STUN_PORT
+        );
+        assert_eq!(resolve_stun("[::1]:3479", true).unwrap().port(), 3479);
+    }
+}
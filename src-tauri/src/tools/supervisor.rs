@@ -0,0 +1,617 @@
+//! 受监管的常驻工具
+//!
+//! 每个工具至多一个监管实例，由独立线程负责启动、健康检查和按策略重启，
+//! 状态变化通过`lers://supervisor/state`事件通知前端。
+
+use crate::errors::{ProgramError, SpawnError};
+use crate::tools::command::{spawn_tool, ChildrenManager, CommandEvent, EventSink, SpawnRequest};
+use crate::tools::config_builder::persist_store_value;
+use crate::tools::migration::SUPERVISED_KEY;
+use crate::tools::tool_registry::ToolRegistry;
+use crate::tools::Status;
+use log::{error, info, warn};
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, SocketAddr, TcpStream};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::ipc::Channel;
+use tauri::{AppHandle, Emitter, Manager, State};
+
+/// 首次重启前的等待时间，之后每次翻倍
+const BACKOFF_INITIAL: Duration = Duration::from_secs(1);
+const BACKOFF_MAX: Duration = Duration::from_secs(60);
+/// 运行超过该时间后视为稳定，重置退避
+const STABLE_AFTER: Duration = Duration::from_secs(60);
+/// 健康检查间隔
+const HEALTH_INTERVAL: Duration = Duration::from_secs(2);
+/// 启动后的宽限期，期间的检查失败不计入
+const HEALTH_GRACE: Duration = Duration::from_secs(15);
+/// 连续失败多少次后判定为不健康
+const HEALTH_FAILURES: u32 = 3;
+const TCP_CHECK_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// 重启策略
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum RestartPolicy {
+    #[default]
+    Never,
+    /// 非零退出、被信号结束或健康检查失败时重启
+    OnFailure,
+    Always,
+}
+
+/// 健康检查方式
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub(crate) enum HealthCheck {
+    /// 定期连接本机端口
+    TcpPort { port: u16 },
+    /// 宽限期内标准输出中出现指定内容视为就绪
+    StdoutPattern { pattern: String },
+}
+
+/// 监管定义
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub(crate) struct SupervisedTool {
+    #[serde(flatten)]
+    pub(crate) request: SpawnRequest,
+    #[serde(default)]
+    pub(crate) restart: RestartPolicy,
+    #[serde(default)]
+    pub(crate) health: Option<HealthCheck>,
+    /// 随vnt会话启动和停止
+    #[serde(default)]
+    pub(crate) with_vnt: bool,
+}
+
+/// 监管状态
+#[derive(serde::Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ServiceState {
+    Stopped,
+    Starting,
+    /// 运行中，未配置健康检查或尚未通过检查
+    Running,
+    Healthy,
+    Unhealthy,
+    /// 等待重启
+    Backoff,
+    /// 无法启动且不再重试
+    Failed,
+}
+
+/// 最近一次退出
+#[derive(serde::Serialize, Clone, Copy, Debug)]
+pub(crate) struct ServiceExit {
+    code: Option<i32>,
+    signal: Option<i32>,
+}
+
+#[derive(serde::Serialize, Clone, Debug)]
+pub(crate) struct ServiceStatus {
+    tool: String,
+    state: ServiceState,
+    /// 当前子进程句柄
+    child: Option<String>,
+    restarts: u32,
+    last_exit: Option<ServiceExit>,
+    error: Option<String>,
+    restart: RestartPolicy,
+    with_vnt: bool,
+}
+
+enum Signal {
+    Line(String),
+    Exited(ServiceExit),
+    Stop,
+}
+
+struct Service {
+    definition: SupervisedTool,
+    status: ServiceStatus,
+    /// 监管线程运行期间存在
+    control: Option<Sender<Signal>>,
+    on_event: Option<Channel<CommandEvent>>,
+}
+
+#[derive(Default)]
+pub(crate) struct Supervisor {
+    services: Mutex<HashMap<String, Service>>,
+}
+
+impl Service {
+    fn new(definition: SupervisedTool) -> Self {
+        let status = ServiceStatus {
+            tool: definition.request.tool.clone(),
+            state: ServiceState::Stopped,
+            child: None,
+            restarts: 0,
+            last_exit: None,
+            error: None,
+            restart: definition.restart,
+            with_vnt: definition.with_vnt,
+        };
+        Service {
+            definition,
+            status,
+            control: None,
+            on_event: None,
+        }
+    }
+}
+
+impl Supervisor {
+    /// 载入已保存的监管定义，不会启动它们
+    pub(crate) fn load(&self, definitions: Vec<SupervisedTool>) {
+        let mut services = self.services.lock().unwrap();
+        for definition in definitions {
+            services.insert(definition.request.tool.clone(), Service::new(definition));
+        }
+    }
+
+    /// 有监管线程在运行时返回错误
+    pub(crate) fn ensure_stopped(&self) -> Result<(), ProgramError> {
+        check_stopped(&self.services.lock().unwrap())
+    }
+
+    /// 替换全部监管定义，有监管线程在运行时拒绝
+    pub(crate) fn replace(&self, definitions: Vec<SupervisedTool>) -> Result<(), ProgramError> {
+        let mut services = self.services.lock().unwrap();
+        check_stopped(&services)?;
+        services.clear();
+        for definition in definitions {
+            services.insert(definition.request.tool.clone(), Service::new(definition));
+        }
+        Ok(())
+    }
+
+    fn definitions(&self) -> Vec<SupervisedTool> {
+        let mut definitions: Vec<_> = self
+            .services
+            .lock()
+            .unwrap()
+            .values()
+            .map(|x| x.definition.clone())
+            .collect();
+        definitions.sort_by(|a, b| a.request.tool.cmp(&b.request.tool));
+        definitions
+    }
+
+    fn list(&self) -> Vec<ServiceStatus> {
+        let mut list: Vec<_> = self
+            .services
+            .lock()
+            .unwrap()
+            .values()
+            .map(|x| x.status.clone())
+            .collect();
+        list.sort_by(|a, b| a.tool.cmp(&b.tool));
+        list
+    }
+}
+
+fn check_stopped(services: &HashMap<String, Service>) -> Result<(), ProgramError> {
+    match services.values().find(|x| x.control.is_some()) {
+        Some(service) => Err(ProgramError::SupervisedRunning(
+            service.definition.request.tool.clone(),
+        )),
+        None => Ok(()),
+    }
+}
+
+fn save_definitions(app: &AppHandle) -> Result<(), ProgramError> {
+    let definitions = app.state::<Supervisor>().definitions();
+    let value = serde_json::to_value(definitions)
+        .map_err(|e| ProgramError::ConfigParseFailed(e.to_string()))?;
+    persist_store_value(app, SUPERVISED_KEY, value)
+}
+
+/// 修改监管状态并通知前端
+fn update(app: &AppHandle, tool: &str, f: impl FnOnce(&mut ServiceStatus)) {
+    let status = {
+        let supervisor = app.state::<Supervisor>();
+        let mut services = supervisor.services.lock().unwrap();
+        let Some(service) = services.get_mut(tool) else {
+            return;
+        };
+        f(&mut service.status);
+        service.status.clone()
+    };
+    if let Err(e) = app.emit("lers://supervisor/state", status) {
+        error!("Failed to emit supervisor state: {}", e);
+    }
+}
+
+/// 启动监管线程
+fn start_service(
+    app: &AppHandle,
+    tool: &str,
+    on_event: Option<Channel<CommandEvent>>,
+) -> Result<(), ProgramError> {
+    let (sender, receiver) = mpsc::channel();
+    {
+        let supervisor = app.state::<Supervisor>();
+        let mut services = supervisor.services.lock().unwrap();
+        let service = services
+            .get_mut(tool)
+            .ok_or_else(|| ProgramError::NotSupervised(tool.to_string()))?;
+        if service.control.is_some() {
+            return Err(ProgramError::SupervisedRunning(tool.to_string()));
+        }
+        service.control = Some(sender.clone());
+        if on_event.is_some() {
+            service.on_event = on_event;
+        }
+        service.status.restarts = 0;
+        service.status.error = None;
+    }
+    let app = app.clone();
+    let tool = tool.to_string();
+    std::thread::spawn(move || {
+        let state = supervise(&app, &tool, sender, receiver);
+        let supervisor = app.state::<Supervisor>();
+        let mut services = supervisor.services.lock().unwrap();
+        if let Some(service) = services.get_mut(&tool) {
+            service.control = None;
+        }
+        drop(services);
+        update(&app, &tool, |status| {
+            status.state = state;
+            status.child = None;
+        });
+        info!("Supervision of {} ended: {:?}", tool, state);
+    });
+    Ok(())
+}
+
+/// 请求监管线程停止，子进程随之停止
+fn stop_service(app: &AppHandle, tool: &str) -> Result<(), ProgramError> {
+    let supervisor = app.state::<Supervisor>();
+    let services = supervisor.services.lock().unwrap();
+    let service = services
+        .get(tool)
+        .ok_or_else(|| ProgramError::NotSupervised(tool.to_string()))?;
+    if let Some(control) = &service.control {
+        let _ = control.send(Signal::Stop);
+    }
+    Ok(())
+}
+
+/// 启动失败时，仅进程创建失败值得重试，白名单拒绝等错误重试也不会成功
+fn retryable(error: &SpawnError) -> bool {
+    matches!(
+        error,
+        SpawnError::SpawnFailed { .. } | SpawnError::AlreadyRunning { .. }
+    )
+}
+
+fn backoff(failures: u32) -> Duration {
+    BACKOFF_INITIAL
+        .saturating_mul(1 << failures.min(16))
+        .min(BACKOFF_MAX)
+}
+
+/// 等待退避结束，期间收到停止请求则返回false
+fn wait_backoff(receiver: &Receiver<Signal>, delay: Duration) -> bool {
+    let deadline = Instant::now() + delay;
+    loop {
+        let now = Instant::now();
+        if now >= deadline {
+            return true;
+        }
+        match receiver.recv_timeout(deadline - now) {
+            Ok(Signal::Stop) | Err(RecvTimeoutError::Disconnected) => return false,
+            Ok(_) | Err(RecvTimeoutError::Timeout) => {}
+        }
+    }
+}
+
+/// 子进程的一次运行结果
+struct RunOutcome {
+    exit: ServiceExit,
+    unhealthy: bool,
+    stopped: bool,
+}
+
+/// 监管循环，返回结束时的状态
+fn supervise(
+    app: &AppHandle,
+    tool: &str,
+    sender: Sender<Signal>,
+    receiver: Receiver<Signal>,
+) -> ServiceState {
+    let mut failures = 0u32;
+    loop {
+        let Some((definition, on_event)) = app
+            .state::<Supervisor>()
+            .services
+            .lock()
+            .unwrap()
+            .get(tool)
+            .map(|x| (x.definition.clone(), x.on_event.clone()))
+        else {
+            return ServiceState::Stopped;
+        };
+        update(app, tool, |status| {
+            status.state = ServiceState::Starting;
+            status.child = None;
+        });
+        let sink = service_sink(&definition, on_event, sender.clone());
+        let started = Instant::now();
+        let outcome = match spawn_tool(app, definition.request.clone(), sink) {
+            Ok(id) => {
+                update(app, tool, |status| {
+                    status.state = ServiceState::Running;
+                    status.child = Some(id.clone());
+                    status.error = None;
+                });
+                watch(app, tool, &id, &definition, &receiver, started)
+            }
+            Err(e) => {
+                warn!("Failed to start supervised {}: {}", tool, e);
+                update(app, tool, |status| status.error = Some(e.to_string()));
+                if !retryable(&e) || definition.restart == RestartPolicy::Never {
+                    return ServiceState::Failed;
+                }
+                RunOutcome {
+                    exit: ServiceExit {
+                        code: None,
+                        signal: None,
+                    },
+                    unhealthy: false,
+                    stopped: false,
+                }
+            }
+        };
+        if outcome.stopped {
+            return ServiceState::Stopped;
+        }
+        let failed = outcome.unhealthy || outcome.exit.code != Some(0);
+        update(app, tool, |status| {
+            status.last_exit = Some(outcome.exit);
+            status.child = None;
+        });
+        let restart = match definition.restart {
+            RestartPolicy::Never => false,
+            RestartPolicy::OnFailure => failed,
+            RestartPolicy::Always => true,
+        };
+        if !restart {
+            return ServiceState::Stopped;
+        }
+        if started.elapsed() >= STABLE_AFTER {
+            failures = 0;
+        }
+        let delay = backoff(failures);
+        failures = failures.saturating_add(1);
+        info!("Restarting supervised {} in {:?}", tool, delay);
+        update(app, tool, |status| {
+            status.state = ServiceState::Backoff;
+            status.restarts += 1;
+        });
+        if !wait_backoff(&receiver, delay) {
+            return ServiceState::Stopped;
+        }
+    }
+}
+
+/// 转发输出给前端，同时把退出和用于健康检查的输出交给监管线程
+fn service_sink(
+    definition: &SupervisedTool,
+    on_event: Option<Channel<CommandEvent>>,
+    sender: Sender<Signal>,
+) -> EventSink {
+    let sender = Mutex::new(sender);
+    let watch_stdout = matches!(definition.health, Some(HealthCheck::StdoutPattern { .. }));
+    std::sync::Arc::new(move |event: CommandEvent| {
+        let signal = match &event {
+            CommandEvent::Stdout(line) if watch_stdout => Some(Signal::Line(line.clone())),
+            CommandEvent::Terminated { code, signal } => Some(Signal::Exited(ServiceExit {
+                code: *code,
+                signal: *signal,
+            })),
+            _ => None,
+        };
+        if let Some(signal) = signal {
+            let _ = sender.lock().unwrap().send(signal);
+        }
+        if let Some(on_event) = &on_event {
+            let _ = on_event.send(event);
+        }
+    })
+}
+
+/// 等待子进程退出，期间执行健康检查
+fn watch(
+    app: &AppHandle,
+    tool: &str,
+    id: &str,
+    definition: &SupervisedTool,
+    receiver: &Receiver<Signal>,
+    started: Instant,
+) -> RunOutcome {
+    let manager = app.state::<ChildrenManager>();
+    let mut healthy = false;
+    let mut health_failures = 0u32;
+    let mut unhealthy = false;
+    let mut stopped = false;
+    loop {
+        let exit = match receiver.recv_timeout(HEALTH_INTERVAL) {
+            Ok(Signal::Exited(exit)) => Some(exit),
+            Ok(Signal::Stop) => {
+                stopped = true;
+                let _ = manager.stop(id);
+                None
+            }
+            Ok(Signal::Line(line)) => {
+                if let Some(HealthCheck::StdoutPattern { pattern }) = &definition.health {
+                    if !healthy && line.contains(pattern.as_str()) {
+                        healthy = true;
+                        update(app, tool, |status| status.state = ServiceState::Healthy);
+                    }
+                }
+                None
+            }
+            Err(RecvTimeoutError::Timeout) => None,
+            Err(RecvTimeoutError::Disconnected) => Some(ServiceExit {
+                code: None,
+                signal: None,
+            }),
+        };
+        if let Some(exit) = exit {
+            return RunOutcome {
+                exit,
+                unhealthy,
+                stopped,
+            };
+        }
+        if stopped || unhealthy {
+            continue;
+        }
+        let in_grace = started.elapsed() < HEALTH_GRACE;
+        match &definition.health {
+            Some(HealthCheck::TcpPort { port }) => {
+                let address = SocketAddr::from((Ipv4Addr::LOCALHOST, *port));
+                if TcpStream::connect_timeout(&address, TCP_CHECK_TIMEOUT).is_ok() {
+                    health_failures = 0;
+                    if !healthy {
+                        healthy = true;
+                        update(app, tool, |status| status.state = ServiceState::Healthy);
+                    }
+                } else if !in_grace {
+                    health_failures += 1;
+                    unhealthy = health_failures >= HEALTH_FAILURES;
+                }
+            }
+            Some(HealthCheck::StdoutPattern { .. }) => {
+                unhealthy = !healthy && !in_grace;
+            }
+            None => {}
+        }
+        if unhealthy {
+            warn!("Supervised {} failed its health check, restarting", tool);
+            update(app, tool, |status| status.state = ServiceState::Unhealthy);
+            let _ = manager.stop(id);
+        }
+    }
+}
+
+/// vnt会话启动后，启动随之运行的工具
+pub(crate) fn on_vnt_started(app: &AppHandle) {
+    let tools: Vec<_> = app
+        .state::<Supervisor>()
+        .services
+        .lock()
+        .unwrap()
+        .values()
+        .filter(|x| x.definition.with_vnt && x.control.is_none())
+        .map(|x| x.definition.request.tool.clone())
+        .collect();
+    for tool in tools {
+        if let Err(e) = start_service(app, &tool, None) {
+            error!("Failed to start supervised {}: {}", tool, e);
+        }
+    }
+}
+
+/// vnt会话停止后，停止随之运行的工具
+pub(crate) fn on_vnt_stopped(app: &AppHandle) {
+    stop_where(app, |x| x.definition.with_vnt);
+}
+
+/// 退出程序前停止全部监管，避免子进程被结束后又被重启
+pub(crate) fn stop_all(app: &AppHandle) {
+    stop_where(app, |_| true);
+}
+
+fn stop_where(app: &AppHandle, filter: impl Fn(&Service) -> bool) {
+    let supervisor = app.state::<Supervisor>();
+    let services = supervisor.services.lock().unwrap();
+    for service in services.values().filter(|x| filter(x)) {
+        if let Some(control) = &service.control {
+            let _ = control.send(Signal::Stop);
+        }
+    }
+}
+
+fn vnt_running(app: &AppHandle) -> bool {
+    app.state::<Mutex<Status>>()
+        .lock()
+        .map(|x| x.vnt.is_some())
+        .unwrap_or(false)
+}
+
+/// 添加或替换监管定义并按需启动，随vnt运行的工具仅在vnt已启动时立即启动
+#[tauri::command]
+pub(crate) fn supervise_tool(
+    app: AppHandle,
+    definition: SupervisedTool,
+    on_event: Option<Channel<CommandEvent>>,
+    registry: State<'_, ToolRegistry>,
+    supervisor: State<'_, Supervisor>,
+) -> Result<ServiceStatus, String> {
+    let tool = definition.request.tool.clone();
+    if registry.get(&tool).is_none() {
+        return Err(SpawnError::UnknownTool { tool }.to_string());
+    }
+    let with_vnt = definition.with_vnt;
+    {
+        let mut services = supervisor.services.lock().unwrap();
+        if services.get(&tool).is_some_and(|x| x.control.is_some()) {
+            return Err(ProgramError::SupervisedRunning(tool).to_string());
+        }
+        let mut service = Service::new(definition);
+        service.on_event = on_event;
+        services.insert(tool.clone(), service);
+    }
+    save_definitions(&app).map_err(|e| e.to_string())?;
+    if !with_vnt || vnt_running(&app) {
+        start_service(&app, &tool, None).map_err(|e| e.to_string())?;
+    }
+    supervisor
+        .list()
+        .into_iter()
+        .find(|x| x.tool == tool)
+        .ok_or_else(|| ProgramError::NotSupervised(tool).to_string())
+}
+
+/// 停止并移除监管定义
+#[tauri::command]
+pub(crate) fn unsupervise_tool(
+    app: AppHandle,
+    tool: String,
+    supervisor: State<'_, Supervisor>,
+) -> Result<(), String> {
+    let service = supervisor
+        .services
+        .lock()
+        .unwrap()
+        .remove(&tool)
+        .ok_or_else(|| ProgramError::NotSupervised(tool.clone()).to_string())?;
+    // 监管线程找不到定义后不会再重启
+    if let Some(control) = service.control {
+        let _ = control.send(Signal::Stop);
+    }
+    save_definitions(&app).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub(crate) fn start_supervised(
+    app: AppHandle,
+    tool: String,
+    on_event: Option<Channel<CommandEvent>>,
+) -> Result<(), String> {
+    start_service(&app, &tool, on_event).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub(crate) fn stop_supervised(app: AppHandle, tool: String) -> Result<(), String> {
+    stop_service(&app, &tool).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub(crate) fn list_supervised(supervisor: State<'_, Supervisor>) -> Vec<ServiceStatus> {
+    supervisor.list()
+}
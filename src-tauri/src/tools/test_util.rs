@@ -0,0 +1,75 @@
+//! 单元测试共用的临时目录、脚本与本地HTTP服务器
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+
+/// 新建一个唯一的临时目录
+pub(crate) fn temp_dir() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("lers-test-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// 写入可执行的shell脚本
+#[cfg(unix)]
+pub(crate) fn write_script(path: &Path, script: &str) {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::write(path, format!("#!/bin/sh\n{}\n", script)).unwrap();
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755)).unwrap();
+}
+
+/// 测试服务器收到的请求
+pub(crate) struct Request {
+    pub(crate) path: String,
+    /// Range头的起始偏移
+    pub(crate) range: Option<u64>,
+}
+
+/// 在本地端口上依次处理count个请求，handler返回完整的响应，返回服务器地址
+pub(crate) fn serve(
+    count: usize,
+    handler: impl Fn(&Request) -> Vec<u8> + Send + 'static,
+) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    std::thread::spawn(move || {
+        for stream in listener.incoming().take(count) {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            let mut request = Request {
+                path: line
+                    .split_whitespace()
+                    .nth(1)
+                    .unwrap_or_default()
+                    .to_string(),
+                range: None,
+            };
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line.trim().is_empty() {
+                    break;
+                }
+                if let Some(value) = line.to_ascii_lowercase().strip_prefix("range: bytes=") {
+                    request.range = value.trim().trim_end_matches('-').parse().ok();
+                }
+            }
+            let _ = stream.write_all(&handler(&request));
+        }
+    });
+    format!("http://{}", addr)
+}
+
+/// 拼装HTTP响应，length可以与body长度不同以模拟截断
+pub(crate) fn response(status: &str, body: &[u8], length: usize) -> Vec<u8> {
+    let mut response = format!(
+        "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status, length
+    )
+    .into_bytes();
+    response.extend_from_slice(body);
+    response
+}
@@ -0,0 +1,700 @@
+//! 可启动工具的白名单
+//!
+//! 前端只能按名称启动已注册的工具，可执行文件必须位于工具目录内，
+//! 参数、环境变量与标准输入均按注册时的约束校验，每次启动都会写入审计日志。
+
+use crate::errors::SpawnError;
+use crate::tools::command::SpawnOptions;
+use crate::tools::installer::active_dir;
+use crate::tools::ExternalFilePosition;
+use log::{info, warn};
+use std::collections::HashMap;
+use std::io::Write;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Manager};
+
+/// 工具目录，相对于资源目录
+const TOOLS_DIR: &str = "tool";
+/// 审计日志超过该大小后轮换
+const AUDIT_LOG_LIMIT: u64 = 1024 * 1024;
+
+/// 单个参数的约束
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub(crate) enum ArgKind {
+    /// 固定值
+    Literal {
+        value: String,
+    },
+    /// 枚举值之一
+    Choice {
+        values: Vec<String>,
+    },
+    /// IPv4或IPv6地址
+    Ip,
+    /// IP地址或主机名
+    Host,
+    Port,
+    Integer {
+        min: i64,
+        max: i64,
+    },
+}
+
+impl ArgKind {
+    fn check(&self, value: &str) -> Result<(), String> {
+        if value.chars().any(|c| c.is_control()) {
+            return Err("contains control characters".to_string());
+        }
+        match self {
+            ArgKind::Literal { value: expected } => {
+                if value != expected {
+                    return Err(format!("expected \"{}\"", expected));
+                }
+            }
+            ArgKind::Choice { values } => {
+                if !values.iter().any(|x| x == value) {
+                    return Err(format!("expected one of {}", values.join(", ")));
+                }
+            }
+            ArgKind::Ip => {
+                value
+                    .parse::<IpAddr>()
+                    .map_err(|_| "not an IP address".to_string())?;
+            }
+            ArgKind::Host => {
+                if value.parse::<IpAddr>().is_err() && !is_hostname(value) {
+                    return Err("not an IP address or host name".to_string());
+                }
+            }
+            ArgKind::Port => {
+                match value.parse::<u16>() {
+                    Ok(port) if port != 0 => {}
+                    _ => return Err("not a port number".to_string()),
+                };
+            }
+            ArgKind::Integer { min, max } => match value.parse::<i64>() {
+                Ok(x) if (*min..=*max).contains(&x) => {}
+                _ => return Err(format!("expected an integer in {}..={}", min, max)),
+            },
+        }
+        Ok(())
+    }
+}
+
+fn is_hostname(value: &str) -> bool {
+    !value.is_empty()
+        && value.len() <= 253
+        && value.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+}
+
+/// 参数列表的约束：先是固定位置参数，之后是数量受限的同类参数
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub(crate) struct ArgSchema {
+    fixed: Vec<ArgKind>,
+    rest: Option<ArgKind>,
+    min_rest: usize,
+    max_rest: usize,
+}
+
+impl ArgSchema {
+    fn check(&self, tool: &str, args: &[String]) -> Result<(), SpawnError> {
+        let (min, max) = match self.rest {
+            Some(_) => (
+                self.fixed.len() + self.min_rest,
+                self.fixed.len() + self.max_rest,
+            ),
+            None => (self.fixed.len(), self.fixed.len()),
+        };
+        if args.len() < min || args.len() > max {
+            return Err(SpawnError::ArgumentCount {
+                tool: tool.to_string(),
+                min,
+                max,
+                count: args.len(),
+            });
+        }
+        for (index, value) in args.iter().enumerate() {
+            let kind = self.fixed.get(index).or(self.rest.as_ref());
+            if let Some(kind) = kind {
+                kind.check(value)
+                    .map_err(|reason| SpawnError::InvalidArgument {
+                        index,
+                        value: value.clone(),
+                        reason,
+                    })?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 已注册的工具
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub(crate) struct ToolSpec {
+    pub(crate) name: String,
+    /// 可执行文件相对于工具目录的路径，不含平台后缀
+    pub(crate) executable: String,
+    #[serde(default)]
+    pub(crate) args: ArgSchema,
+    /// 允许设置的环境变量
+    #[serde(default)]
+    pub(crate) env: Vec<String>,
+    /// 是否允许接管标准输入，伪终端模式同样需要
+    #[serde(default)]
+    pub(crate) stdin: bool,
+    /// 同一时间只允许运行一个实例
+    #[serde(default)]
+    pub(crate) singleton: bool,
+}
+
+/// 通过校验、可以启动的命令
+pub(crate) struct ResolvedSpawn {
+    pub(crate) executable: PathBuf,
+    pub(crate) cwd: PathBuf,
+    pub(crate) singleton: bool,
+}
+
+/// 工具白名单
+pub(crate) struct ToolRegistry {
+    tools: RwLock<HashMap<String, ToolSpec>>,
+    /// 审计日志路径，写入期间持有锁
+    audit: Mutex<PathBuf>,
+}
+
+impl Default for ToolRegistry {
+    fn default() -> Self {
+        let registry = ToolRegistry {
+            tools: RwLock::new(HashMap::new()),
+            audit: Mutex::new(PathBuf::from(ExternalFilePosition::SpawnAudit.to_string())),
+        };
+        for spec in builtin_tools() {
+            registry.register(spec);
+        }
+        registry
+    }
+}
+
+/// 内置工具
+fn builtin_tools() -> Vec<ToolSpec> {
+    vec![ToolSpec {
+        name: "gping".to_string(),
+        executable: "gping".to_string(),
+        args: ArgSchema {
+            fixed: vec![],
+            rest: Some(ArgKind::Host),
+            min_rest: 1,
+            max_rest: 8,
+        },
+        env: vec![],
+        stdin: false,
+        singleton: false,
+    }]
+}
+
+/// 工具目录，位于资源目录下
+pub(crate) fn tools_dir(app: &AppHandle) -> Result<PathBuf, SpawnError> {
+    app.path()
+        .resource_dir()
+        .map(|x| x.join(TOOLS_DIR))
+        .map_err(|e| SpawnError::ToolsDirUnavailable {
+            message: e.to_string(),
+        })
+}
+
+/// 规范化路径并确认其位于工具目录内，避免通过..或符号链接逃逸
+fn inside_tools_dir(tools_dir: &Path, path: &Path) -> Result<PathBuf, SpawnError> {
+    let outside = || SpawnError::OutsideToolsDir {
+        path: path.to_string_lossy().to_string(),
+    };
+    let root = tools_dir.canonicalize().map_err(|_| outside())?;
+    let path = path.canonicalize().map_err(|_| outside())?;
+    if path.starts_with(&root) {
+        Ok(path)
+    } else {
+        Err(outside())
+    }
+}
+
+impl ToolRegistry {
+    pub(crate) fn register(&self, spec: ToolSpec) {
+        self.tools.write().unwrap().insert(spec.name.clone(), spec);
+    }
+
+    /// 以工具目录中的工具替换此前注册的目录工具，同名时覆盖内置工具
+    pub(crate) fn replace_catalog(&self, specs: Vec<ToolSpec>) {
+        let mut tools = self.tools.write().unwrap();
+        tools.clear();
+        for spec in builtin_tools().into_iter().chain(specs) {
+            tools.insert(spec.name.clone(), spec);
+        }
+    }
+
+    /// 测试中把审计日志写到临时目录
+    #[cfg(test)]
+    pub(crate) fn set_audit_log(&self, path: PathBuf) {
+        *self.audit.lock().unwrap() = path;
+    }
+
+    pub(crate) fn get(&self, name: &str) -> Option<ToolSpec> {
+        self.tools.read().unwrap().get(name).cloned()
+    }
+
+    /// 按白名单校验启动请求，返回可执行文件的绝对路径和工作目录
+    pub(crate) fn resolve(
+        &self,
+        tools_dir: &Path,
+        tool: &str,
+        args: &[String],
+        options: &SpawnOptions,
+    ) -> Result<ResolvedSpawn, SpawnError> {
+        let spec = self.get(tool).ok_or_else(|| SpawnError::UnknownTool {
+            tool: tool.to_string(),
+        })?;
+        // 通过安装器安装的工具使用当前版本目录
+        let base = active_dir(tools_dir, &spec.name).unwrap_or_else(|| tools_dir.to_path_buf());
+        let executable = base.join(format!(
+            "{}{}",
+            spec.executable,
+            std::env::consts::EXE_SUFFIX
+        ));
+        if !executable.is_file() {
+            return Err(SpawnError::ExecutableMissing {
+                tool: tool.to_string(),
+                path: executable.to_string_lossy().to_string(),
+            });
+        }
+        let executable = inside_tools_dir(tools_dir, &executable)?;
+        spec.args.check(tool, args)?;
+        if let Some(name) = options.env.keys().find(|x| !spec.env.contains(x)) {
+            return Err(SpawnError::EnvNotAllowed {
+                tool: tool.to_string(),
+                name: name.clone(),
+            });
+        }
+        // 伪终端模式下可以向终端输入
+        if (options.stdin || options.pty.is_some()) && !spec.stdin {
+            return Err(SpawnError::StdinNotAllowed {
+                tool: tool.to_string(),
+            });
+        }
+        // 工作目录默认为可执行文件所在目录，且不能离开工具目录
+        let cwd = match &options.cwd {
+            Some(cwd) => inside_tools_dir(tools_dir, &tools_dir.join(cwd))?,
+            None => executable
+                .parent()
+                .map(Path::to_path_buf)
+                .unwrap_or_else(|| tools_dir.to_path_buf()),
+        };
+        Ok(ResolvedSpawn {
+            executable,
+            cwd,
+            singleton: spec.singleton,
+        })
+    }
+
+    /// 追加一条审计记录
+    pub(crate) fn audit(&self, record: AuditRecord) {
+        match &record.error {
+            None => info!(
+                "Spawn {} {:?}: {}",
+                record.tool, record.args, record.outcome
+            ),
+            Some(e) => warn!(
+                "Spawn {} {:?}: {} ({})",
+                record.tool, record.args, record.outcome, e
+            ),
+        }
+        let path = self.audit.lock().unwrap_or_else(|e| e.into_inner());
+        if let Err(e) = append_audit(&path, &record) {
+            warn!("Failed to write spawn audit log: {}", e);
+        }
+    }
+}
+
+/// 启动审计记录
+#[derive(serde::Serialize, Debug)]
+pub(crate) struct AuditRecord {
+    /// unix时间戳(毫秒)
+    time: u64,
+    tool: String,
+    args: Vec<String>,
+    /// 仅记录变量名
+    env: Vec<String>,
+    cwd: Option<String>,
+    executable: Option<String>,
+    outcome: &'static str,
+    id: Option<String>,
+    pid: Option<u32>,
+    error: Option<String>,
+}
+
+impl AuditRecord {
+    pub(crate) fn new(tool: &str, args: &[String], options: &SpawnOptions) -> Self {
+        let mut env: Vec<_> = options.env.keys().cloned().collect();
+        env.sort();
+        AuditRecord {
+            time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|x| x.as_millis() as u64)
+                .unwrap_or(0),
+            tool: tool.to_string(),
+            args: args.to_vec(),
+            env,
+            cwd: None,
+            executable: None,
+            outcome: "rejected",
+            id: None,
+            pid: None,
+            error: None,
+        }
+    }
+
+    pub(crate) fn resolved(mut self, resolved: &ResolvedSpawn) -> Self {
+        self.executable = Some(resolved.executable.to_string_lossy().to_string());
+        self.cwd = Some(resolved.cwd.to_string_lossy().to_string());
+        self
+    }
+
+    pub(crate) fn rejected(mut self, error: &SpawnError) -> Self {
+        self.outcome = "rejected";
+        self.error = Some(error.to_string());
+        self
+    }
+
+    pub(crate) fn failed(mut self, error: &SpawnError) -> Self {
+        self.outcome = "failed";
+        self.error = Some(error.to_string());
+        self
+    }
+
+    pub(crate) fn spawned(mut self, id: &str, pid: u32) -> Self {
+        self.outcome = "spawned";
+        self.id = Some(id.to_string());
+        self.pid = Some(pid);
+        self
+    }
+}
+
+fn append_audit(path: &Path, record: &AuditRecord) -> std::io::Result<()> {
+    if std::fs::metadata(path).is_ok_and(|x| x.len() > AUDIT_LOG_LIMIT) {
+        let mut rotated = path.as_os_str().to_owned();
+        rotated.push(".1");
+        std::fs::rename(path, rotated)?;
+    }
+    let mut line = serde_json::to_vec(record)?;
+    line.push(b'\n');
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?
+        .write_all(&line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::pty::TerminalSize;
+    use crate::tools::test_util::temp_dir;
+
+    fn args(values: &[&str]) -> Vec<String> {
+        values.iter().map(|x| x.to_string()).collect()
+    }
+
+    /// 在工具目录中放置可执行文件并注册
+    fn registry(tools_dir: &Path, spec: ToolSpec) -> ToolRegistry {
+        let executable = tools_dir.join(format!(
+            "{}{}",
+            spec.executable,
+            std::env::consts::EXE_SUFFIX
+        ));
+        std::fs::write(executable, b"").unwrap();
+        let registry = ToolRegistry::default();
+        registry.register(spec);
+        registry
+    }
+
+    fn spec(env: &[&str], stdin: bool) -> ToolSpec {
+        ToolSpec {
+            name: "tool".to_string(),
+            executable: "tool".to_string(),
+            args: ArgSchema::default(),
+            env: args(env),
+            stdin,
+            singleton: false,
+        }
+    }
+
+    #[test]
+    fn rejects_paths_outside_tools_dir() {
+        let dir = temp_dir();
+        let tools_dir = dir.join("tool");
+        std::fs::create_dir_all(&tools_dir).unwrap();
+        let inside = tools_dir.join("inside");
+        let outside = dir.join("outside");
+        std::fs::write(&inside, b"").unwrap();
+        std::fs::write(&outside, b"").unwrap();
+        assert!(inside_tools_dir(&tools_dir, &inside).is_ok());
+        assert!(matches!(
+            inside_tools_dir(&tools_dir, &outside),
+            Err(SpawnError::OutsideToolsDir { .. })
+        ));
+        // 通过..回到上级目录
+        assert!(matches!(
+            inside_tools_dir(&tools_dir, &tools_dir.join("../outside")),
+            Err(SpawnError::OutsideToolsDir { .. })
+        ));
+        // 不存在的路径无法规范化，同样拒绝
+        assert!(inside_tools_dir(&tools_dir, &tools_dir.join("missing")).is_err());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rejects_symlink_escape() {
+        let dir = temp_dir();
+        let tools_dir = dir.join("tool");
+        std::fs::create_dir_all(&tools_dir).unwrap();
+        std::fs::write(dir.join("outside"), b"").unwrap();
+        std::os::unix::fs::symlink(dir.join("outside"), tools_dir.join("link")).unwrap();
+        std::os::unix::fs::symlink(&dir, tools_dir.join("parent")).unwrap();
+        assert!(matches!(
+            inside_tools_dir(&tools_dir, &tools_dir.join("link")),
+            Err(SpawnError::OutsideToolsDir { .. })
+        ));
+        assert!(matches!(
+            inside_tools_dir(&tools_dir, &tools_dir.join("parent/outside")),
+            Err(SpawnError::OutsideToolsDir { .. })
+        ));
+        // 指向工具目录外的可执行文件不能启动
+        let mut spec = spec(&[], false);
+        spec.executable = "link".to_string();
+        let registry = ToolRegistry::default();
+        registry.register(spec);
+        assert!(matches!(
+            registry.resolve(&tools_dir, "tool", &[], &SpawnOptions::default()),
+            Err(SpawnError::OutsideToolsDir { .. })
+        ));
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn rejects_cwd_outside_tools_dir() {
+        let dir = temp_dir();
+        let tools_dir = dir.join("tool");
+        std::fs::create_dir_all(&tools_dir).unwrap();
+        let registry = registry(&tools_dir, spec(&[], false));
+        let options = SpawnOptions {
+            cwd: Some(PathBuf::from("..")),
+            ..Default::default()
+        };
+        assert!(matches!(
+            registry.resolve(&tools_dir, "tool", &[], &options),
+            Err(SpawnError::OutsideToolsDir { .. })
+        ));
+        let resolved = registry
+            .resolve(&tools_dir, "tool", &[], &SpawnOptions::default())
+            .unwrap();
+        assert_eq!(resolved.cwd, tools_dir.canonicalize().unwrap());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn rejects_unknown_tool() {
+        let dir = temp_dir();
+        let registry = ToolRegistry::default();
+        assert!(matches!(
+            registry.resolve(&dir, "rm", &[], &SpawnOptions::default()),
+            Err(SpawnError::UnknownTool { tool }) if tool == "rm"
+        ));
+        // 已注册但可执行文件不存在
+        assert!(matches!(
+            registry.resolve(&dir, "gping", &args(&["1.1.1.1"]), &SpawnOptions::default()),
+            Err(SpawnError::ExecutableMissing { .. })
+        ));
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn checks_argument_count() {
+        let schema = ArgSchema {
+            fixed: vec![ArgKind::Literal {
+                value: "-c".to_string(),
+            }],
+            rest: Some(ArgKind::Ip),
+            min_rest: 1,
+            max_rest: 2,
+        };
+        assert!(schema.check("tool", &args(&["-c", "10.0.0.1"])).is_ok());
+        assert!(schema
+            .check("tool", &args(&["-c", "10.0.0.1", "::1"]))
+            .is_ok());
+        for count in [1, 4] {
+            let values = args(&["-c", "10.0.0.1", "10.0.0.2", "10.0.0.3"][..count]);
+            assert!(matches!(
+                schema.check("tool", &values),
+                Err(SpawnError::ArgumentCount { min: 2, max: 3, count: c, .. }) if c == count
+            ));
+        }
+        // 没有rest时参数数量固定
+        let schema = ArgSchema::default();
+        assert!(schema.check("tool", &[]).is_ok());
+        assert!(matches!(
+            schema.check("tool", &args(&["x"])),
+            Err(SpawnError::ArgumentCount { min: 0, max: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn checks_argument_kinds() {
+        let cases = [
+            (
+                ArgKind::Literal {
+                    value: "-c".to_string(),
+                },
+                &["-c"][..],
+                &["-C", "-c "][..],
+            ),
+            (
+                ArgKind::Choice {
+                    values: args(&["tcp", "udp"]),
+                },
+                &["tcp", "udp"],
+                &["icmp", ""],
+            ),
+            (
+                ArgKind::Ip,
+                &["10.0.0.1", "fe80::1"],
+                &["10.0.0.256", "host"],
+            ),
+            (
+                ArgKind::Host,
+                &["10.0.0.1", "example.com", "a-b"],
+                &["-rf", "a..b", "a b", "a;b", ""],
+            ),
+            (ArgKind::Port, &["1", "65535"], &["0", "65536", "-1"]),
+            (
+                ArgKind::Integer { min: -1, max: 10 },
+                &["-1", "10"],
+                &["-2", "11", "1.5"],
+            ),
+        ];
+        for (kind, valid, invalid) in cases {
+            for value in valid {
+                assert!(kind.check(value).is_ok(), "{:?} {}", kind, value);
+            }
+            for value in invalid {
+                assert!(kind.check(value).is_err(), "{:?} {}", kind, value);
+            }
+        }
+        // 控制字符一律拒绝
+        let choice = ArgKind::Choice {
+            values: args(&["a\nb"]),
+        };
+        assert!(choice.check("a\nb").is_err());
+        // 错误中带有参数位置
+        let schema = ArgSchema {
+            rest: Some(ArgKind::Port),
+            max_rest: 2,
+            ..Default::default()
+        };
+        assert!(matches!(
+            schema.check("tool", &args(&["80", "http"])),
+            Err(SpawnError::InvalidArgument { index: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn checks_env_allowlist() {
+        let dir = temp_dir();
+        let registry = registry(&dir, spec(&["TERM"], false));
+        let mut options = SpawnOptions::default();
+        options.env.insert("TERM".to_string(), "xterm".to_string());
+        assert!(registry.resolve(&dir, "tool", &[], &options).is_ok());
+        options
+            .env
+            .insert("LD_PRELOAD".to_string(), "x.so".to_string());
+        assert!(matches!(
+            registry.resolve(&dir, "tool", &[], &options),
+            Err(SpawnError::EnvNotAllowed { name, .. }) if name == "LD_PRELOAD"
+        ));
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn checks_stdin_allowlist() {
+        let dir = temp_dir();
+        let registry = registry(&dir, spec(&[], false));
+        let stdin = SpawnOptions {
+            stdin: true,
+            ..Default::default()
+        };
+        let pty = SpawnOptions {
+            pty: Some(TerminalSize { cols: 80, rows: 24 }),
+            ..Default::default()
+        };
+        for options in [&stdin, &pty] {
+            assert!(matches!(
+                registry.resolve(&dir, "tool", &[], options),
+                Err(SpawnError::StdinNotAllowed { .. })
+            ));
+        }
+        registry.register(spec(&[], true));
+        for options in [&stdin, &pty] {
+            assert!(registry.resolve(&dir, "tool", &[], options).is_ok());
+        }
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn writes_and_rotates_audit_log() {
+        let dir = temp_dir();
+        let path = dir.join("spawn_audit.log");
+        let rotated = dir.join("spawn_audit.log.1");
+        let mut options = SpawnOptions::default();
+        options.env.insert("TERM".to_string(), "secret".to_string());
+        let record = AuditRecord::new("gping", &args(&["10.0.0.1"]), &options).spawned("id", 42);
+        append_audit(&path, &record).unwrap();
+        append_audit(&path, &record).unwrap();
+        let content = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<serde_json::Value> = content
+            .lines()
+            .map(|x| serde_json::from_str(x).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["tool"], "gping");
+        assert_eq!(lines[0]["outcome"], "spawned");
+        assert_eq!(lines[0]["pid"], 42);
+        // 只记录变量名，不记录值
+        assert_eq!(lines[0]["env"], serde_json::json!(["TERM"]));
+        assert!(!content.contains("secret"));
+        // 超过大小限制后轮换到.1
+        std::fs::write(&path, vec![b'x'; AUDIT_LOG_LIMIT as usize + 1]).unwrap();
+        let error = SpawnError::UnknownTool {
+            tool: "rm".to_string(),
+        };
+        append_audit(
+            &path,
+            &AuditRecord::new("rm", &[], &options).rejected(&error),
+        )
+        .unwrap();
+        assert_eq!(
+            std::fs::metadata(&rotated).unwrap().len(),
+            AUDIT_LOG_LIMIT + 1
+        );
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(content.lines().count(), 1);
+        assert!(content.contains("\"outcome\":\"rejected\""));
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
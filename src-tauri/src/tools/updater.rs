@@ -0,0 +1,928 @@
+//! 程序自更新
+//!
+//! 从配置的通道（stable或beta）获取签名的发布清单，比较语义化版本号，
+//! 新版本程序下载后校验SHA-256和Ed25519签名，再替换当前程序。
+//! 替换前的程序保留为`.old`备份并以看护模式启动：看护进程等待旧版本退出后启动新版本，
+//! 新版本在限定时间内未确认启动成功时恢复备份并重新启动旧版本。
+
+use crate::errors::ProgramError;
+use crate::tools::config_builder::persist_store_value;
+use crate::tools::downloader::{
+    self, sha256_file, DownloadArtifact, DownloadEvent, DownloadManager,
+};
+use crate::tools::migration::UPDATE_KEY;
+use crate::tools::signing::{open_envelope, verify};
+use crate::tools::{write_file_atomic, ExternalFilePosition};
+use log::{error, info, warn};
+use semver::Version;
+use std::ffi::OsString;
+use std::io::Read;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use sysinfo::{Pid, ProcessesToUpdate, System};
+use tauri::ipc::Channel;
+use tauri::{AppHandle, Emitter, Manager, State, Url};
+use tauri_plugin_store::StoreExt;
+
+/// 支持的清单格式版本
+const MANIFEST_SCHEMA: u32 = 1;
+/// 清单大小上限
+const MAX_MANIFEST_SIZE: u64 = 1024 * 1024;
+const FETCH_TIMEOUT: Duration = Duration::from_secs(15);
+/// 构建时写入的默认更新地址
+const DEFAULT_ENDPOINT: Option<&str> = option_env!("LERS_UPDATE_ENDPOINT");
+/// 以看护模式启动的命令行参数，后跟旧版本的进程号
+const WATCHDOG_ARG: &str = "--update-watchdog";
+/// 等待旧版本退出的时间
+const EXIT_TIMEOUT: Duration = Duration::from_secs(30);
+/// 新版本需要在此时间内确认启动成功
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(60);
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// 更新通道
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum UpdateChannel {
+    #[default]
+    Stable,
+    Beta,
+}
+
+impl UpdateChannel {
+    fn name(&self) -> &'static str {
+        match self {
+            UpdateChannel::Stable => "stable",
+            UpdateChannel::Beta => "beta",
+        }
+    }
+}
+
+/// 更新设置
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub(crate) struct UpdateSettings {
+    pub(crate) channel: UpdateChannel,
+    /// 更新地址，清单位于`{endpoint}/{channel}.json`，为空时使用构建时的默认地址
+    pub(crate) endpoint: Option<String>,
+    /// 启动时发现新版本后自动下载安装，否则只提示
+    pub(crate) auto_install: bool,
+}
+
+/// 某一平台的程序文件
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+struct ReleaseArtifact {
+    os: String,
+    arch: String,
+    url: String,
+    /// 文件内容的SHA-256，十六进制
+    sha256: String,
+    #[serde(default)]
+    size: Option<u64>,
+    /// 文件内容的Ed25519签名，base64编码
+    signature: String,
+}
+
+/// 发布清单
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+struct ReleaseManifest {
+    schema: u32,
+    channel: UpdateChannel,
+    version: String,
+    #[serde(default)]
+    notes: String,
+    #[serde(default)]
+    published_at: Option<String>,
+    platforms: Vec<ReleaseArtifact>,
+}
+
+impl ReleaseManifest {
+    /// 校验签名并解析，清单的通道必须与请求的通道一致
+    fn parse(content: &[u8], channel: UpdateChannel) -> Result<(Self, Version), ProgramError> {
+        let payload = open_envelope(content)?;
+        let manifest = serde_json::from_slice::<ReleaseManifest>(&payload)
+            .map_err(|e| ProgramError::InvalidReleaseManifest(e.to_string()))?;
+        let invalid = |reason: String| Err(ProgramError::InvalidReleaseManifest(reason));
+        if manifest.schema != MANIFEST_SCHEMA {
+            return invalid(format!("unsupported schema {}", manifest.schema));
+        }
+        if manifest.channel != channel {
+            return invalid(format!(
+                "expected channel {}, got {}",
+                channel.name(),
+                manifest.channel.name()
+            ));
+        }
+        let version = match Version::parse(&manifest.version) {
+            Ok(version) => version,
+            Err(e) => return invalid(format!("{}: {}", manifest.version, e)),
+        };
+        // 稳定通道不发布预览版
+        if channel == UpdateChannel::Stable && !version.pre.is_empty() {
+            return invalid(format!("pre-release {} in stable channel", version));
+        }
+        for artifact in &manifest.platforms {
+            let hashed = artifact.sha256.len() == 64
+                && artifact.sha256.chars().all(|c| c.is_ascii_hexdigit());
+            if !hashed || artifact.signature.trim().is_empty() {
+                return invalid(format!(
+                    "artifact for {} {} needs a sha256 and a signature",
+                    artifact.os, artifact.arch
+                ));
+            }
+        }
+        Ok((manifest, version))
+    }
+
+    /// 当前平台的程序文件
+    fn artifact(&self) -> Option<&ReleaseArtifact> {
+        self.platforms
+            .iter()
+            .find(|x| x.os == std::env::consts::OS && x.arch == std::env::consts::ARCH)
+    }
+
+    fn info(&self) -> ReleaseInfo {
+        ReleaseInfo {
+            version: self.version.clone(),
+            channel: self.channel,
+            notes: self.notes.clone(),
+            published_at: self.published_at.clone(),
+            size: self.artifact().and_then(|x| x.size),
+        }
+    }
+}
+
+/// 返回给前端的新版本信息
+#[derive(serde::Serialize, Clone, Debug)]
+pub(crate) struct ReleaseInfo {
+    version: String,
+    channel: UpdateChannel,
+    notes: String,
+    published_at: Option<String>,
+    size: Option<u64>,
+}
+
+/// 替换程序后的进度
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum MarkerState {
+    /// 已替换，等待新版本确认启动
+    Pending,
+    /// 新版本已启动
+    Confirmed,
+    /// 新版本启动失败，已恢复旧版本
+    RolledBack,
+}
+
+/// 更新标记，看护进程和新版本通过它交接
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+struct UpdateMarker {
+    version: String,
+    previous_version: String,
+    state: MarkerState,
+    executable: PathBuf,
+    backup: PathBuf,
+}
+
+/// 已发现和已下载的新版本
+#[derive(Default)]
+pub(crate) struct Updater(Mutex<UpdaterState>);
+
+#[derive(Default)]
+struct UpdaterState {
+    available: Option<ReleaseManifest>,
+    /// 校验通过、等待安装的程序文件
+    staged: Option<(ReleaseManifest, PathBuf)>,
+}
+
+impl Updater {
+    /// 更新设置变化后清除已发现的版本，需要重新检查
+    pub(crate) fn reset(&self) {
+        self.0.lock().unwrap().available = None;
+    }
+}
+
+/// 更新状态
+#[derive(serde::Serialize, Clone, Debug)]
+pub(crate) struct UpdateStatus {
+    current_version: String,
+    settings: UpdateSettings,
+    available: Option<ReleaseInfo>,
+    /// 新版本已下载，可以安装
+    staged: bool,
+    /// 最近一次启动失败而被回滚的版本
+    rolled_back: Option<String>,
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+fn io_error(path: &Path, e: std::io::Error) -> ProgramError {
+    ProgramError::UpdateFailed(format!("{}: {}", path.display(), e))
+}
+
+fn marker_path() -> PathBuf {
+    PathBuf::from(ExternalFilePosition::UpdateMarker.to_string())
+}
+
+fn read_marker(path: &Path) -> Option<UpdateMarker> {
+    let content = std::fs::read(path).ok()?;
+    serde_json::from_slice(&content)
+        .inspect_err(|e| error!("Failed to parse update marker: {}", e))
+        .ok()
+}
+
+fn write_marker(path: &Path, marker: &UpdateMarker) -> Result<(), ProgramError> {
+    let content =
+        serde_json::to_vec_pretty(marker).map_err(|e| ProgramError::WriteFailed(e.to_string()))?;
+    write_file_atomic(path, &content).map_err(|e| {
+        error!("Failed to write update marker: {}", e);
+        ProgramError::WriteFailed(path.display().to_string())
+    })
+}
+
+fn remove_marker(path: &Path) {
+    let _ = std::fs::remove_file(path);
+}
+
+fn settings(app: &AppHandle) -> UpdateSettings {
+    app.store(ExternalFilePosition::Config.to_string())
+        .ok()
+        .and_then(|store| store.get(UPDATE_KEY))
+        .and_then(|x| serde_json::from_value(x).ok())
+        .unwrap_or_default()
+}
+
+/// 只允许https，以及本机的http地址（用于本地测试的清单服务器）
+pub(crate) fn check_endpoint(endpoint: &str) -> Result<(), ProgramError> {
+    let url = Url::parse(endpoint).map_err(|e| ProgramError::InvalidConfig(e.to_string()))?;
+    let loopback = url.host_str().is_some_and(|host| {
+        host == "localhost"
+            || host
+                .trim_start_matches('[')
+                .trim_end_matches(']')
+                .parse::<IpAddr>()
+                .is_ok_and(|ip| ip.is_loopback())
+    });
+    match url.scheme() {
+        "https" => Ok(()),
+        "http" if loopback => Ok(()),
+        scheme => Err(ProgramError::InvalidConfig(format!(
+            "update endpoint must use https, got {}",
+            scheme
+        ))),
+    }
+}
+
+fn endpoint(settings: &UpdateSettings) -> Option<&str> {
+    settings
+        .endpoint
+        .as_deref()
+        .or(DEFAULT_ENDPOINT)
+        .filter(|x| !x.trim().is_empty())
+}
+
+fn fetch_manifest(settings: &UpdateSettings) -> Result<(ReleaseManifest, Version), ProgramError> {
+    let endpoint = endpoint(settings).ok_or(ProgramError::UpdateNotConfigured)?;
+    check_endpoint(endpoint)?;
+    let url = format!(
+        "{}/{}.json",
+        endpoint.trim_end_matches('/'),
+        settings.channel.name()
+    );
+    let response = ureq::AgentBuilder::new()
+        .timeout(FETCH_TIMEOUT)
+        .build()
+        .get(&url)
+        .call()
+        .map_err(|e| ProgramError::DownloadFailed(format!("{}: {}", url, e)))?;
+    let mut content = vec![];
+    response
+        .into_reader()
+        .take(MAX_MANIFEST_SIZE + 1)
+        .read_to_end(&mut content)
+        .map_err(|e| ProgramError::DownloadFailed(e.to_string()))?;
+    if content.len() as u64 > MAX_MANIFEST_SIZE {
+        return Err(ProgramError::InvalidReleaseManifest(
+            "manifest is too large".to_string(),
+        ));
+    }
+    ReleaseManifest::parse(&content, settings.channel)
+}
+
+/// 检查是否有比当前版本更新、且支持当前平台的版本
+pub(crate) fn check(app: &AppHandle) -> Result<Option<ReleaseInfo>, ProgramError> {
+    let (manifest, version) = fetch_manifest(&settings(app))?;
+    let current = &app.package_info().version;
+    let newer = &version > current && manifest.artifact().is_some();
+    let info = manifest.info();
+    let updater = app.state::<Updater>();
+    let mut state = updater.0.lock().unwrap();
+    if newer {
+        info!("Update {} available, current version {}", version, current);
+        state.available = Some(manifest);
+        Ok(Some(info))
+    } else {
+        state.available = None;
+        Ok(None)
+    }
+}
+
+/// 新版本程序文件的保存位置
+fn staged_path(manifest: &ReleaseManifest) -> Result<PathBuf, ProgramError> {
+    let exe = std::env::current_exe().map_err(|e| ProgramError::UpdateFailed(e.to_string()))?;
+    let name = exe
+        .file_name()
+        .ok_or_else(|| ProgramError::UpdateFailed(exe.display().to_string()))?;
+    Ok(
+        PathBuf::from(ExternalFilePosition::Update(manifest.version.clone()).to_string())
+            .join(name),
+    )
+}
+
+/// 校验程序文件的哈希和签名
+fn verify_artifact(artifact: &ReleaseArtifact, path: &Path) -> Result<(), ProgramError> {
+    let actual = sha256_file(path).map_err(|e| io_error(path, e))?;
+    if actual != artifact.sha256.to_ascii_lowercase() {
+        return Err(ProgramError::ChecksumMismatch(format!(
+            "{}: expected {}, got {}",
+            path.display(),
+            artifact.sha256,
+            actual
+        )));
+    }
+    let content = std::fs::read(path).map_err(|e| io_error(path, e))?;
+    verify(&content, &artifact.signature)
+}
+
+/// 下载已发现的新版本，未检查过时先检查
+pub(crate) fn download(
+    app: &AppHandle,
+    on_event: &dyn Fn(DownloadEvent),
+) -> Result<ReleaseInfo, ProgramError> {
+    let available = app.state::<Updater>().0.lock().unwrap().available.clone();
+    let manifest = match available {
+        Some(manifest) => manifest,
+        None => {
+            check(app)?;
+            app.state::<Updater>()
+                .0
+                .lock()
+                .unwrap()
+                .available
+                .clone()
+                .ok_or_else(|| ProgramError::NoUpdate(app.package_info().version.to_string()))?
+        }
+    };
+    let artifact = manifest
+        .artifact()
+        .ok_or_else(|| ProgramError::NoUpdate(manifest.version.clone()))?;
+    let target = staged_path(&manifest)?;
+    let manager = app.state::<DownloadManager>();
+    let _guard = manager.acquire(vec![target.clone()])?;
+    let file = DownloadArtifact {
+        url: artifact.url.clone(),
+        path: target
+            .file_name()
+            .map(|x| x.to_string_lossy().to_string())
+            .unwrap_or_default(),
+        sha256: artifact.sha256.clone(),
+        size: artifact.size,
+    };
+    downloader::download(&file, &target, on_event)?;
+    if let Err(e) = verify_artifact(artifact, &target) {
+        let _ = std::fs::remove_file(&target);
+        return Err(e);
+    }
+    info!("Update {} downloaded and verified", manifest.version);
+    let info = manifest.info();
+    app.state::<Updater>().0.lock().unwrap().staged = Some((manifest, target));
+    Ok(info)
+}
+
+/// 用新版本替换当前程序，启动看护进程后退出
+pub(crate) fn install(app: &AppHandle) -> Result<(), ProgramError> {
+    let staged = app.state::<Updater>().0.lock().unwrap().staged.clone();
+    let (manifest, path) =
+        staged.ok_or_else(|| ProgramError::NoUpdate("update is not downloaded".to_string()))?;
+    let artifact = manifest
+        .artifact()
+        .ok_or_else(|| ProgramError::NoUpdate(manifest.version.clone()))?;
+    // 下载后文件可能被改动，安装前重新校验
+    verify_artifact(artifact, &path)?;
+    let executable =
+        std::env::current_exe().map_err(|e| ProgramError::UpdateFailed(e.to_string()))?;
+    let backup = with_suffix(&executable, ".old");
+    let incoming = with_suffix(&executable, ".new");
+    // 先复制到程序所在目录，保证后续重命名在同一文件系统内
+    std::fs::copy(&path, &incoming).map_err(|e| io_error(&incoming, e))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&incoming, std::fs::Permissions::from_mode(0o755))
+            .map_err(|e| io_error(&incoming, e))?;
+    }
+    let _ = std::fs::remove_file(&backup);
+    // 运行中的程序可以重命名但不能覆盖
+    std::fs::rename(&executable, &backup).map_err(|e| io_error(&executable, e))?;
+    let marker_path = marker_path();
+    let restore = || {
+        let _ = std::fs::remove_file(&executable);
+        if let Err(e) = std::fs::rename(&backup, &executable) {
+            error!("Failed to restore {}: {}", executable.display(), e);
+        }
+        let _ = std::fs::remove_file(&incoming);
+        remove_marker(&marker_path);
+    };
+    if let Err(e) = std::fs::rename(&incoming, &executable) {
+        restore();
+        return Err(io_error(&executable, e));
+    }
+    let marker = UpdateMarker {
+        version: manifest.version.clone(),
+        previous_version: app.package_info().version.to_string(),
+        state: MarkerState::Pending,
+        executable: executable.clone(),
+        backup: backup.clone(),
+    };
+    if let Err(e) = write_marker(&marker_path, &marker) {
+        restore();
+        return Err(e);
+    }
+    let spawned = std::env::current_dir().and_then(|cwd| {
+        Command::new(&backup)
+            .arg(WATCHDOG_ARG)
+            .arg(std::process::id().to_string())
+            .current_dir(cwd)
+            .spawn()
+    });
+    if let Err(e) = spawned {
+        restore();
+        return Err(ProgramError::UpdateFailed(e.to_string()));
+    }
+    info!(
+        "Installed update {}, restarting from {}",
+        manifest.version, marker.previous_version
+    );
+    app.exit(0);
+    Ok(())
+}
+
+/// 以看护模式启动时执行看护并返回true，此时不应再启动界面
+pub(crate) fn run_watchdog() -> bool {
+    let mut args = std::env::args().skip(1);
+    if args.next().as_deref() != Some(WATCHDOG_ARG) {
+        return false;
+    }
+    let parent = args.next().and_then(|x| x.parse::<u32>().ok());
+    if let Err(e) = watch(parent, &marker_path()) {
+        error!("Update watchdog failed: {}", e);
+    }
+    true
+}
+
+fn wait_for_exit(pid: u32) {
+    let pid = Pid::from_u32(pid);
+    let mut system = System::new();
+    let started = Instant::now();
+    while started.elapsed() < EXIT_TIMEOUT {
+        system.refresh_processes(ProcessesToUpdate::Some(&[pid]), true);
+        if system.process(pid).is_none() {
+            return;
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+    warn!("Previous version {} did not exit in time", pid);
+}
+
+/// 启动新版本并等待其确认，失败时回滚
+fn watch(parent: Option<u32>, marker_path: &Path) -> Result<(), ProgramError> {
+    let mut marker = read_marker(marker_path)
+        .ok_or_else(|| ProgramError::MissingFile(marker_path.display().to_string()))?;
+    if marker.state != MarkerState::Pending {
+        return Ok(());
+    }
+    // 单例运行，旧版本退出后新版本才能启动
+    if let Some(parent) = parent {
+        wait_for_exit(parent);
+    }
+    let confirmed = || read_marker(marker_path).is_some_and(|x| x.state == MarkerState::Confirmed);
+    let cwd = std::env::current_dir().map_err(|e| ProgramError::UpdateFailed(e.to_string()))?;
+    let reason = match Command::new(&marker.executable).current_dir(&cwd).spawn() {
+        Ok(mut child) => {
+            let started = Instant::now();
+            loop {
+                std::thread::sleep(POLL_INTERVAL);
+                let exited = child.try_wait();
+                if confirmed() {
+                    info!("Update {} started", marker.version);
+                    return Ok(());
+                }
+                match exited {
+                    Ok(Some(status)) => break format!("exited with {}", status),
+                    Ok(None) => {}
+                    Err(e) => break e.to_string(),
+                }
+                if started.elapsed() > STARTUP_TIMEOUT {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    break "did not confirm startup in time".to_string();
+                }
+            }
+        }
+        Err(e) => e.to_string(),
+    };
+    warn!(
+        "Update {} failed to start: {}, rolling back to {}",
+        marker.version, reason, marker.previous_version
+    );
+    let failed = with_suffix(&marker.executable, ".failed");
+    let _ = std::fs::remove_file(&failed);
+    if let Err(e) = std::fs::rename(&marker.executable, &failed) {
+        warn!(
+            "Failed to move aside {}: {}",
+            marker.executable.display(),
+            e
+        );
+    }
+    std::fs::copy(&marker.backup, &marker.executable)
+        .map_err(|e| io_error(&marker.executable, e))?;
+    marker.state = MarkerState::RolledBack;
+    write_marker(marker_path, &marker)?;
+    Command::new(&marker.executable)
+        .current_dir(&cwd)
+        .spawn()
+        .map_err(|e| ProgramError::UpdateFailed(e.to_string()))?;
+    Ok(())
+}
+
+/// 启动完成后调用：确认新版本启动成功，清理上次更新留下的文件
+pub(crate) fn confirm_startup(app: &AppHandle) {
+    let executable = std::env::current_exe().ok();
+    let marker_path = marker_path();
+    let Some(mut marker) = read_marker(&marker_path) else {
+        if let Some(executable) = &executable {
+            let _ = std::fs::remove_file(with_suffix(executable, ".old"));
+        }
+        return;
+    };
+    let current = app.package_info().version.to_string();
+    match marker.state {
+        MarkerState::Pending if marker.version == current => {
+            marker.state = MarkerState::Confirmed;
+            if let Err(e) = write_marker(&marker_path, &marker) {
+                error!("Failed to confirm update: {}", e);
+            }
+        }
+        // 看护进程可能仍在运行备份文件，下次启动时再清理
+        MarkerState::Pending => {
+            warn!("Update marker for {} is stale", marker.version);
+            remove_marker(&marker_path);
+        }
+        MarkerState::Confirmed => {
+            let _ = std::fs::remove_file(&marker.backup);
+            remove_marker(&marker_path);
+        }
+        // 保留标记，用于提示和避免自动安装同一版本
+        MarkerState::RolledBack => {
+            let _ = std::fs::remove_file(with_suffix(&marker.executable, ".failed"));
+        }
+    }
+}
+
+/// 最近一次被回滚的版本
+fn rolled_back() -> Option<String> {
+    read_marker(&marker_path())
+        .filter(|x| x.state == MarkerState::RolledBack)
+        .map(|x| x.version)
+}
+
+/// 后台检查更新，发现新版本时通知前端，开启自动安装时直接下载安装
+pub(crate) fn start_update_check(app: AppHandle) {
+    if endpoint(&settings(&app)).is_none() {
+        return;
+    }
+    std::thread::spawn(move || {
+        let info = match check(&app) {
+            Ok(Some(info)) => info,
+            Ok(None) => return,
+            Err(e) => {
+                error!("Failed to check for updates: {}", e);
+                return;
+            }
+        };
+        if let Err(e) = app.emit("lers://update/available", &info) {
+            error!("Failed to emit update: {}", e);
+        }
+        // 启动失败过的版本只能手动安装
+        if !settings(&app).auto_install || rolled_back().as_ref() == Some(&info.version) {
+            return;
+        }
+        if let Err(e) = download(&app, &|_: DownloadEvent| {}).and_then(|_| install(&app)) {
+            error!("Failed to install update: {}", e);
+        }
+    });
+}
+
+/// 保存更新设置，切换通道或地址后需要重新检查
+#[tauri::command]
+pub(crate) fn set_update_settings(
+    app: AppHandle,
+    settings: UpdateSettings,
+    updater: State<Updater>,
+) -> Result<(), String> {
+    if let Some(endpoint) = &settings.endpoint {
+        check_endpoint(endpoint).map_err(|e| e.to_string())?;
+    }
+    let value = serde_json::to_value(&settings).map_err(|e| e.to_string())?;
+    persist_store_value(&app, UPDATE_KEY, value).map_err(|e| e.to_string())?;
+    updater.reset();
+    Ok(())
+}
+
+#[tauri::command]
+pub(crate) fn update_status(app: AppHandle, updater: State<Updater>) -> UpdateStatus {
+    let state = updater.0.lock().unwrap();
+    UpdateStatus {
+        current_version: app.package_info().version.to_string(),
+        settings: settings(&app),
+        available: state.available.as_ref().map(ReleaseManifest::info),
+        staged: state.staged.is_some(),
+        rolled_back: rolled_back(),
+    }
+}
+
+/// 检查更新，没有新版本时返回空
+#[tauri::command]
+pub(crate) async fn check_update(app: AppHandle) -> Result<Option<ReleaseInfo>, String> {
+    tauri::async_runtime::spawn_blocking(move || check(&app))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())
+}
+
+/// 下载并校验新版本，进度通过通道推送
+#[tauri::command]
+pub(crate) async fn download_update(
+    app: AppHandle,
+    on_event: Channel<DownloadEvent>,
+) -> Result<ReleaseInfo, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let on_event = |event: DownloadEvent| {
+            let _ = on_event.send(event);
+        };
+        download(&app, &on_event)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+    .map_err(|e| e.to_string())
+}
+
+/// 安装已下载的新版本，成功后程序会重启
+#[tauri::command]
+pub(crate) async fn install_update(app: AppHandle) -> Result<(), String> {
+    tauri::async_runtime::spawn_blocking(move || install(&app))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::signing::{envelope_with, sign_with, test_key};
+    #[cfg(unix)]
+    use crate::tools::test_util::write_script;
+    use crate::tools::test_util::{self, response, temp_dir};
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine;
+    use ed25519_dalek::SigningKey;
+    use sha2::{Digest, Sha256};
+    use std::collections::HashMap;
+
+    const PROGRAM: &[u8] = b"new program";
+
+    /// 本地清单服务器，按路径返回内容
+    fn serve(count: usize, files: HashMap<&'static str, Vec<u8>>) -> String {
+        test_util::serve(count, move |request| {
+            match files.get(request.path.as_str()) {
+                Some(body) => response("200 OK", body, body.len()),
+                None => response("404 Not Found", b"", 0),
+            }
+        })
+    }
+
+    fn manifest(channel: &str, version: &str, sha256: String, url: String) -> Vec<u8> {
+        serde_json::to_vec(&serde_json::json!({
+            "schema": MANIFEST_SCHEMA,
+            "channel": channel,
+            "version": version,
+            "platforms": [{
+                "os": std::env::consts::OS,
+                "arch": std::env::consts::ARCH,
+                "url": url,
+                "sha256": sha256,
+                "signature": sign_with(&test_key(), PROGRAM),
+            }],
+        }))
+        .unwrap()
+    }
+
+    fn program_sha256() -> String {
+        format!("{:x}", Sha256::digest(PROGRAM))
+    }
+
+    fn settings(endpoint: String, channel: UpdateChannel) -> UpdateSettings {
+        UpdateSettings {
+            channel,
+            endpoint: Some(endpoint),
+            auto_install: false,
+        }
+    }
+
+    fn fetch(
+        channel: UpdateChannel,
+        path: &'static str,
+        content: Vec<u8>,
+    ) -> Result<(ReleaseManifest, Version), ProgramError> {
+        let endpoint = serve(1, HashMap::from([(path, content)]));
+        fetch_manifest(&settings(endpoint, channel))
+    }
+
+    #[test]
+    fn accepts_signed_manifest() {
+        let payload = manifest("stable", "9.0.0", program_sha256(), String::new());
+        let (manifest, version) = fetch(
+            UpdateChannel::Stable,
+            "/stable.json",
+            envelope_with(&test_key(), &payload),
+        )
+        .unwrap();
+        assert_eq!(version, Version::new(9, 0, 0));
+        assert!(manifest.artifact().is_some());
+    }
+
+    #[test]
+    fn rejects_untrusted_signature() {
+        let payload = manifest("stable", "9.0.0", program_sha256(), String::new());
+        let untrusted = SigningKey::from_bytes(&[9u8; 32]);
+        let result = fetch(
+            UpdateChannel::Stable,
+            "/stable.json",
+            envelope_with(&untrusted, &payload),
+        );
+        assert!(matches!(result, Err(ProgramError::SignatureInvalid(_))));
+        // 签名后改动清单同样被拒绝
+        let mut envelope: serde_json::Value =
+            serde_json::from_slice(&envelope_with(&test_key(), &payload)).unwrap();
+        let tampered = manifest("stable", "9.0.1", program_sha256(), String::new());
+        envelope["payload"] = STANDARD.encode(tampered).into();
+        let result = fetch(
+            UpdateChannel::Stable,
+            "/stable.json",
+            serde_json::to_vec(&envelope).unwrap(),
+        );
+        assert!(matches!(result, Err(ProgramError::SignatureInvalid(_))));
+    }
+
+    #[test]
+    fn rejects_channel_mismatch() {
+        // beta清单被放到stable地址下
+        let payload = manifest("beta", "9.0.0", program_sha256(), String::new());
+        let result = fetch(
+            UpdateChannel::Stable,
+            "/stable.json",
+            envelope_with(&test_key(), &payload),
+        );
+        assert!(matches!(
+            result,
+            Err(ProgramError::InvalidReleaseManifest(_))
+        ));
+    }
+
+    #[test]
+    fn stable_rejects_pre_release() {
+        let payload = manifest("stable", "9.0.0-beta.1", program_sha256(), String::new());
+        let result = fetch(
+            UpdateChannel::Stable,
+            "/stable.json",
+            envelope_with(&test_key(), &payload),
+        );
+        assert!(matches!(
+            result,
+            Err(ProgramError::InvalidReleaseManifest(_))
+        ));
+        // beta通道允许预览版
+        let payload = manifest("beta", "9.0.0-beta.1", program_sha256(), String::new());
+        let (_, version) = fetch(
+            UpdateChannel::Beta,
+            "/beta.json",
+            envelope_with(&test_key(), &payload),
+        )
+        .unwrap();
+        assert!(!version.pre.is_empty());
+    }
+
+    #[test]
+    fn rejects_hash_mismatch() {
+        let wrong = format!("{:x}", Sha256::digest(b"other program"));
+        let endpoint = serve(1, HashMap::from([("/program", PROGRAM.to_vec())]));
+        let payload = manifest("stable", "9.0.0", wrong, format!("{}/program", endpoint));
+        let (manifest, _) =
+            ReleaseManifest::parse(&envelope_with(&test_key(), &payload), UpdateChannel::Stable)
+                .unwrap();
+        let artifact = manifest.artifact().unwrap();
+        let dir = temp_dir();
+        let target = dir.join("program");
+        // 下载时校验哈希，不匹配的文件不会留下
+        let file = DownloadArtifact {
+            url: artifact.url.clone(),
+            path: "program".to_string(),
+            sha256: artifact.sha256.clone(),
+            size: None,
+        };
+        let result = downloader::download(&file, &target, &|_| {});
+        assert!(matches!(result, Err(ProgramError::ChecksumMismatch(_))));
+        assert!(!target.exists());
+        // 安装前的复验同样拒绝
+        std::fs::write(&target, PROGRAM).unwrap();
+        assert!(matches!(
+            verify_artifact(artifact, &target),
+            Err(ProgramError::ChecksumMismatch(_))
+        ));
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn watch_rolls_back_failed_update() {
+        let dir = temp_dir();
+        let executable = dir.join("app");
+        let backup = with_suffix(&executable, ".old");
+        let started = dir.join("started");
+        // 新版本启动后立即退出，旧版本启动时留下标记文件
+        write_script(&executable, "exit 1");
+        write_script(&backup, &format!("touch '{}'", started.display()));
+        let marker_path = dir.join("update.json");
+        let marker = UpdateMarker {
+            version: "9.0.0".to_string(),
+            previous_version: "1.0.0".to_string(),
+            state: MarkerState::Pending,
+            executable: executable.clone(),
+            backup: backup.clone(),
+        };
+        write_marker(&marker_path, &marker).unwrap();
+        watch(None, &marker_path).unwrap();
+        let marker = read_marker(&marker_path).unwrap();
+        assert_eq!(marker.state, MarkerState::RolledBack);
+        assert_eq!(
+            std::fs::read(&executable).unwrap(),
+            std::fs::read(&backup).unwrap()
+        );
+        assert!(std::fs::read_to_string(with_suffix(&executable, ".failed"))
+            .unwrap()
+            .contains("exit 1"));
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !started.exists() && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        assert!(started.exists(), "previous version was not restarted");
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn watch_keeps_confirmed_update() {
+        let dir = temp_dir();
+        let executable = dir.join("app");
+        let marker_path = dir.join("update.json");
+        // 新版本把标记改为已确认，模拟confirm_startup
+        write_script(
+            &executable,
+            &format!(
+                "sed -i 's/\"pending\"/\"confirmed\"/' '{}'; sleep 5",
+                marker_path.display()
+            ),
+        );
+        let marker = UpdateMarker {
+            version: "9.0.0".to_string(),
+            previous_version: "1.0.0".to_string(),
+            state: MarkerState::Pending,
+            executable: executable.clone(),
+            backup: with_suffix(&executable, ".old"),
+        };
+        write_marker(&marker_path, &marker).unwrap();
+        watch(None, &marker_path).unwrap();
+        assert_eq!(
+            read_marker(&marker_path).unwrap().state,
+            MarkerState::Confirmed
+        );
+        assert!(!with_suffix(&executable, ".failed").exists());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
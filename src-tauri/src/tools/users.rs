@@ -13,6 +13,8 @@ pub(crate) struct User {
     pub(crate) name: String,
     pub(crate) nat_traversal_type: String,
     pub(crate) status: bool,
+    /// p2p连接使用的底层地址族，ipv4或ipv6，非p2p时为空
+    pub(crate) underlay: Option<String>,
 }
 
 impl User {
@@ -22,10 +24,19 @@ impl User {
             name,
             nat_traversal_type,
             status,
+            underlay: None,
         }
     }
 }
 
+/// 判断路由地址的地址族，ipv4映射的ipv6地址视为ipv4
+fn underlay_of(addr: &std::net::SocketAddr) -> &'static str {
+    match addr.ip() {
+        std::net::IpAddr::V6(ip) if ip.to_ipv4_mapped().is_none() => "ipv6",
+        _ => "ipv4",
+    }
+}
+
 /// 获取用户列表
 #[tauri::command]
 pub(crate) fn get_user_list(status: State<'_, Mutex<Status>>) -> Result<Vec<User>, String> {
@@ -74,8 +85,12 @@ pub(crate) fn _fresh_user_list(vnt: Vnt, users: Vec<User>) -> Result<Vec<User>,
         match Ipv4Addr::from_str(&user.ip) {
             Ok(ip) => {
                 let mut nat_traversal_type = String::from("PSP");
+                let mut underlay = None;
                 // 判断连接模式
                 if let Some(route) = vnt.route(&ip) {
+                    if route.is_p2p() {
+                        underlay = Some(underlay_of(&route.addr).to_string());
+                    }
                     nat_traversal_type = if route.is_p2p() {
                         if route.protocol.is_base_tcp() {
                             "P2P_TCP"
@@ -97,6 +112,7 @@ pub(crate) fn _fresh_user_list(vnt: Vnt, users: Vec<User>) -> Result<Vec<User>,
                     .to_string();
                 }
                 user.nat_traversal_type = nat_traversal_type;
+                user.underlay = underlay;
             }
             Err(_) => {
                 return Err("Failed to parse ip".to_string());
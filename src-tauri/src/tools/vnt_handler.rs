@@ -1,7 +1,12 @@
 use crate::errors::ProgramError;
+use crate::tools::failover::{probe_servers, Failover, ServerProbe};
+use crate::tools::migration::ConfigLoadError;
+use crate::tools::port_mapping::{mapping_ports, start_port_mapping, stop_port_mapping};
+use crate::tools::stun::select_stun_servers;
+use crate::tools::supervisor::{on_vnt_started, on_vnt_stopped};
 use crate::tools::users::{User, _fresh_user_list};
 use crate::tools::{do_vecs_match, Status};
-use log::{error, info};
+use log::{error, info, warn};
 use std::sync::atomic::Ordering;
 use std::sync::Mutex;
 use std::thread::sleep;
@@ -13,11 +18,13 @@ use vnt::{DeviceInfo, ErrorInfo, PeerClientInfo, RegisterInfo};
 #[derive(Clone)]
 pub(crate) struct VntHandler {
     app: tauri::AppHandle,
+    /// 创建时的vnt代数，忽略已被替换的vnt的回调
+    generation: u64,
 }
 
 impl VntHandler {
-    pub(crate) fn new(app: tauri::AppHandle) -> Self {
-        Self { app }
+    pub(crate) fn new(app: tauri::AppHandle, generation: u64) -> Self {
+        Self { app, generation }
     }
 }
 
@@ -26,6 +33,9 @@ impl vnt::VntCallback for VntHandler {
         info!("vnt started");
         let binding = self.app.state::<Mutex<Status>>();
         let status = binding.lock().unwrap();
+        if status.generation != self.generation {
+            return;
+        }
         status.running.store(true, Ordering::Relaxed);
         if let Err(e) = self.app.emit("lers://vnt/status", true) {
             error!("Failed to emit status: {}", e);
@@ -40,6 +50,12 @@ impl vnt::VntCallback for VntHandler {
         info!("vnt register: {}", _info);
         let binding = self.app.state::<Mutex<Status>>();
         let mut status = binding.lock().unwrap();
+        if status.generation != self.generation {
+            return true;
+        }
+        if let Some(failover) = status.failover.as_mut() {
+            failover.on_registered();
+        }
         status.virtual_ip = _info.virtual_ip.to_string();
         if let Err(e) = self
             .app
@@ -58,13 +74,18 @@ impl vnt::VntCallback for VntHandler {
             .iter()
             .map(|x| {
                 let nat_type;
+                let mut underlay = None;
                 let virtual_ip = x.virtual_ip.clone().to_string();
                 if let Some(user) = status.users.iter().find(|user| user.ip == virtual_ip) {
                     nat_type = user.nat_traversal_type.clone();
+                    underlay = user.underlay.clone();
                 } else {
                     nat_type = String::from("PSP");
                 }
-                User::new(virtual_ip, x.name.clone(), nat_type, x.status.is_online())
+                let mut user =
+                    User::new(virtual_ip, x.name.clone(), nat_type, x.status.is_online());
+                user.underlay = underlay;
+                user
             })
             .collect::<Vec<_>>();
         // 如果用户列表发生变化，则更新用户列表
@@ -75,6 +96,18 @@ impl vnt::VntCallback for VntHandler {
     }
 
     fn error(&self, _info: ErrorInfo) {
+        let binding = self.app.state::<Mutex<Status>>();
+        if let Ok(mut status) = binding.lock() {
+            if status.generation == self.generation {
+                if let Some(failover) = status.failover.as_mut() {
+                    if matches!(_info.code, vnt::ErrorType::Disconnect) {
+                        failover.on_disconnected();
+                    } else {
+                        failover.on_error();
+                    }
+                }
+            }
+        }
         error!(
             "vnt Error:[{:?}] {}",
             _info.code,
@@ -86,6 +119,9 @@ impl vnt::VntCallback for VntHandler {
         info!("vnt stopped");
         let binding = self.app.state::<Mutex<Status>>();
         let mut status = binding.lock().unwrap();
+        if status.generation != self.generation {
+            return;
+        }
         status.running.store(false, Ordering::Relaxed);
         if let Err(e) = self.app.emit("lers://vnt/status", false) {
             error!("Failed to emit status: {}", e);
@@ -95,46 +131,173 @@ impl vnt::VntCallback for VntHandler {
     }
 }
 
-/// 启动vnt
+/// 使用当前故障切换状态中的服务器创建并启动vnt
+///
+/// stun服务器需要在加锁前选好，探测耗时较长
+fn launch_vnt(
+    app: &tauri::AppHandle,
+    status: &mut Status,
+    stun_servers: Vec<String>,
+) -> Result<(), String> {
+    let Some(server) = status.failover.as_ref().and_then(|x| x.active()).cloned() else {
+        return Err("No server available".to_string());
+    };
+    let mut config = status.config.clone();
+    config.server_address_str = server.clone();
+    config.stun_server = Some(stun_servers);
+    let config = match config.get_vnt_config() {
+        Ok(config) => config,
+        Err(e) => match e.downcast_ref::<ProgramError>() {
+            Some(e) => return Err(e.to_string()),
+            None => return Err(ProgramError::ConfigBuildFailed.to_string()),
+        },
+    };
+    status.generation += 1;
+    let Ok(vnt) = Vnt::new(config, VntHandler::new(app.clone(), status.generation)) else {
+        return Err("Failed to start vnt".to_string());
+    };
+    info!("vnt using server {}", server);
+    // 启动vnt
+    let vnt_clone = vnt.clone();
+    std::thread::spawn(move || {
+        vnt_clone.wait();
+        info!("vnt thread stopped")
+    });
+    // 启动用户打洞类型侦测
+    get_nat_traversal_type(app.clone(), vnt.clone(), status.generation);
+    status.vnt.replace(vnt);
+    if let Err(e) = app.emit("lers://vnt/server", server) {
+        error!("Failed to emit server: {}", e);
+    }
+    Ok(())
+}
+
+/// 监视服务器连接，注册失败或断开超时后切换到下一个服务器
+fn watch_failover(app: tauri::AppHandle, session: u64) {
+    std::thread::spawn(move || {
+        loop {
+            sleep(Duration::from_secs(2));
+            let binding = app.state::<Mutex<Status>>();
+            let stun_candidates = {
+                let Ok(mut status) = binding.lock() else {
+                    break;
+                };
+                let Some(failover) = status.failover.as_mut() else {
+                    break;
+                };
+                if failover.session != session {
+                    break;
+                }
+                if !failover.should_switch() {
+                    continue;
+                }
+                let server = failover.advance().cloned().unwrap_or_default();
+                warn!("server connection lost, switching to {}", server);
+                if let Some(vnt) = status.vnt.take() {
+                    vnt.stop();
+                }
+                status.config.stun_candidates()
+            };
+            // 只使用探测可用的stun服务器，探测期间不持有锁
+            let stun_servers = select_stun_servers(&app, &stun_candidates);
+            let Ok(mut status) = binding.lock() else {
+                break;
+            };
+            // 探测期间可能已停止或重新启动
+            if status.failover.as_ref().map(|x| x.session) != Some(session) {
+                break;
+            }
+            if let Err(e) = launch_vnt(&app, &mut status, stun_servers) {
+                error!("Failed to switch server: {}", e);
+                status.failover = None;
+                break;
+            }
+        }
+        info!("watch_failover thread stopped")
+    });
+}
+
+/// 启动vnt，先探测所有服务器并使用最优的一个
 #[tauri::command]
 pub(crate) fn start_vnt(
     app: tauri::AppHandle,
     status: State<'_, Mutex<Status>>,
 ) -> Result<(), String> {
-    match status
+    app.state::<ConfigLoadError>()
+        .check()
+        .map_err(|e| e.to_string())?;
+    // 探测期间不持有锁
+    let (candidates, stun_candidates) = match status
+        .lock()
+        .map_err(|e| ProgramError::ReadFailed(e.to_string()))
+    {
+        Ok(status) => {
+            if status.secrets_locked {
+                return Err(ProgramError::SecretsLocked.to_string());
+            }
+            status
+                .config
+                .validate()
+                .and_then(|_| status.config.validate_servers())
+                .map_err(|e| e.to_string())?;
+            (
+                status.config.server_candidates(),
+                status.config.stun_candidates(),
+            )
+        }
+        Err(e) => return Err(e.to_string()),
+    };
+    // 只使用探测可用的stun服务器
+    let stun_servers = select_stun_servers(&app, &stun_candidates);
+    let probes = probe_servers(&candidates);
+    info!("server probes: {:?}", probes);
+    if let Err(e) = app.emit("lers://vnt/server_probes", probes.clone()) {
+        error!("Failed to emit server probes: {}", e);
+    }
+    let result = match status
         .lock()
         .map_err(|e| ProgramError::ReadFailed(e.to_string()))
     {
         Ok(mut status) => {
-            let Ok(config) = status.config.get_vnt_config() else {
-                return Err(ProgramError::ConfigBuildFailed.to_string());
-            };
-            let Ok(vnt) = Vnt::new(config, VntHandler::new(app.clone())) else {
-                return Err("Failed to start vnt".to_string());
-            };
-            // 启动vnt
-            let vnt_clone = vnt.clone();
-            std::thread::spawn(move || {
-                vnt_clone.wait();
-                info!("vnt thread stopped")
-            });
-            // 启动用户打洞类型侦测
-            get_nat_traversal_type(app.clone(), vnt.clone());
-            status.vnt.replace(vnt);
+            let session = status.generation + 1;
+            let servers = probes.into_iter().map(|x| x.address).collect();
+            status.failover = Some(Failover::new(session, servers));
+            if let Err(e) = launch_vnt(&app, &mut status, stun_servers) {
+                status.failover = None;
+                return Err(e);
+            }
+            watch_failover(app.clone(), session);
+            // 自动端口映射
+            let advanced = &status.config.advanced;
+            if advanced.port_mapping {
+                let ports = mapping_ports(advanced.ports.as_deref().unwrap_or_default());
+                start_port_mapping(app.clone(), ports);
+            }
             Ok(())
         }
         Err(e) => Err(e.to_string()),
+    };
+    // 启动随vnt运行的工具，此时已释放锁
+    if result.is_ok() {
+        on_vnt_started(&app);
     }
+    result
 }
 
 /// 停止vnt
 #[tauri::command]
-pub(crate) fn stop_vnt(status: State<'_, Mutex<Status>>) -> Result<(), String> {
+pub(crate) fn stop_vnt(
+    app: tauri::AppHandle,
+    status: State<'_, Mutex<Status>>,
+) -> Result<(), String> {
+    stop_port_mapping(&app);
+    on_vnt_stopped(&app);
     match status
         .lock()
         .map_err(|e| ProgramError::ReadFailed(e.to_string()))
     {
         Ok(mut status) => {
+            status.failover = None;
             if let Some(vnt) = status.vnt.take() {
                 vnt.stop();
                 return Ok(());
@@ -145,6 +308,29 @@ pub(crate) fn stop_vnt(status: State<'_, Mutex<Status>>) -> Result<(), String> {
     }
 }
 
+/// 探测配置中的所有服务器
+#[tauri::command]
+pub(crate) fn probe_server_list(
+    status: State<'_, Mutex<Status>>,
+) -> Result<Vec<ServerProbe>, String> {
+    let candidates = match status.lock() {
+        Ok(status) => status.config.server_candidates(),
+        Err(e) => return Err(e.to_string()),
+    };
+    Ok(probe_servers(&candidates))
+}
+
+/// 获取当前使用的服务器
+#[tauri::command]
+pub(crate) fn get_active_server(
+    status: State<'_, Mutex<Status>>,
+) -> Result<Option<String>, String> {
+    match status.lock() {
+        Ok(status) => Ok(status.failover.as_ref().and_then(|x| x.active()).cloned()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
 /// 获取vnt运行状态
 #[tauri::command]
 pub(crate) fn get_running_status(status: State<'_, Mutex<Status>>) -> Result<bool, String> {
@@ -163,13 +349,21 @@ pub(crate) fn get_virtual_ip(status: State<'_, Mutex<Status>>) -> Result<String,
     }
 }
 
-/// 五分钟查询一次用户打洞类型和本机nat类型
-pub(crate) fn get_nat_traversal_type(app: tauri::AppHandle, vnt: Vnt) {
+/// 五分钟查询一次用户打洞类型和本机nat类型，vnt被替换后退出
+pub(crate) fn get_nat_traversal_type(app: tauri::AppHandle, vnt: Vnt, generation: u64) {
     let app_clone = app.clone();
     std::thread::spawn(move || {
         // 延迟3秒，避免vnt未启动
         sleep(Duration::from_secs(3));
         let status = app_clone.state::<Mutex<Status>>();
+        if status
+            .lock()
+            .map(|x| x.generation != generation)
+            .unwrap_or(true)
+        {
+            info!("get_nat_traversal_type thread stopped");
+            return;
+        }
         let nat_type = match vnt.nat_info().nat_type {
             vnt::channel::punch::NatType::Cone => "圆锥形",
             vnt::channel::punch::NatType::Symmetric => "对称型",
@@ -180,7 +374,7 @@ pub(crate) fn get_nat_traversal_type(app: tauri::AppHandle, vnt: Vnt) {
         loop {
             match status.lock() {
                 Ok(mut status) => {
-                    if status.running.load(Ordering::Relaxed) {
+                    if status.generation == generation && status.running.load(Ordering::Relaxed) {
                         let new_users = status.users.clone();
                         match _fresh_user_list(vnt.clone(), new_users) {
                             Ok(new_users) => {
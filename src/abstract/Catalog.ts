@@ -0,0 +1,81 @@
+import {Channel, invoke} from "@tauri-apps/api/core";
+import {DownloadEvent} from "./Downloader.ts";
+import {InstalledTool, InstallSource} from "./Installer.ts";
+
+// 清单来源，远程地址或本地文件
+export type CatalogSource = { url: string } | { file: string }
+
+export type CatalogToolTag = "download" | "command" | "switch" | "button" | "args_get"
+
+export type PlatformArtifact = InstallSource & {
+    // 与Rust的std::env::consts::OS/ARCH一致，如windows、x86_64
+    os: string
+    arch: string
+}
+
+export type ToolPermissions = {
+    // 允许设置的环境变量
+    env: string[]
+    // 是否允许接管标准输入
+    stdin: boolean
+    // 同一时间只允许运行一个实例
+    singleton: boolean
+}
+
+export type CatalogTool = {
+    name: string
+    display_name: string | null
+    description: string
+    link: string | null
+    // 图标，emoji或图片地址
+    icon: string | null
+    version: string
+    tags: CatalogToolTag[]
+    platforms: PlatformArtifact[]
+    // 入口命令，相对于安装目录
+    entry: string | null
+    // 参数模板，可包含网络占位符
+    arg_template: string[]
+    permissions: ToolPermissions
+    browser: string | null
+}
+
+export type ToolListing = CatalogTool & {
+    // 当前平台是否有安装包
+    available: boolean
+    installed: InstalledTool | null
+    // 已安装但版本与清单不同
+    update_available: boolean
+}
+
+export async function list_tools(): Promise<ToolListing[]> {
+    return await invoke<ToolListing[]>("list_tools")
+}
+
+// 安装清单中的当前版本
+export async function install_tool(
+    name: string,
+    handler?: (event: DownloadEvent) => void
+): Promise<InstalledTool> {
+    const onEvent = new Channel<DownloadEvent>()
+    if (handler) {
+        onEvent.onmessage = handler
+    }
+    return await invoke<InstalledTool>("install_tool", {
+        name,
+        onEvent
+    })
+}
+
+export async function remove_tool(name: string): Promise<void> {
+    await invoke("remove_tool", {name})
+}
+
+// 设置后立即加载，为空时停止从外部更新
+export async function set_catalog_source(source: CatalogSource | null): Promise<boolean> {
+    return await invoke<boolean>("set_catalog_source", {source})
+}
+
+export async function reload_catalog(): Promise<boolean> {
+    return await invoke<boolean>("reload_catalog")
+}
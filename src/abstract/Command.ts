@@ -6,7 +6,7 @@ export interface TerminatedPayload {
     signal: number | null
 }
 
-type CommandEvent =
+export type CommandEvent =
     | {
     event: "stdout",
     payload: string
@@ -14,15 +14,106 @@ type CommandEvent =
     | {
     event: "error",
     payload: string
+}
+    | {
+    // 伪终端模式下的原始输出，保留ANSI控制序列
+    event: "data",
+    payload: number[]
 }
     | {
     event: "terminated",
     payload: TerminatedPayload
 }
 
-interface CommandEvents {
+export interface SpawnOptions {
+    // 工作目录
+    cwd?: string
+    // 追加的环境变量
+    env?: Record<string, string>
+    // 接管标准输入，之后可通过Child.write写入
+    stdin?: boolean
+    // 内存中保留的输出行数，伪终端模式下为输出块数，默认1000
+    buffer_lines?: number
+    // 同时写入按工具名轮换的日志文件
+    log_file?: boolean
+    // 进程树的资源限制
+    limits?: ResourceLimits | null
+    // 在该尺寸的伪终端中运行，输出以data事件推送，Child.write写入终端
+    pty?: TerminalSize | null
+}
+
+export type TerminalSize = {
+    cols: number
+    rows: number
+}
+
+export type ResourceLimits = {
+    // 100表示占满一个核心
+    cpu_percent?: number | null
+    rss_bytes?: number | null
+    action?: "warn" | "kill"
+    // 连续超限多少次采样后才处理，默认3
+    sustained?: number
+}
+
+export type Usage = {
+    cpu_percent: number
+    rss_bytes: number
+    threads: number | null
+}
+
+// lers://child/resources事件推送的采样
+export type ResourceSample = {
+    id: string
+    tool: string
+    pid: number
+    uptime_secs: number
+    process: Usage
+    tree: Usage
+    tree_processes: number
+}
+
+// lers://child/resource_limit事件
+export type LimitExceeded = {
+    id: string
+    tool: string
+    resource: "cpu" | "memory"
+    value: number
+    limit: number
+    action: "warn" | "kill"
+}
+
+// command_spawn拒绝启动时返回的错误
+export type SpawnRejection = {
+    kind: "unknown_tool" | "tools_dir_unavailable" | "executable_missing" | "outside_tools_dir"
+        | "argument_count" | "invalid_argument" | "template" | "env_not_allowed" | "stdin_not_allowed"
+        | "already_running" | "spawn_failed"
+    message: string
+    [detail: string]: unknown
+}
+
+export type ChildState =
+    | { state: "running" }
+    | { state: "stopping" }
+    | { state: "exited", code: number | null, signal: number | null }
+
+export type ChildInfo = {
+    id: string
+    pid: number
+    command: string
+    args: string[]
+    // 是否运行在伪终端中
+    terminal: boolean
+    // unix时间戳(毫秒)
+    started_at: number
+    // 最近一次资源采样
+    resources: ResourceSample | null
+} & ChildState
+
+export interface CommandEvents {
     stdout: string,
     error: string,
+    data: Uint8Array,
     terminated: TerminatedPayload
 }
 
@@ -30,26 +121,32 @@ export class Command extends EventEmitter<CommandEvents> {
     private readonly command: string
     private readonly args: string[]
     private readonly hide: boolean
+    private readonly options: SpawnOptions
 
+    // tool为Rust端注册的工具名
+    // args支持占位符{virtual_ip}、{server}、{online_peer_ips}、{peer:<name>.ip}，{{和}}表示字面量花括号
     static create(
-        program: string,
+        tool: string,
         args: string | string[] = [],
-        hide: boolean = false
+        hide: boolean = false,
+        options: SpawnOptions = {}
     ): Command {
-        return new Command(program, args, hide)
+        return new Command(tool, args, hide, options)
     }
 
-    constructor(command: string, args: string | string[] = [], hide: boolean) {
+    constructor(command: string, args: string | string[] = [], hide: boolean, options: SpawnOptions = {}) {
         super();
         this.command = command
         this.args = typeof args === 'string' ? [args] : args
         this.hide = hide
+        this.options = options
     }
 
     async spawn(): Promise<Child> {
-        const command = this.command
+        const tool = this.command
         const args = this.args
         const hide = this.hide
+        const options = this.options
 
         if (typeof args === 'object') {
             Object.freeze(args)
@@ -64,30 +161,71 @@ export class Command extends EventEmitter<CommandEvents> {
                 case "error":
                     this.emit("error", event.payload)
                     break
+                case "data":
+                    this.emit("data", Uint8Array.from(event.payload))
+                    break
                 case "terminated":
                     this.emit("terminated", event.payload)
                     break
             }
         }
-        return await invoke<number>("command_spawn", {
-            command,
+        return await invoke<string>("command_spawn", {
+            tool,
             args,
             hide,
+            options,
             onEvent
-        }).then((pid) => new Child(pid))
+        }).then((id) => new Child(id))
     }
 }
 
 export class Child {
-    pid: number
+    // 子进程句柄，不同于系统PID
+    id: string
 
-    constructor(pid: number) {
-        this.pid = pid
+    constructor(id: string) {
+        this.id = id
     }
 
     async kill(): Promise<void> {
         await invoke("child_kill", {
-            pid: this.pid
+            id: this.id
         })
     }
+
+    async write(data: string): Promise<void> {
+        await invoke("child_write_stdin", {
+            id: this.id,
+            data
+        })
+    }
+
+    // 调整伪终端的尺寸
+    async resize(size: TerminalSize): Promise<void> {
+        await invoke("child_resize", {
+            id: this.id,
+            cols: size.cols,
+            rows: size.rows
+        })
+    }
+
+    async status(): Promise<ChildInfo> {
+        return await invoke<ChildInfo>("child_status", {
+            id: this.id
+        })
+    }
+}
+
+// 回放子进程缓冲的输出后继续推送实时输出，页面刷新后可借此重新观察工具
+export async function child_attach(id: string, handler: (event: CommandEvent) => void): Promise<void> {
+    const onEvent = new Channel<CommandEvent>()
+    onEvent.onmessage = handler
+    await invoke("child_attach", {
+        id,
+        onEvent
+    })
+}
+
+export async function list_children(): Promise<ChildInfo[]> {
+    return await invoke<ChildInfo[]>("list_children")
 }
@@ -0,0 +1,19 @@
+// 工具安装和程序更新的下载进度
+export type DownloadEvent =
+    | {
+    // 续传时downloaded为已有的字节数
+    event: "started",
+    payload: { path: string, downloaded: number, total: number | null }
+}
+    | {
+    event: "progress",
+    payload: { path: string, downloaded: number, total: number | null, bytesPerSecond: number }
+}
+    | {
+    event: "verifying",
+    payload: { path: string }
+}
+    | {
+    event: "finished",
+    payload: { path: string }
+}
@@ -0,0 +1,48 @@
+import {invoke} from "@tauri-apps/api/core";
+
+export type ArchiveFormat = "zip" | "tar_gz" | "tar_xz" | "file"
+
+// 工具清单中的安装来源，由Rust端下载安装
+export type InstallSource = {
+    url: string
+    // 安装包的SHA-256，十六进制
+    sha256: string
+    size?: number | null
+    // 缺省时按URL的后缀推断
+    format?: ArchiveFormat | null
+    // 解压时去掉的前导目录层数
+    strip_components?: number
+}
+
+export type InstalledVersion = {
+    version: string
+    size_bytes: number
+}
+
+export type InstalledTool = {
+    tool: string
+    // 当前使用的版本
+    active: string | null
+    versions: InstalledVersion[]
+    // 全部版本占用的磁盘空间
+    size_bytes: number
+}
+
+export async function set_active_version(tool: string, version: string): Promise<InstalledTool> {
+    return await invoke<InstalledTool>("set_active_version", {
+        tool,
+        version
+    })
+}
+
+// 未指定版本时卸载全部版本
+export async function uninstall_tool(tool: string, version?: string): Promise<InstalledTool | null> {
+    return await invoke<InstalledTool | null>("uninstall_tool", {
+        tool,
+        version: version ?? null
+    })
+}
+
+export async function list_installed_tools(): Promise<InstalledTool[]> {
+    return await invoke<InstalledTool[]>("list_installed_tools")
+}
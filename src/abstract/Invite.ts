@@ -0,0 +1,73 @@
+import {invoke} from "@tauri-apps/api/core";
+import {Config} from "./LocalConfig.ts";
+
+export type InviteLink = {
+    code: string,
+    uri: string,
+    fingerprint: string,
+}
+
+export type InvitePreview = {
+    profile: string,
+    server: string,
+    // 邀请者的服务器列表, 为空时只使用server
+    servers: Array<string>,
+    cipher_mode: string,
+    has_password: boolean,
+    password_required: boolean,
+    expires_at: number | null,
+    inviter: string,
+}
+
+export type Profile = {
+    name: string,
+    config: Config,
+}
+
+export async function create_invite(profile: string | null, include_password: boolean, expires_in: number | null): Promise<InviteLink> {
+    return await invoke<InviteLink>("create_invite", {
+        profile,
+        includePassword: include_password,
+        expiresIn: expires_in
+    })
+}
+
+export async function preview_invite(invite: string): Promise<InvitePreview> {
+    return await invoke<InvitePreview>("preview_invite", {invite})
+}
+
+export async function take_pending_invite(): Promise<string | null> {
+    return await invoke<string | null>("take_pending_invite")
+}
+
+// server和inviter为用户在预览中确认过的值, 返回是否需要重连才能生效
+export async function join_invite(invite: string, password: string | null, server: string, inviter: string): Promise<boolean> {
+    return await invoke<boolean>("join_invite", {invite, password, server, inviter})
+}
+
+export async function list_profiles(): Promise<Profile[]> {
+    return await invoke<Profile[]>("list_profiles")
+}
+
+export async function use_profile(name: string): Promise<boolean> {
+    return await invoke<boolean>("use_profile", {name})
+}
+
+export async function delete_profile(name: string): Promise<void> {
+    return await invoke("delete_profile", {name})
+}
+
+export type QrFormat = "svg" | "png"
+
+// 返回二维码图片字节
+export async function generate_invite_qr(profile: string | null, include_password: boolean, format: QrFormat = "svg"): Promise<ArrayBuffer> {
+    return await invoke<ArrayBuffer>("generate_invite_qr", {
+        profile,
+        includePassword: include_password,
+        format
+    })
+}
+
+export async function import_invite_qr(path: string, password: string | null): Promise<Profile> {
+    return await invoke<Profile>("import_invite_qr", {path, password})
+}
@@ -1,17 +1,97 @@
 import {invoke} from "@tauri-apps/api/core";
 
+export type PunchMode = "ipv4" | "ipv6" | "ipv4_tcp" | "ipv4_udp" | "ipv6_tcp" | "ipv6_udp" | "all"
+
+export type Ipv6Mode = "only" | "allow" | "disable"
+
+export type ChannelMode = "relay" | "p2p" | "all"
+
+export type CompressMode = "none" | "lz4"
+
+export type CipherMode = "none" | "xor" | "aes_gcm" | "chacha20_poly1305"
+
+export type AdvancedConfig = {
+    mtu: number | null,
+    device_name: string | null,
+    ports: Array<number> | null,
+    punch_mode: PunchMode,
+    ipv6_mode: Ipv6Mode,
+    // 按延迟选择通道
+    first_latency: boolean,
+    channel_mode: ChannelMode,
+    compress_mode: CompressMode,
+    cipher_mode: CipherMode,
+    // 自动在路由器上映射端口，需要固定端口
+    port_mapping: boolean,
+}
+
+export type ServerEntry = {
+    address: string,
+    // 数值越小越优先
+    priority: number,
+}
+
 export type Config = {
     token: string,
     name: string,
     server_address_str: string,
     stun_server: Array<String> | null,
     device_id: string,
+    password?: string | null,
+    servers?: Array<ServerEntry>,
+    advanced?: AdvancedConfig,
+}
+
+// 默认不返回明文密钥，reveal为true时返回
+export async function get_config(reveal: boolean = false): Promise<Config> {
+    return await invoke("get_config", {reveal}) as Config;
+}
+
+export type ConfigChanged = {
+    config: Config,
+    reconnect_required: boolean,
 }
 
-export async function get_config(): Promise<Config> {
-    return await invoke("get_config") as Config;
+// 返回是否需要重连才能生效
+export async function set_config(config: Config): Promise<boolean> {
+    return await invoke<boolean>("set_config", {"config": config})
 }
+export type ConfigFormat = "json" | "toml"
 
-export async function set_config(config: Config): Promise<void> {
-    return await invoke("set_config", {"config": config})
-}
\ No newline at end of file
+export type ConflictStrategy = "skip" | "overwrite" | "rename"
+
+export type ImportPreview = {
+    format: ConfigFormat,
+    server: string,
+    config_redacted: boolean,
+    profiles: Array<{
+        name: string,
+        server: string,
+        conflict: boolean,
+        redacted: boolean,
+    }>,
+    preferences: Array<string>,
+}
+
+export async function export_config(path: string, include_secrets: boolean, format: ConfigFormat | null = null): Promise<void> {
+    return await invoke("export_config", {path, format, includeSecrets: include_secrets})
+}
+
+export async function preview_import_config(path: string): Promise<ImportPreview> {
+    return await invoke<ImportPreview>("preview_import_config", {path})
+}
+
+// 返回是否需要重连才能生效
+export async function import_config(path: string, conflict: ConflictStrategy = "skip"): Promise<boolean> {
+    return await invoke<boolean>("import_config", {path, conflict})
+}
+
+// 启动时配置文件无法读取的原因, 可以读取时为null
+export async function config_load_error(): Promise<string | null> {
+    return await invoke<string | null>("config_load_error")
+}
+
+// 备份无法读取的配置文件并恢复默认配置, 完成后程序会重启
+export async function reset_broken_config(): Promise<void> {
+    return await invoke<void>("reset_broken_config")
+}
@@ -0,0 +1,21 @@
+import {invoke} from "@tauri-apps/api/core";
+
+export type SecretStatus = {
+    // 配置中的密钥无法解密
+    locked: boolean,
+    // 没有可用于加密的密钥，需要输入或设置口令
+    passphrase_required: boolean,
+}
+
+export async function secret_status(): Promise<SecretStatus> {
+    return await invoke<SecretStatus>("secret_status")
+}
+
+export async function unlock_secrets(passphrase: string): Promise<void> {
+    return await invoke("unlock_secrets", {passphrase})
+}
+
+// 清空无法解密的token和组网密码，可同时设置新口令
+export async function reset_secrets(passphrase: string | null): Promise<void> {
+    return await invoke("reset_secrets", {passphrase})
+}
@@ -0,0 +1,119 @@
+import {Channel, invoke} from "@tauri-apps/api/core";
+import {listen, UnlistenFn} from "@tauri-apps/api/event";
+import {CommandEvent, CommandEvents, SpawnOptions} from "./Command.ts";
+import {EventEmitter} from "./EventEmitter";
+
+export type RestartPolicy = "never" | "on_failure" | "always"
+
+export type HealthCheck =
+    | { type: "tcp_port", port: number }
+    | { type: "stdout_pattern", pattern: string }
+
+export type SupervisedTool = {
+    // Rust端注册的工具名
+    tool: string
+    args?: string[]
+    hide?: boolean
+    options?: SpawnOptions
+    restart?: RestartPolicy
+    health?: HealthCheck | null
+    // 随vnt启动和停止
+    with_vnt?: boolean
+}
+
+export type ServiceState = "stopped" | "starting" | "running" | "healthy" | "unhealthy" | "backoff" | "failed"
+
+export type ServiceStatus = {
+    tool: string
+    state: ServiceState
+    // 当前子进程句柄
+    child: string | null
+    restarts: number
+    last_exit: { code: number | null, signal: number | null } | null
+    error: string | null
+    restart: RestartPolicy
+    with_vnt: boolean
+}
+
+interface SupervisedEvents extends CommandEvents {
+    state: ServiceStatus
+}
+
+// 受监管的工具，输出会跨越多次重启持续转发
+export class Supervised extends EventEmitter<SupervisedEvents> {
+    readonly definition: SupervisedTool
+    private unlisten: UnlistenFn | undefined
+
+    constructor(definition: SupervisedTool) {
+        super();
+        this.definition = definition
+    }
+
+    private channel(): Channel<CommandEvent> {
+        const onEvent = new Channel<CommandEvent>()
+        onEvent.onmessage = (event) => {
+            switch (event.event) {
+                case "stdout":
+                    this.emit("stdout", event.payload)
+                    break
+                case "error":
+                    this.emit("error", event.payload)
+                    break
+                case "data":
+                    this.emit("data", Uint8Array.from(event.payload))
+                    break
+                case "terminated":
+                    this.emit("terminated", event.payload)
+                    break
+            }
+        }
+        return onEvent
+    }
+
+    private async watch() {
+        if (this.unlisten) {
+            return
+        }
+        this.unlisten = await listen<ServiceStatus>("lers://supervisor/state", (event) => {
+            if (event.payload.tool === this.definition.tool) {
+                this.emit("state", event.payload)
+            }
+        })
+    }
+
+    // 保存监管定义并按需启动
+    async supervise(): Promise<ServiceStatus> {
+        await this.watch()
+        return await invoke<ServiceStatus>("supervise_tool", {
+            definition: this.definition,
+            onEvent: this.channel()
+        })
+    }
+
+    async start(): Promise<void> {
+        await this.watch()
+        await invoke("start_supervised", {
+            tool: this.definition.tool,
+            onEvent: this.channel()
+        })
+    }
+
+    async stop(): Promise<void> {
+        await invoke("stop_supervised", {
+            tool: this.definition.tool
+        })
+    }
+
+    // 停止并移除监管定义
+    async remove(): Promise<void> {
+        await invoke("unsupervise_tool", {
+            tool: this.definition.tool
+        })
+        this.unlisten?.()
+        this.unlisten = undefined
+    }
+}
+
+export async function list_supervised(): Promise<ServiceStatus[]> {
+    return await invoke<ServiceStatus[]>("list_supervised")
+}
@@ -1,9 +1,10 @@
 import {ReactNode} from "react";
-import {resolveResource} from "@tauri-apps/api/path";
-import {exists, mkdir} from "@tauri-apps/plugin-fs";
-import {download} from "@tauri-apps/plugin-upload";
 import {openUrl} from "@tauri-apps/plugin-opener";
 import {Child, Command, TerminatedPayload} from "./Command.ts";
+import {DownloadEvent} from "./Downloader.ts";
+import {Supervised, SupervisedTool} from "./Supervisor.ts";
+import {install_tool} from "./Catalog.ts";
+import {list_installed_tools} from "./Installer.ts";
 
 export enum ToolTag {
     // 需要下载
@@ -24,27 +25,22 @@ export type Tool = {
     link: string,
     icon: ReactNode,
     tags: Array<ToolTag>,
-    // 下载链接和位置
-    download_url_position?: Record<string, string>,
+    // 工具清单中的工具名，由Rust端按签名的清单下载安装
+    catalog?: string,
     // 浏览器运行
     browser?: string,
-    // 运行的命令
+    // 运行的命令，为Rust端注册的工具名
     command?: string,
     // 命令的参数
     args?: Array<string>,
+    // Switch工具的监管策略，崩溃后按策略重启
+    supervise?: Omit<SupervisedTool, "tool" | "args">,
     // 额外节点
     extra?: ReactNode,
     // 回调
     callback?: RunningCallBack
 }
 
-interface ProgressPayload {
-    progress: number
-    progressTotal: number
-    total: number
-    transferSpeed: number
-}
-
 interface RunningCallBack {
     before?: () => Promise<Array<string> | void>,
     after?: (result: TerminatedPayload) => void,
@@ -56,6 +52,7 @@ interface RunningCallBack {
 export class ToolControl {
     public tool: Tool
     private child: Child | undefined
+    private supervised: Supervised | undefined
     private callback: RunningCallBack
 
     public constructor(tool: Tool, callback?: RunningCallBack) {
@@ -64,43 +61,20 @@ export class ToolControl {
     }
 
     public async check() {
-        if (this.tool.tags.includes(ToolTag.Download) && this.tool.download_url_position) {
-            // 检查文件是否存在
-            for (const [_url, position] of Object.entries(this.tool.download_url_position)) {
-                const path = await resolveResource(position)
-                if (!await exists(path)) {
-                    return false
-                }
-            }
+        if (this.tool.tags.includes(ToolTag.Download) && this.tool.catalog) {
+            const installed = await list_installed_tools()
+            return installed.some(x => x.tool === this.tool.catalog && x.active !== null)
         }
         return true
     }
 
-    public async load(download_handler?: (payload: ProgressPayload) => void) {
-        // 需要下载，存在下载链接
-        if (this.tool.tags.includes(ToolTag.Download) && this.tool.download_url_position) {
-            if (!await this.check()) {
-                for (const [url, position] of Object.entries(this.tool.download_url_position)) {
-                    const path = await resolveResource(position)
-                    const folder = path.split("\\").slice(0, -1).join("\\")
-                    // 文件夹不存在
-                    if (!await exists(folder)) {
-                        await mkdir(folder, {recursive: true})
-                    }
-                    if (!await exists(path)) {
-                        // 下载文件
-                        try {
-                            await download(
-                                url,
-                                path,
-                                download_handler
-                            )
-                        } catch (e) {
-                            console.error(e)
-                            throw new Error("文件下载失败")
-                        }
-                    }
-                }
+    public async load(download_handler?: (event: DownloadEvent) => void) {
+        if (this.tool.tags.includes(ToolTag.Download) && this.tool.catalog) {
+            try {
+                await install_tool(this.tool.catalog, download_handler)
+            } catch (e) {
+                console.error(e)
+                throw new Error("工具安装失败")
             }
         }
     }
@@ -122,9 +96,33 @@ export class ToolControl {
             await openUrl(this.tool.browser)
             return
         }
+        if (this.tool.tags.includes(ToolTag.Command) && this.tool.command
+            && this.tool.tags.includes(ToolTag.Switch) && this.tool.supervise) {
+            // 交由Rust端监管运行
+            this.supervised = new Supervised({
+                ...this.tool.supervise,
+                tool: this.tool.command,
+                args: this.tool.args
+            })
+            this.supervised.addListener("state", (status) => {
+                if (status.state === "stopped" || status.state === "failed") {
+                    if (this.callback.after) {
+                        this.callback.after(status.last_exit ?? {code: null, signal: null})
+                    }
+                }
+            })
+            if (this.callback.onStdout) {
+                this.supervised.addListener("stdout", (result) => this.callback.onStdout?.(result))
+            }
+            if (this.callback.onError) {
+                this.supervised.addListener("error", (error) => this.callback.onError?.(error))
+            }
+            await this.supervised.supervise()
+            return
+        }
         if (this.tool.tags.includes(ToolTag.Command) && this.tool.command) {
             // 运行命令
-            let command = Command.create(await resolveResource(this.tool.command), this.tool.args)
+            let command = Command.create(this.tool.command, this.tool.args)
             command
                 .addListener("terminated", (result) => {
                     this.child = undefined
@@ -151,6 +149,10 @@ export class ToolControl {
     }
 
     public async stop() {
+        if (this.supervised) {
+            await this.supervised.stop()
+            return
+        }
         if (this.child) {
             await this.child.kill()
         }
@@ -0,0 +1,57 @@
+import {Channel, invoke} from "@tauri-apps/api/core";
+import {DownloadEvent} from "./Downloader.ts";
+
+export type UpdateChannel = "stable" | "beta"
+
+export type UpdateSettings = {
+    channel: UpdateChannel
+    // 更新地址，清单位于{endpoint}/{channel}.json，为空时使用内置地址
+    endpoint: string | null
+    // 启动时发现新版本后自动下载安装
+    auto_install: boolean
+}
+
+export type ReleaseInfo = {
+    version: string
+    channel: UpdateChannel
+    notes: string
+    published_at: string | null
+    size: number | null
+}
+
+export type UpdateStatus = {
+    current_version: string
+    settings: UpdateSettings
+    available: ReleaseInfo | null
+    // 新版本已下载，可以安装
+    staged: boolean
+    // 最近一次启动失败而被回滚的版本
+    rolled_back: string | null
+}
+
+export async function update_status(): Promise<UpdateStatus> {
+    return await invoke<UpdateStatus>("update_status")
+}
+
+export async function set_update_settings(settings: UpdateSettings): Promise<void> {
+    await invoke("set_update_settings", {settings})
+}
+
+// 没有新版本时返回null
+export async function check_update(): Promise<ReleaseInfo | null> {
+    return await invoke<ReleaseInfo | null>("check_update")
+}
+
+// 下载并校验哈希和签名
+export async function download_update(handler?: (event: DownloadEvent) => void): Promise<ReleaseInfo> {
+    const onEvent = new Channel<DownloadEvent>()
+    if (handler) {
+        onEvent.onmessage = handler
+    }
+    return await invoke<ReleaseInfo>("download_update", {onEvent})
+}
+
+// 替换程序并重启，新版本启动失败时自动回滚
+export async function install_update(): Promise<void> {
+    await invoke("install_update")
+}
@@ -24,9 +24,86 @@ export async function get_virtual_ip(): Promise<string> {
     return await invoke<string>("get_virtual_ip");
 }
 
+export type ServerProbe = {
+    address: string,
+    priority: number,
+    reachable: boolean,
+    latency_ms: number | null,
+    error: string | null,
+}
+
+export async function probe_server_list(): Promise<ServerProbe[]> {
+    return await invoke<ServerProbe[]>("probe_server_list");
+}
+
+export async function get_active_server(): Promise<string | null> {
+    return await invoke<string | null>("get_active_server");
+}
+
 export type User = {
     ip: string,
     name: string,
     nat_traversal_type: string,
     status: boolean,
-}
\ No newline at end of file
+    // p2p连接的底层地址族
+    underlay: "ipv4" | "ipv6" | null,
+}
+export type StunProbe = {
+    server: string,
+    reachable: boolean,
+    mapped_address: string | null,
+    latency_ms: number | null,
+    error: string | null,
+}
+
+// refresh为true时忽略缓存重新探测
+export async function get_stun_probes(refresh: boolean = false): Promise<StunProbe[]> {
+    return await invoke<StunProbe[]>("get_stun_probes", {refresh});
+}
+
+export type NatBehavior = "no_nat" | "endpoint_independent" | "address_dependent" | "address_and_port_dependent" | "unknown"
+
+export type PortMappingStatus = {
+    method: "upnp_igd" | "nat_pmp" | "pcp" | null,
+    gateway: string | null,
+    external_ip: string | null,
+    mappings: Array<{
+        protocol: "udp" | "tcp",
+        internal_port: number,
+        external_port: number,
+    }>,
+    renewed_at: number | null,
+    error: string | null,
+}
+
+export type NatReport = {
+    generated_at: number,
+    udp_blocked: boolean,
+    nat_type: string,
+    mapping: NatBehavior,
+    filtering: NatBehavior,
+    local_address: string | null,
+    public_addresses: string[],
+    observations: Array<{
+        server: string,
+        mapped_address: string | null,
+        other_address: string | null,
+        latency_ms: number | null,
+        error: string | null,
+    }>,
+    port_predictable: boolean | null,
+    port_delta: number | null,
+    ipv6_available: boolean,
+    ipv6_address: string | null,
+    vnt_nat_type: string | null,
+    port_mapping: PortMappingStatus | null,
+}
+
+// refresh为true时重新探测
+export async function get_nat_report(refresh: boolean = false): Promise<NatReport> {
+    return await invoke<NatReport>("get_nat_report", {refresh});
+}
+
+export async function export_diagnostics(path: string): Promise<void> {
+    return await invoke("export_diagnostics", {path});
+}
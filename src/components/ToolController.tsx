@@ -130,9 +130,11 @@ export function ToolControllerItem({tool}: { tool: Tool }) {
                         <Typography.Link
                             style={{marginLeft: "10px"}}
                             onClick={() => {
-                                controller.load((payload) => {
-                                    const percentage = Math.floor((payload.progressTotal / payload.total) * 100);
-                                    set_progress(percentage)
+                                controller.load((event) => {
+                                    if (event.event === "progress" && event.payload.total) {
+                                        const percentage = Math.floor((event.payload.downloaded / event.payload.total) * 100);
+                                        set_progress(percentage)
+                                    }
                                 })
                                     .then(() => {
                                         setReady(true)
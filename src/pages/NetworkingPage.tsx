@@ -1,6 +1,12 @@
-import {Avatar, Button, Card, Col, Drawer, Flex, Input, List, message, Row, Select, Space, Typography} from "antd";
+import {Avatar, Button, Card, Col, Drawer, Flex, Input, List, message, Modal, Row, Select, Space, Typography} from "antd";
 import {useCallback, useEffect, useState} from "react";
-import {Config, get_config, set_config} from "../abstract/LocalConfig.ts";
+import {
+    Config,
+    config_load_error,
+    get_config,
+    reset_broken_config,
+    set_config
+} from "../abstract/LocalConfig.ts";
 import {PoweroffOutlined} from "@ant-design/icons";
 import {
     fresh_user_list,
@@ -14,6 +20,8 @@ import {
 import {listen} from "@tauri-apps/api/event";
 import {useDebounce, useThrottle} from "../abstract/ReactTool.ts";
 import {get_avatar, Styles} from "../abstract/AvatarBuild.ts";
+import {join_invite, preview_invite, take_pending_invite} from "../abstract/Invite.ts";
+import {reset_secrets, secret_status, unlock_secrets} from "../abstract/Secret.ts";
 
 function NetworkingPage() {
     // 运行状态
@@ -42,8 +50,8 @@ function NetworkingPage() {
 
     // 挂载执行
     useEffect(() => {
-        // 挂载时读取配置
-        get_config()
+        // 挂载时读取配置，设置面板需要显示和编辑组名
+        get_config(true)
             .then((config) => {
                 setConfig(config)
             })
@@ -108,8 +116,141 @@ function NetworkingPage() {
             message.error("监听本机Nat类型失败")
             console.error(e)
         })
+        // 处理邀请链接
+        const open_invite = (invite: string) => {
+            preview_invite(invite)
+                .then((preview) => {
+                    let password: string | null = null
+                    Modal.confirm({
+                        title: "加入网络",
+                        content: <Flex vertical gap={"small"}>
+                            <Typography.Text>是否创建配置 {preview.profile} ({preview.server}) 并连接?</Typography.Text>
+                            {preview.servers.length > 0 ?
+                                <Typography.Text type={"secondary"}>服务器列表: {preview.servers.join(", ")}</Typography.Text> : null}
+                            <Typography.Text type={"secondary"}>邀请人指纹: {preview.inviter}</Typography.Text>
+                            {preview.password_required ?
+                                <Input.Password placeholder={"组网密码"} onChange={(e) => {
+                                    password = e.target.value
+                                }}/> : null}
+                        </Flex>,
+                        onOk: async () => {
+                            await join_invite(invite, password, preview.server, preview.inviter)
+                                .then(async () => {
+                                    await stop_vnt().catch(() => undefined)
+                                    await start_vnt()
+                                })
+                                .catch((e) => {
+                                    message.error("加入网络失败")
+                                    console.error(e)
+                                })
+                        }
+                    })
+                })
+                .catch((e) => {
+                    message.error("邀请无效或已过期")
+                    console.error(e)
+                })
+        }
+        // 配置文件无法读取时不会覆盖原文件，由用户修复后重启或确认重置
+        config_load_error()
+            .then((error) => {
+                if (error === null) {
+                    return
+                }
+                Modal.confirm({
+                    title: "配置文件无法读取",
+                    content: <Flex vertical gap={"small"}>
+                        <Typography.Text>{error}</Typography.Text>
+                        <Typography.Text type={"secondary"}>
+                            修复前不会保存任何修改，也不能连接。可以手动修复配置文件后重启，或备份后恢复默认配置。
+                        </Typography.Text>
+                    </Flex>,
+                    okText: "恢复默认配置",
+                    okButtonProps: {danger: true},
+                    cancelText: "稍后处理",
+                    onOk: async () => {
+                        await reset_broken_config().catch((e) => {
+                            message.error("恢复默认配置失败")
+                            console.error(e)
+                        })
+                    }
+                })
+            })
+            .catch((e) => {
+                console.error(e)
+            })
+        // 密钥无法解密或没有可用密钥时要求输入口令
+        const reload_config = () => get_config(true).then((config) => setConfig(config))
+        secret_status()
+            .then((secret) => {
+                if (!secret.locked && !secret.passphrase_required) {
+                    return
+                }
+                let passphrase = ""
+                const modal = Modal.confirm({
+                    title: secret.locked ? "解锁密钥" : "设置口令",
+                    content: <Flex vertical gap={"small"}>
+                        <Typography.Text>
+                            {secret.locked ? "无法解密已保存的token和组网密码，请输入口令。忘记口令时可以重置，重置后需要重新填写。"
+                                : "系统凭据管理器不可用，请设置用于加密token和组网密码的口令。"}
+                        </Typography.Text>
+                        <Input.Password placeholder={"口令"} onChange={(e) => {
+                            passphrase = e.target.value
+                        }}/>
+                    </Flex>,
+                    okText: secret.locked ? "解锁" : "设置",
+                    cancelText: "稍后",
+                    footer: (_, {OkBtn, CancelBtn}) => <>
+                        {secret.locked ? <Button danger onClick={() => {
+                            reset_secrets(passphrase || null)
+                                .then(() => {
+                                    modal.destroy()
+                                    message.warning("密钥已重置，请重新填写token和组网密码")
+                                    return reload_config()
+                                })
+                                .catch((e) => {
+                                    message.error(`重置失败：${e}`)
+                                    console.error(e)
+                                })
+                        }}>重置</Button> : null}
+                        <CancelBtn/>
+                        <OkBtn/>
+                    </>,
+                    onOk: async () => {
+                        try {
+                            await unlock_secrets(passphrase)
+                            await reload_config()
+                        } catch (e) {
+                            message.error(`口令错误或保存失败：${e}`)
+                            console.error(e)
+                            // 保持对话框打开以便重试
+                            throw e
+                        }
+                    }
+                })
+            })
+            .catch((e) => {
+                console.error(e)
+            })
+        take_pending_invite()
+            .then((invite) => {
+                if (invite) {
+                    open_invite(invite)
+                }
+            })
+        const ls5 = listen<string>("lers://invite/open", (invite) => {
+            take_pending_invite().then(() => open_invite(invite.payload))
+        }).catch((e) => {
+            message.error("监听邀请链接失败")
+            console.error(e)
+        })
         return () => {
             // 取消监听
+            ls5.then((un) => {
+                if (un) {
+                    un()
+                }
+            })
             ls1.then((un) => {
                 if (un) {
                     un()
@@ -139,8 +280,11 @@ function NetworkingPage() {
      */
     const set_config_debounced = useCallback(useDebounce((config: Config) => {
         set_config(config)
-            .then(() => {
+            .then((reconnect_required) => {
                 setConfig(config)
+                if (reconnect_required) {
+                    message.info("重新连接后配置生效")
+                }
             })
             .catch((e) => {
                 message.error("设置配置失败")
@@ -367,7 +511,7 @@ function NetworkingPage() {
                                             }}
                                             // 判断是否成功打洞
                                             type={user.nat_traversal_type.indexOf("P2P") != -1 || user.nat_traversal_type.indexOf("PCP") != -1 ? "success" : "danger"}>
-                                            {user.nat_traversal_type}
+                                            {user.nat_traversal_type}{user.underlay === "ipv6" ? " (IPv6)" : ""}
                                         </Typography.Text>
                                         <Typography.Text type={user.status ? "success" : "danger"}>
                                             {user.status ? "在线" : "离线"}
@@ -5,10 +5,52 @@ import {get_user_list} from "../abstract/VntControl.ts";
 import {GpingSelect} from "../tools/GPing.tsx";
 import {Updater} from "../tools/Updater.tsx";
 import {DownloadOutlined} from "@ant-design/icons";
+import {useEffect, useState} from "react";
+import {listen} from "@tauri-apps/api/event";
+import {CatalogToolTag, list_tools, ToolListing} from "../abstract/Catalog.ts";
+
+const CATALOG_TAGS: Record<CatalogToolTag, ToolTag> = {
+    download: ToolTag.Download,
+    command: ToolTag.Command,
+    switch: ToolTag.Switch,
+    button: ToolTag.Button,
+    args_get: ToolTag.ArgsGet
+}
+
+// 清单中的工具转换为页面使用的工具
+function catalogTool(listing: ToolListing): Tool {
+    const icon = listing.icon ?? "🧰"
+    return {
+        name: listing.display_name ?? listing.name,
+        description: listing.description,
+        link: listing.link ?? "",
+        icon: icon.startsWith("http")
+            ? <Avatar shape={"square"} src={icon}/>
+            : <Avatar shape={"square"}>{icon}</Avatar>,
+        tags: listing.tags.map(tag => CATALOG_TAGS[tag]),
+        catalog: listing.name,
+        browser: listing.browser ?? undefined,
+        command: listing.name,
+        args: listing.arg_template,
+        supervise: listing.tags.includes("switch") ? {} : undefined
+    }
+}
 
 function ToolPage() {
     const [modal, modalHolder] = Modal.useModal()
     const [messageApi, messageHolder] = message.useMessage();
+    const [catalog, setCatalog] = useState<Array<ToolListing>>([])
+
+    useEffect(() => {
+        const refresh = () => list_tools()
+            .then(setCatalog)
+            .catch(e => console.error(e))
+        refresh()
+        const unlisten = listen("lers://catalog/updated", refresh)
+        return () => {
+            unlisten.then(f => f())
+        }
+    }, [])
 
     const tools: Array<Tool> = [
         {
@@ -17,10 +59,9 @@ function ToolPage() {
             link: "https://github.com/orf/gping",
             icon: <Avatar shape={"square"}>🚀</Avatar>,
             tags: [ToolTag.Download, ToolTag.Button, ToolTag.Command, ToolTag.ArgsGet],
-            download_url_position: {
-                "http://8.137.114.160:5244/d/local/LightP2P/tools/gping.exe": "./tool/gping.exe"
-            },
-            command: "./tool/gping.exe",
+            // 由签名的工具清单提供下载地址和校验值
+            catalog: "gping",
+            command: "gping",
             callback: {
                 before: async () => {
                     const users = await get_user_list()
@@ -57,7 +98,13 @@ function ToolPage() {
                 bordered
                 locale={{emptyText: "没有成员"}}
                 itemLayout={"horizontal"}
-                dataSource={tools}
+                dataSource={[
+                    ...tools,
+                    // 内置工具优先，清单中的同名工具不重复展示
+                    ...catalog
+                        .filter(x => x.available && !tools.some(tool => tool.command === x.name))
+                        .map(catalogTool)
+                ]}
                 renderItem={(tool) => (
                     <List.Item>
                         <ToolControllerItem tool={tool}/>
@@ -1,30 +1,51 @@
 import {HookAPI} from "antd/es/modal/useModal";
 import {MessageInstance} from "antd/es/message/interface";
-import {compareVersion} from "../abstract/ReactTool.ts";
-import PackageJson from "../../package.json";
 import {Typography} from "antd";
-import {openUrl} from "@tauri-apps/plugin-opener";
+import {check_update, download_update, install_update, update_status} from "../abstract/Updater.ts";
+
+const PROGRESS_KEY = "update-progress"
 
 export async function Updater(modal: HookAPI, messageApi: MessageInstance) {
-    fetch("http://8.137.114.160:5244/d/local/LightP2P/info.json")
-        .then(res => res.json())
-        .then(async (data) => {
-            if (compareVersion(PackageJson.version, data["version"]) == -1) {
-                modal.confirm({
-                    title: "有新版本",
-                    content: (
-                        <Typography.Text style={{whiteSpace: 'pre-line'}}>
-                            {data["update"]}
-                        </Typography.Text>
-                    ),
-                    onOk: () => {
-                        openUrl("http://8.137.114.160:5244/d/local/LightP2P/latest.exe")
-                    },
-                    okText: "下载",
-                    cancelText: "取消"
-                });
-            } else {
-                messageApi.info("当前已是最新版本")
-            }
-        })
-}
\ No newline at end of file
+    try {
+        const status = await update_status()
+        if (status.rolled_back) {
+            messageApi.warning(`${status.rolled_back}版本启动失败，已恢复到${status.current_version}`)
+        }
+        const release = await check_update()
+        if (!release) {
+            messageApi.info("当前已是最新版本")
+            return
+        }
+        modal.confirm({
+            title: `有新版本 ${release.version}`,
+            content: (
+                <Typography.Text style={{whiteSpace: 'pre-line'}}>
+                    {release.notes}
+                </Typography.Text>
+            ),
+            onOk: async () => {
+                try {
+                    await download_update((event) => {
+                        if (event.event === "progress" && event.payload.total) {
+                            const percent = Math.floor(event.payload.downloaded / event.payload.total * 100)
+                            messageApi.loading({key: PROGRESS_KEY, content: `正在下载 ${percent}%`, duration: 0})
+                        } else if (event.event === "verifying") {
+                            messageApi.loading({key: PROGRESS_KEY, content: "正在校验", duration: 0})
+                        }
+                    })
+                    messageApi.success({key: PROGRESS_KEY, content: "下载完成，即将重启"})
+                    // 成功后程序退出，由新版本接替运行
+                    await install_update()
+                } catch (e) {
+                    console.error(e)
+                    messageApi.error({key: PROGRESS_KEY, content: `更新失败：${e}`})
+                }
+            },
+            okText: "安装",
+            cancelText: "取消"
+        });
+    } catch (e) {
+        console.error(e)
+        messageApi.error(`检查更新失败：${e}`)
+    }
+}